@@ -4,6 +4,10 @@
 use wasm_bindgen::prelude::*;
 // serdeクレートから、Rustのデータ構造とJSONのようなシリアライズ可能な形式との間で相互変換を行うためのSerializeとDeserializeトレイトをインポート
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 /// WASM実行中にRustコードがパニック（回復不能なエラー）を起こした際に、ブラウザの開発者コンソールに詳細なエラー情報を出力するためのフックを設定
 #[cfg(feature = "console_error_panic_hook")]
@@ -87,91 +91,599 @@ impl<'de> Deserialize<'de> for CellState {
     }
 }
 
+impl TryFrom<u8> for CellState {
+    type Error = String;
+
+    /// `0`/`1`/`2`の数値を`CellState`に変換する
+    /// 3以上の値はエラーになる
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(CellState::Empty),
+            1 => Ok(CellState::Filled),
+            2 => Ok(CellState::Crossed),
+            _ => Err(format!("invalid cell state: {}", value)),
+        }
+    }
+}
+
+impl CellState {
+    /// 人間が読むファイル形式で使う、状態の名前（`"empty"`/`"filled"`/`"crossed"`）を返す
+    pub fn name(self) -> &'static str {
+        match self {
+            CellState::Empty => "empty",
+            CellState::Filled => "filled",
+            CellState::Crossed => "crossed",
+        }
+    }
+
+    /// メッセージに埋め込むための日本語の名称を返す
+    fn label(self) -> &'static str {
+        match self {
+            CellState::Empty => "空",
+            CellState::Filled => "塗り",
+            CellState::Crossed => "×",
+        }
+    }
+}
+
+/// `CellState`を数値ではなく`"filled"`のような文字列としてシリアライズするためのラッパー型
+/// `CellState`自体は互換性のため常に数値で出力されるので、可読なJSONを作りたい場合にだけこの型で包む
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CellStateStr(pub CellState);
+
+impl From<CellState> for CellStateStr {
+    fn from(state: CellState) -> Self {
+        CellStateStr(state)
+    }
+}
+
+impl Serialize for CellStateStr {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(self.0.name())
+    }
+}
+
+/// 盤面を文字列形式でシリアライズするための関数
+/// 構造体のフィールドに`#[serde(serialize_with = "serialize_grid_as_str")]`と付けると、
+/// そのフィールドの盤面だけを`[["filled", "crossed", ...], ...]`の形で出力できる
+pub fn serialize_grid_as_str<S>(grid: &[Vec<CellState>], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.collect_seq(
+        grid.iter()
+            .map(|row| row.iter().copied().map(CellStateStr).collect::<Vec<_>>()),
+    )
+}
+
+/// 自動解析が付けた×とユーザーが付けた×を区別する、表示用のマスの状態
+/// 数値は`CellState`と共通で、自動解析が付けた×だけを`3`として表す
+/// 解析では`Crossed`と`AutoCrossed`をどちらも同じ「×」として扱う
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MarkedCell {
+    Empty = 0,
+    Filled = 1,
+    Crossed = 2,     // ユーザーが付けた×
+    AutoCrossed = 3, // 自動解析が付けた×
+}
+
+impl Serialize for MarkedCell {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_u8(*self as u8)
+    }
+}
+
+impl<'de> Deserialize<'de> for MarkedCell {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        match u8::deserialize(deserializer)? {
+            0 => Ok(MarkedCell::Empty),
+            1 => Ok(MarkedCell::Filled),
+            2 => Ok(MarkedCell::Crossed),
+            3 => Ok(MarkedCell::AutoCrossed),
+            value => Err(serde::de::Error::custom(format!(
+                "invalid marked cell state: {}",
+                value
+            ))),
+        }
+    }
+}
+
+impl From<MarkedCell> for CellState {
+    fn from(cell: MarkedCell) -> Self {
+        match cell {
+            MarkedCell::Empty => CellState::Empty,
+            MarkedCell::Filled => CellState::Filled,
+            MarkedCell::Crossed | MarkedCell::AutoCrossed => CellState::Crossed,
+        }
+    }
+}
+
 /// 解析結果をJavaScriptに返すためのデータ構造（struct）
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct SolveResult {
     grid: Vec<Vec<CellState>>, // 更新された盤面の状態
     message: String,           // ユーザーに表示するメッセージ
     error: bool,               // エラーが発生したかどうかを示すフラグ
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    metrics: Option<SolveMetrics>, // 解析の経過の統計（`solve_puzzle`でのみ設定し、それ以外では省略する）
+}
+
+/// `solve_puzzle`の解析の経過の統計
+/// 矛盾で解析を打ち切った場合も、そこまでの値を記録する
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SolveMetrics {
+    iterations: usize, // 解析したイテレーションの数（矛盾した場合は、矛盾が見つかったイテレーションの番号）
+    lines_processed: usize, // 解析したラインの本数（全イテレーションの合計、矛盾したラインを含む）
+    conflict: Option<ConflictInfo>, // 矛盾が見つかったラインの情報（矛盾がなければ`null`）
+}
+
+/// 矛盾が見つかったラインを表すデータ構造
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ConflictInfo {
+    axis: String,    // ラインの向き（"row"または"column"）
+    index: usize,    // ラインの番号（0始まり）
+    message: String, // ライン解析が返したエラーメッセージ
+}
+
+impl SolveMetrics {
+    /// 解析の結果と、解析中に数えた値から統計を組み立てる
+    fn new(
+        outcome: &Result<usize, PropagationError>,
+        last_iteration: usize,
+        lines_processed: usize,
+    ) -> Self {
+        let (iterations, conflict) = match outcome {
+            Ok(iterations) => (*iterations, None),
+            Err(PropagationError::Conflict {
+                axis,
+                index,
+                message,
+            }) => (
+                last_iteration,
+                Some(ConflictInfo {
+                    axis: axis.key().to_string(),
+                    index: *index,
+                    message: message.clone(),
+                }),
+            ),
+            Err(PropagationError::IterationLimit) => (last_iteration, None),
+        };
+        SolveMetrics {
+            iterations,
+            lines_processed,
+            conflict,
+        }
+    }
+}
+
+impl SolveResult {
+    /// 解析後の盤面（矛盾が見つかった場合は、呼び出し時点の盤面）
+    pub fn grid(&self) -> &[Vec<CellState>] {
+        &self.grid
+    }
+
+    /// ユーザーに表示するメッセージ
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// 入力の不備や矛盾などのエラーが発生したか
+    pub fn is_error(&self) -> bool {
+        self.error
+    }
+
+    /// 盤面全体の解析（ライン伝播）の結果から、JavaScriptに返す`SolveResult`を組み立てる
+    ///
+    /// # Arguments
+    /// * `outcome` - `Propagator::propagate`などの戻り値
+    /// * `original_grid` - 呼び出し時点の盤面
+    /// * `current_grid` - 解析後の盤面
+    fn from_propagation(
+        outcome: Result<usize, PropagationError>,
+        original_grid: Vec<Vec<CellState>>,
+        current_grid: Vec<Vec<CellState>>,
+    ) -> Self {
+        match outcome {
+            Ok(_) => {
+                let message = if current_grid == original_grid {
+                    // 呼び出し時点から何も変化がなければ、これ以上進展はない
+                    "これ以上自動で確定できるマスはありません".to_string()
+                } else {
+                    // 呼び出し時点から変化していれば、更新があったことを伝える
+                    "確定できるマスを更新しました".to_string()
+                };
+                SolveResult {
+                    grid: current_grid,
+                    message,
+                    error: false,
+                    metrics: None,
+                }
+            }
+            // 矛盾が見つかった場合は、呼び出し時点の盤面とエラーメッセージを返す
+            Err(e @ PropagationError::Conflict { .. }) => SolveResult {
+                grid: original_grid,
+                message: e.to_string(),
+                error: true,
+                metrics: None,
+            },
+            // 最大反復回数に達した場合は、そこまでの盤面をエラーとして返す
+            Err(e @ PropagationError::IterationLimit) => SolveResult {
+                grid: current_grid,
+                message: e.to_string(),
+                error: true,
+                metrics: None,
+            },
+        }
+    }
+}
+
+/// `solve_puzzle`の各フェーズにかかった時間（ミリ秒）
+/// `profiling`フィーチャーを有効にしたビルドでだけ計測する
+#[cfg(feature = "profiling")]
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+pub struct PhaseTimings {
+    deserialize_ms: f64, // 引数のデシリアライズと検査
+    row_ms: f64,         // 行の解析（全イテレーションの合計）
+    column_ms: f64,      // 列の解析（全イテレーションの合計）
+    transpose_ms: f64,   // 列の解析の前後に行う転置（全イテレーションの合計）
+    serialize_ms: f64,   // 結果のシリアライズ
+}
+
+/// `profiling`フィーチャーを有効にしたときに`solve_puzzle`が返すデータ構造
+/// `SolveResult`のフィールドに`timings`を加えた形になる
+#[cfg(feature = "profiling")]
+#[derive(Serialize, Deserialize)]
+pub struct ProfiledSolveResult {
+    grid: Vec<Vec<CellState>>,     // 更新された盤面の状態
+    message: String,               // ユーザーに表示するメッセージ
+    error: bool,                   // エラーが発生したかどうかを示すフラグ
+    metrics: Option<SolveMetrics>, // 解析の経過の統計
+    timings: PhaseTimings,         // 各フェーズにかかった時間
+}
+
+#[cfg(feature = "profiling")]
+impl ProfiledSolveResult {
+    /// 解析結果に`Propagator`が計測した時間を加えた値を組み立てる
+    /// シリアライズの時間はまだ測っていないので0にしておき、`into_js`で書き込む
+    fn new(result: SolveResult, deserialize_ms: f64, propagator: &Propagator) -> Self {
+        ProfiledSolveResult {
+            grid: result.grid,
+            message: result.message,
+            error: result.error,
+            metrics: result.metrics,
+            timings: PhaseTimings {
+                deserialize_ms,
+                ..*propagator.timings.borrow()
+            },
+        }
+    }
+
+    /// 結果を1回だけシリアライズし、それにかかった時間を`timings.serialize_ms`に書き込んで返す
+    fn into_js(mut self) -> Result<JsValue, JsValue> {
+        let started = now_ms();
+        let value = serde_wasm_bindgen::to_value(&self)?;
+        self.timings.serialize_ms = now_ms() - started;
+        // 盤面を含む全体をシリアライズし直さず、`timings`だけを差し替える
+        js_sys::Reflect::set(
+            &value,
+            &JsValue::from_str("timings"),
+            &serde_wasm_bindgen::to_value(&self.timings)?,
+        )?;
+        Ok(value)
+    }
+}
+
+/// 計測や時間制限に使う現在時刻（ミリ秒）を返す
+/// WASMでは`performance.now()`を使い、使えない環境では精度の低い`Date.now()`で代用する
+/// `performance.now`の関数は最初の呼び出しで探して覚えておくので、2回目以降はJavaScriptの呼び出し1回で済む
+#[cfg(target_arch = "wasm32")]
+fn now_ms() -> f64 {
+    thread_local! {
+        static PERFORMANCE: Option<(JsValue, js_sys::Function)> = {
+            let performance = js_sys::Reflect::get(&js_sys::global(), &JsValue::from_str("performance")).ok();
+            performance.and_then(|performance| {
+                let now = js_sys::Reflect::get(&performance, &JsValue::from_str("now")).ok()?;
+                Some((performance, now.dyn_into::<js_sys::Function>().ok()?))
+            })
+        };
+    }
+    PERFORMANCE
+        .with(|performance| {
+            let (performance, now) = performance.as_ref()?;
+            now.call0(performance).ok()?.as_f64()
+        })
+        .unwrap_or_else(js_sys::Date::now)
+}
+
+/// 計測や時間制限に使う現在時刻（ミリ秒）を返す
+/// WASM以外では`JsValue`を扱えないので、最初の呼び出しからの経過時間を`std::time::Instant`で測る
+#[cfg(not(target_arch = "wasm32"))]
+fn now_ms() -> f64 {
+    static ORIGIN: std::sync::OnceLock<std::time::Instant> = std::sync::OnceLock::new();
+    ORIGIN
+        .get_or_init(std::time::Instant::now)
+        .elapsed()
+        .as_secs_f64()
+        * 1000.0
 }
 
 // --- コアロジック関数 ---
 
-/// 1行または1列（ライン）を解析し、確定できるマスを導き出す関数
+/// ルールを、ラインに置きうる全ての配置パターンのビットマスク集合へ事前に変換（コンパイル）したもの
+/// パターンの生成は重い処理なので、一度だけ行って毎イテレーションの解析で使い回す
+#[derive(Clone)]
+struct CompiledRule {
+    size: usize,                                                     // ラインの長さ
+    words: usize,         // 1パターンを表すのに必要なu64の個数
+    patterns: Arc<[u64]>, // 全パターンを`words`個ずつ連結したもの（立っているビットが「塗り」、同じルールのライン同士で共有する）
+    blank: Arc<std::sync::OnceLock<Result<Vec<CellState>, String>>>, // 全て「空」のラインを解析した結果（最初に必要になったときに求め、同じルールのライン同士で共有する）
+}
+
+/// ルールから全ての配置パターンを生成し、ビットマスクの集合にまとめる関数
 ///
 /// # Arguments
-/// * `line_size` - 解析対象ラインの長さ（列数または行数）
+/// * `size` - ラインの長さ
 /// * `rule` - そのラインに適用されるルール（例: `[2, 1]`）
-/// * `user_line` - 現在のラインの状態（ユーザーの入力や前回の解析結果を含む）
-///
-/// # Returns
-/// * `Ok(Vec<CellState>)` - 更新されたラインの状態
-/// * `Err(String)` - 矛盾などが発生した場合のエラーメッセージ
-fn solve_line(
-    line_size: usize,
-    rule: &[usize],
-    user_line: &[CellState],
-) -> Result<Vec<CellState>, String> {
-    // ルールが空、または[0]のみの場合、そのラインは全て「×」(Crossed)で確定
-    if rule.is_empty() || (rule.len() == 1 && rule[0] == 0) {
-        let mut new_line = user_line.to_vec();
-        for i in 0..line_size {
-            // もし既に「塗り」のマスがあれば、ルールと矛盾するのでエラー
-            if user_line[i] == CellState::Filled {
-                return Err("入力に矛盾があります".to_string());
+///
+/// # Returns
+/// * `CompiledRule` - `apply`で繰り返し使える、前計算済みのパターン集合
+fn compile_rule(size: usize, rule: &[usize]) -> CompiledRule {
+    // 長さ0のラインでも、1パターン分の領域は確保しておく
+    let words = size.div_ceil(64).max(1);
+
+    // パターンを1つずつビットマスクに変換して、連結した配列の末尾に書き込む
+    // ラインに収まらないルールにはパターンが1つもないので、どの盤面とも矛盾するルールになる
+    let mut patterns = Vec::new();
+    if let Ok(rule) = ValidatedRule::new(rule, size) {
+        for_each_possibility(&rule, &mut |arrangement| {
+            let offset = patterns.len();
+            patterns.resize(offset + words, 0u64);
+            for (i, &value) in arrangement.iter().enumerate() {
+                if value == 1 {
+                    patterns[offset + i / 64] |= 1 << (i % 64);
+                }
             }
-            new_line[i] = CellState::Crossed;
-        }
-        return Ok(new_line);
+        });
+    }
+
+    CompiledRule {
+        size,
+        words,
+        patterns: patterns.into(),
+        blank: Default::default(),
     }
+}
 
-    // 1. ルールに合致する全ての可能性のある配置パターンを生成する
-    let possibilities = generate_possibilities(line_size, rule);
+/// 長さとルールが同じラインをまとめ、パターンの生成をまとめごとに1回だけ行う
+/// 全ての行が`[5]`のように同じルールのラインが多いパズルでは、生成の手間とメモリを大きく減らせる
+/// パターン集合は共有するが、各ラインの盤面での絞り込みは`apply`でライン個別に行う
+#[derive(Default)]
+struct RuleCompiler {
+    compiled: HashMap<(usize, Vec<usize>), CompiledRule>, // コンパイル済みのルール（ラインの長さとルールで引く）
+}
 
-    // 2. 生成された全パターンの中から、現在のラインの状態(`user_line`)と矛盾しないものだけを絞り込む
-    let valid_possibilities: Vec<_> = possibilities
-        .into_iter()
-        .filter(|p| {
-            // p は一つの可能性パターン (例: [1, 1, 0, 1, 0])
-            (0..line_size).all(|i| match user_line[i] {
-                // 既に「塗り」のマスは、パターンでも「塗り」(1)でなければならない
-                CellState::Filled => p[i] == 1,
-                // 既に「×」のマスは、パターンでも「空」(0)でなければならない
-                CellState::Crossed => p[i] == 0,
-                // 「空」のマスはどんなパターンでもOK
-                CellState::Empty => true,
-            })
-        })
-        .collect();
+impl RuleCompiler {
+    /// ルールをコンパイルする（同じ長さで同じルールを既にコンパイルしていれば、そのパターン集合を共有する）
+    fn compile(&mut self, size: usize, rule: &[usize]) -> CompiledRule {
+        self.compiled
+            .entry((size, rule.to_vec()))
+            .or_insert_with(|| compile_rule(size, rule))
+            .clone()
+    }
+}
+
+/// ライン解析で使う一時的なビットマスクの置き場
+/// 解析のたびに確保し直さないよう、`Propagator`が1つ持って全てのラインの解析で使い回す
+#[derive(Default)]
+struct LineScratch {
+    filled: Vec<u64>,     // ラインで「塗り」のマス
+    crossed: Vec<u64>,    // ラインで「×」のマス
+    all_filled: Vec<u64>, // 矛盾しない全てのパターンで「塗り」のマス
+    any_filled: Vec<u64>, // 矛盾しないいずれかのパターンで「塗り」のマス
+}
 
-    // 矛盾しないパターンが一つもなければ、入力に矛盾があるということ
-    if valid_possibilities.is_empty() {
-        return Err("入力に矛盾があります".to_string());
+impl CompiledRule {
+    /// 1行または1列（ライン）を解析し、確定できるマスを導き出すメソッド
+    ///
+    /// # Arguments
+    /// * `user_line` - 現在のラインの状態（ユーザーの入力や前回の解析結果を含む）
+    ///
+    /// # Returns
+    /// * `Ok(Vec<CellState>)` - 更新されたラインの状態
+    /// * `Err(String)` - 矛盾などが発生した場合のエラーメッセージ
+    fn apply(&self, user_line: &[CellState]) -> Result<Vec<CellState>, String> {
+        self.apply_with(user_line, &mut LineScratch::default())
     }
 
-    // 3. 矛盾しない全パターンで共通しているマスを特定する
-    let mut new_line = user_line.to_vec();
-    for i in 0..line_size {
-        // 既に確定しているマスはスキップ
-        if new_line[i] != CellState::Empty {
-            continue;
+    /// `apply`と同じ解析を、呼び出し側が用意した一時バッファを使って行う
+    /// 同じ`scratch`を繰り返し渡せば、2回目以降はビットマスクのための割り当てが発生しない
+    fn apply_with(
+        &self,
+        user_line: &[CellState],
+        scratch: &mut LineScratch,
+    ) -> Result<Vec<CellState>, String> {
+        // 長さの違うラインを渡されると、範囲外アクセスや誤った確定につながるので先に弾く
+        if user_line.len() != self.size {
+            return Err(format!(
+                "ラインの長さ（{}）がルールを適用する長さ（{}）と一致しません",
+                user_line.len(),
+                self.size
+            ));
+        }
+        // 疎な大盤面に多い、全て「空」のラインは、パターンを走査せずに一度求めた結果を返す
+        if user_line.iter().all(|&cell| cell == CellState::Empty) {
+            return self.blank_result().clone();
         }
+        self.apply_patterns(user_line, scratch)
+    }
 
-        // 最初の有効なパターンのi番目の状態を取得
-        let first_state = valid_possibilities[0][i];
-        // 全ての有効なパターンで、i番目の状態が `first_state` と同じかチェック
-        if valid_possibilities.iter().all(|p| p[i] == first_state) {
-            // 全て同じであれば、そのマスは確定できる
-            new_line[i] = if first_state == 1 {
-                CellState::Filled // 全て1なら「塗り」
-            } else {
-                CellState::Crossed // 全て0なら「×」
-            };
+    /// 全て「空」のラインを解析した結果
+    /// ルールだけで決まるので、最初に呼ばれたときに一度だけパターンを走査して求める
+    fn blank_result(&self) -> &Result<Vec<CellState>, String> {
+        self.blank.get_or_init(|| {
+            self.apply_patterns(
+                &vec![CellState::Empty; self.size],
+                &mut LineScratch::default(),
+            )
+        })
+    }
+
+    /// 長さを確かめた後の`apply_with`の本体で、全パターンを走査してラインを解析する
+    fn apply_patterns(
+        &self,
+        user_line: &[CellState],
+        scratch: &mut LineScratch,
+    ) -> Result<Vec<CellState>, String> {
+        // 1. 現在のラインの状態を「塗り」と「×」のビットマスクに変換する
+        self.fill_masks(user_line, &mut scratch.filled, &mut scratch.crossed);
+        let LineScratch {
+            filled,
+            crossed,
+            all_filled,
+            any_filled,
+        } = scratch;
+
+        // 2. 現在のラインの状態と矛盾しないパターンだけを絞り込み、全パターンの積（AND）と和（OR）を取る
+        // どちらも可換な演算なので、パターンの列挙順によらず同じ結果になる
+        let mut found = false;
+        all_filled.clear();
+        all_filled.resize(self.words, !0u64);
+        any_filled.clear();
+        any_filled.resize(self.words, 0u64);
+        // 矛盾した場合に報告する位置（どのパターンでも、ここより手前までは矛盾なく説明できる）
+        let mut conflict_at = 0;
+        for p in self.patterns.chunks(self.words) {
+            // 既に「塗り」のマスはパターンでも1、既に「×」のマスはパターンでも0でなければならない
+            // 食い違うマスがあれば、その中で最も左の位置を記録しておく
+            let mismatch = (0..self.words).find_map(|w| {
+                let bits = (filled[w] & !p[w]) | (crossed[w] & p[w]);
+                (bits != 0).then(|| w * 64 + bits.trailing_zeros() as usize)
+            });
+            if let Some(i) = mismatch {
+                conflict_at = conflict_at.max(i);
+                continue;
+            }
+            found = true;
+            for (w, &bits) in p.iter().enumerate() {
+                all_filled[w] &= bits;
+                any_filled[w] |= bits;
+            }
+        }
+
+        // 矛盾しないパターンが一つもなければ、入力に矛盾があるということ
+        // 全パターンの中で最も右まで矛盾せずに済んだ位置のマスを、矛盾の原因として報告する
+        if !found {
+            return Err(match user_line.get(conflict_at) {
+                Some(&state) if state != CellState::Empty => format!(
+                    "入力に矛盾があります（{}マス目の{}がルールと合いません）",
+                    conflict_at + 1,
+                    state.label()
+                ),
+                _ => "入力に矛盾があります".to_string(),
+            });
+        }
+
+        // 3. 矛盾しない全パターンで共通しているマスを確定させる
+        let mut new_line = user_line.to_vec();
+        for (i, cell) in new_line.iter_mut().enumerate().take(self.size) {
+            // 既に確定しているマスはスキップ
+            if *cell != CellState::Empty {
+                continue;
+            }
+            if all_filled[i / 64] >> (i % 64) & 1 == 1 {
+                *cell = CellState::Filled; // 全て1なら「塗り」
+            } else if any_filled[i / 64] >> (i % 64) & 1 == 0 {
+                *cell = CellState::Crossed; // 全て0なら「×」
+            }
+        }
+
+        // 更新されたラインを返す
+        Ok(new_line)
+    }
+
+    /// ラインの状態を「塗り」と「×」のビットマスクに変換する
+    fn masks(&self, user_line: &[CellState]) -> (Vec<u64>, Vec<u64>) {
+        let (mut filled, mut crossed) = (Vec::new(), Vec::new());
+        self.fill_masks(user_line, &mut filled, &mut crossed);
+        (filled, crossed)
+    }
+
+    /// `masks`と同じビットマスクを、渡されたバッファに書き込む（バッファの元の内容は捨てる）
+    fn fill_masks(&self, user_line: &[CellState], filled: &mut Vec<u64>, crossed: &mut Vec<u64>) {
+        filled.clear();
+        filled.resize(self.words, 0);
+        crossed.clear();
+        crossed.resize(self.words, 0);
+        for (i, &cell) in user_line.iter().enumerate().take(self.size) {
+            match cell {
+                CellState::Filled => filled[i / 64] |= 1 << (i % 64),
+                CellState::Crossed => crossed[i / 64] |= 1 << (i % 64),
+                CellState::Empty => {}
+            }
         }
     }
 
-    // 更新されたラインを返す
-    Ok(new_line)
+    /// 現在のラインの状態と矛盾しない配置パターンの数を数える
+    fn count_consistent(&self, user_line: &[CellState]) -> usize {
+        self.consistent_patterns(user_line).count()
+    }
+
+    /// 現在のラインの状態と矛盾しない配置パターンを、生成順（ブロックが左に寄っている順）に列挙する
+    fn consistent_patterns(&self, user_line: &[CellState]) -> impl Iterator<Item = &[u64]> {
+        let (filled, crossed) = self.masks(user_line);
+        let words = self.words;
+        self.patterns
+            .chunks(words)
+            .filter(move |p| (0..words).all(|w| (filled[w] & !p[w]) | (crossed[w] & p[w]) == 0))
+    }
+
+    /// パターンの各マスが、何番目のブロック・隙間に属するかを表す番号を返す
+    /// `k`番目のブロックのマスは`2k + 1`、その手前の隙間のマスは`2k`になる
+    fn region_codes(&self, p: &[u64]) -> Vec<usize> {
+        let mut code = 0;
+        (0..self.size)
+            .map(|i| {
+                let filled = p[i / 64] >> (i % 64) & 1 == 1;
+                if filled != (code % 2 == 1) {
+                    code += 1;
+                }
+                code
+            })
+            .collect()
+    }
+
+    /// 最も左に寄せた配置と最も右に寄せた配置を比べ、両方で同じブロック（または同じ隙間）に属するマスを確定させる
+    /// 全パターンを突き合わせる`apply`より弱いが、人間が手で行う推論に近い
+    ///
+    /// # Returns
+    /// * `Vec<Option<CellState>>` - 確定したマスは`Some(状態)`、確定しないマスは`None`
+    fn left_right(&self, user_line: &[CellState]) -> Vec<Option<CellState>> {
+        let mut patterns = self.consistent_patterns(user_line);
+        let Some(leftmost) = patterns.next() else {
+            return vec![None; self.size];
+        };
+        let rightmost = patterns.last().unwrap_or(leftmost);
+        self.region_codes(leftmost)
+            .into_iter()
+            .zip(self.region_codes(rightmost))
+            .map(|(left, right)| {
+                (left == right).then_some(if left % 2 == 1 {
+                    CellState::Filled
+                } else {
+                    CellState::Crossed
+                })
+            })
+            .collect()
+    }
 }
 
 /// ルールに基づいて、考えられる全ての「塗り」の配置パターンを生成する再帰関数
@@ -184,6 +696,107 @@ fn solve_line(
 /// * `Vec<Vec<u8>>` - 考えられる全てのパターン（`1`が塗り、`0`が空）のリスト
 fn generate_possibilities(size: usize, rule: &[usize]) -> Vec<Vec<u8>> {
     let mut solutions = Vec::new();
+    // ラインに収まらないルールには、配置パターンが1つもない
+    if let Ok(rule) = ValidatedRule::new(rule, size) {
+        for_each_possibility(&rule, &mut |arrangement| {
+            solutions.push(arrangement.to_vec())
+        });
+    }
+    solutions
+}
+
+/// ルールのブロックを、最低限の隙間（1マス）だけ空けて詰めて置いたときに必要な長さ
+/// `ブロック長の合計 + (ブロック数 - 1)`で、ルール中の0は無視する
+fn rule_min_len(rule: &[usize]) -> usize {
+    let blocks = rule.iter().filter(|&&n| n > 0);
+    blocks.clone().sum::<usize>() + blocks.count().saturating_sub(1)
+}
+
+/// 検証・正規化を済ませたルール
+/// `ValidatedRule::new`を通ったものだけが存在するので、ブロック長に0を含まず、
+/// ブロックと最低限の隙間がラインの長さに収まることが型で保証される
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct ValidatedRule {
+    blocks: Vec<usize>, // 0を含まないブロック長の並び
+    size: usize,        // ルールを適用するラインの長さ
+}
+
+impl ValidatedRule {
+    /// ルールを正規化し、長さ`size`のラインに収まるかを検証する
+    /// `[]`、`[0]`、`[0, 0]`、`[2, 0, 1]`などの表記揺れは、0を含まない形に揃える
+    ///
+    /// # Returns
+    /// * `Ok(ValidatedRule)` - 正規化したルール
+    /// * `Err(String)` - ブロックと最低限の隙間だけでラインに収まらない場合のエラーメッセージ
+    fn new(rule: &[usize], size: usize) -> Result<Self, String> {
+        let rule = ValidatedRule {
+            blocks: normalize_rule(rule),
+            size,
+        };
+        if rule.min_len() > size {
+            return Err(format!(
+                "ルール{:?}は、長さ{}のラインに収まりません（最低{}マス必要です）",
+                rule.blocks,
+                size,
+                rule.min_len()
+            ));
+        }
+        Ok(rule)
+    }
+
+    /// ブロックを詰めて置いたときに必要な最低限の長さ
+    fn min_len(&self) -> usize {
+        rule_min_len(&self.blocks)
+    }
+
+    /// 各ブロックが取りうる位置の範囲を、候補を生成せずに線形時間で求める
+    /// 全てのブロックを左に詰めたときの開始位置と、右に詰めたときの終了位置（そのブロックの最後のマスの次の位置）を返す
+    /// この範囲の外には、どの配置パターンでもそのブロックが来ない
+    fn block_ranges(&self) -> Vec<(usize, usize)> {
+        // 左に詰めたときの開始位置は、手前のブロックと隙間の合計になる
+        let mut starts = Vec::with_capacity(self.blocks.len());
+        let mut position = 0;
+        for &block in &self.blocks {
+            starts.push(position);
+            position += block + 1;
+        }
+        // 右に詰めたときの終了位置は、後ろのブロックと隙間の合計をラインの長さから引いたものになる
+        let mut ends = vec![0; self.blocks.len()];
+        let mut position = self.size;
+        for (i, &block) in self.blocks.iter().enumerate().rev() {
+            ends[i] = position;
+            position = position.saturating_sub(block + 1);
+        }
+        starts.into_iter().zip(ends).collect()
+    }
+
+    /// 空のラインに重なり法だけを適用したときに確定するマスを、候補を生成せずに求める
+    /// 左に詰めたときと右に詰めたときに同じブロックが重なるマスは「塗り」、
+    /// どのブロックの範囲にも入らないマスは「×」に確定する
+    fn overlap(&self) -> Vec<Option<CellState>> {
+        let mut line = vec![Some(CellState::Crossed); self.size];
+        for (&block, (start, end)) in self.blocks.iter().zip(self.block_ranges()) {
+            for cell in &mut line[start..end] {
+                *cell = None;
+            }
+            // 右に詰めたときの開始位置から、左に詰めたときの終了位置までが重なる
+            for cell in line.iter_mut().take(start + block).skip(end - block) {
+                *cell = Some(CellState::Filled);
+            }
+        }
+        line
+    }
+}
+
+/// ルールに基づいて考えられる全ての「塗り」の配置パターンを、`generate_possibilities`と同じ順序で1つずつ`visit`に渡す
+/// パターンごとに`Vec`を確保せず、1つの作業用バッファを書き換えながら渡すので、
+/// パターンを別の形式に変換して保存する場合（`compile_rule`など）に一時的な割り当てを省ける
+///
+/// # Arguments
+/// * `rule` - 適用するルール（検証済みなので、ラインに収まることが保証されている）
+/// * `visit` - パターン（`1`が塗り、`0`が空）ごとに呼ばれる関数
+fn for_each_possibility(rule: &ValidatedRule, visit: &mut dyn FnMut(&[u8])) {
+    let size = rule.size;
     let mut current_arrangement = vec![0; size];
 
     // 再帰的に探索を行う内部関数
@@ -193,11 +806,11 @@ fn generate_possibilities(size: usize, rule: &[usize]) -> Vec<Vec<u8>> {
         block_index: usize, // 現在配置しようとしているルールのインデックス
         start_index: usize, // 現在のブロックを配置し始めることができる、最小のインデックス
         current_arrangement: &mut Vec<u8>, // 現在の配置状態
-        solutions: &mut Vec<Vec<u8>>, // 完成したパターンの保存場所
+        visit: &mut dyn FnMut(&[u8]), // 完成したパターンを受け取る関数
     ) {
-        // ベースケース: 全てのルールブロックを配置し終えたら、現在の配置を解として保存
+        // ベースケース: 全てのルールブロックを配置し終えたら、現在の配置を解として渡す
         if block_index == rule.len() {
-            solutions.push(current_arrangement.clone());
+            visit(current_arrangement);
             return;
         }
 
@@ -228,7 +841,7 @@ fn generate_possibilities(size: usize, rule: &[usize]) -> Vec<Vec<u8>> {
                 block_index + 1,
                 next_start,
                 current_arrangement,
-                solutions,
+                visit,
             );
 
             // バックトラック：配置したブロックを元に戻し（0で埋める）、次の配置場所を試す
@@ -238,18 +851,40 @@ fn generate_possibilities(size: usize, rule: &[usize]) -> Vec<Vec<u8>> {
         }
     }
 
-    // ルールが空または[0]の場合、すべて0のパターンのみが解となる
-    if rule.is_empty() || (rule.len() == 1 && rule[0] == 0) {
-        solutions.push(vec![0; size]);
+    // ルールが空の場合、すべて0のパターンのみが解となる
+    if rule.blocks.is_empty() {
+        visit(&current_arrangement);
     } else {
         // 再帰処理を開始
-        recurse(size, rule, 0, 0, &mut current_arrangement, &mut solutions);
+        recurse(size, &rule.blocks, 0, 0, &mut current_arrangement, visit);
     }
-    solutions
+}
+
+/// ルールから長さ0のブロックを取り除く関数
+/// 「塗り」のないラインは`[]`とも`[0]`とも書けるため、どの書き方でも同じ`[]`として扱えるようにする
+fn normalize_rule(rule: &[usize]) -> Vec<usize> {
+    rule.iter().copied().filter(|&n| n > 0).collect()
+}
+
+/// 解析前後のラインを比較し、一度確定したマスが書き換えられていないかを検査する関数
+/// ライン解析は「空」のマスを確定させるだけで、確定済みのマスを変えることはないという不変条件を保証する
+///
+/// # Arguments
+/// * `before` - 解析前のラインの状態
+/// * `after` - 解析後のラインの状態
+///
+/// # Returns
+/// * `Some(usize)` - 不変条件を破った最初のマスの位置
+/// * `None` - 「空」のマスが確定しただけの、正常な変化だった場合
+fn find_non_monotonic_change(before: &[CellState], after: &[CellState]) -> Option<usize> {
+    before
+        .iter()
+        .zip(after)
+        .position(|(&b, &a)| b != CellState::Empty && b != a)
 }
 
 /// グリッド（2次元ベクトル）の行と列を入れ替える（転置する）ヘルパー関数
-/// これにより、行を解析する`solve_line`関数を、列の解析にもそのまま再利用できる
+/// これにより、行を解析する`CompiledRule::apply`を、列の解析にもそのまま再利用できる
 fn transpose(grid: Vec<Vec<CellState>>) -> Vec<Vec<CellState>> {
     if grid.is_empty() {
         return Vec::new();
@@ -257,118 +892,9958 @@ fn transpose(grid: Vec<Vec<CellState>>) -> Vec<Vec<CellState>> {
     let rows = grid.len();
     let cols = grid[0].len();
     let mut transposed = vec![vec![CellState::Empty; rows]; cols];
-    for r in 0..rows {
-        for c in 0..cols {
-            transposed[c][r] = grid[r][c];
+    for (r, row) in grid.iter().enumerate() {
+        // 転置先は「空」で初期化してあるので、疎な盤面に多い全て「空」の行は書き込みを省略する
+        if row.iter().all(|&cell| cell == CellState::Empty) {
+            continue;
+        }
+        for (c, &cell) in row.iter().enumerate().take(cols) {
+            transposed[c][r] = cell;
         }
     }
     transposed
 }
 
-/// JavaScriptから呼び出されるメインの関数パズル全体の解析を行う
-#[wasm_bindgen]
-pub fn solve_puzzle(
-    rows: usize,
-    cols: usize,
-    row_rules_js: JsValue,
-    col_rules_js: JsValue,
-    initial_grid_js: JsValue,
-) -> Result<JsValue, JsValue> {
-    // デバッグ用のパニックフックを設定
-    #[cfg(feature = "console_error_panic_hook")]
-    set_panic_hook();
+// --- 疎な盤面の四分木 ---
 
-    // 1. JavaScriptから渡されたJsValueを、Rustのデータ構造に変換（デシリアライズ）する
-    let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
-    let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
-    let mut current_grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(initial_grid_js)?;
+/// 盤面を四分木で表したときのノード
+/// 正方形の領域を表し、全てのマスが同じ状態なら1つの葉にまとめ、そうでなければ4つの小領域に分ける
+/// ほとんどが「空」の大きな盤面では、一様な大領域が1ノードで済むのでメモリを大きく節約できる
+#[derive(Clone, Debug, PartialEq)]
+enum QuadNode {
+    Leaf(CellState),           // 領域内の全てのマスが同じ状態
+    Split(Box<[QuadNode; 4]>), // 左上、右上、左下、右下の小領域
+}
 
-    // 呼び出し時点の盤面を、後で比較するために保存しておく
-    let original_grid = current_grid.clone();
-    // 無限ループを防ぐための最大反復回数を設定
-    let max_iterations = (rows + cols) * 2;
-    let mut iteration = 0;
+impl QuadNode {
+    /// 盤面の`(top, left)`を左上とする一辺`size`（2の累乗）の領域を四分木にする
+    /// 盤面の外にはみ出した部分は「空」として扱う
+    fn build(grid: &[Vec<CellState>], top: usize, left: usize, size: usize) -> Self {
+        let cols = grid.first().map_or(0, Vec::len);
+        if top >= grid.len() || left >= cols {
+            return QuadNode::Leaf(CellState::Empty);
+        }
+        if size == 1 {
+            return QuadNode::Leaf(grid[top][left]);
+        }
+        let half = size / 2;
+        let mut node = QuadNode::Split(Box::new([
+            QuadNode::build(grid, top, left, half),
+            QuadNode::build(grid, top, left + half, half),
+            QuadNode::build(grid, top + half, left, half),
+            QuadNode::build(grid, top + half, left + half, half),
+        ]));
+        node.merge();
+        node
+    }
 
-    // 2. メインの解析ループ盤面に変化がなくなるまで繰り返す
-    loop {
-        let mut changed_in_this_iteration = false;
+    /// 4つの小領域が全て同じ状態の葉なら、1つの葉にまとめる
+    /// 同じ盤面は必ず同じ形の木になるので、木同士の比較がそのまま盤面の比較になる
+    fn merge(&mut self) {
+        if let QuadNode::Split(children) = self
+            && let [
+                QuadNode::Leaf(a),
+                QuadNode::Leaf(b),
+                QuadNode::Leaf(c),
+                QuadNode::Leaf(d),
+            ] = **children
+            && a == b
+            && b == c
+            && c == d
+        {
+            *self = QuadNode::Leaf(a);
+        }
+    }
 
-        // ステップA: 全ての行を解析する
-        for r in 0..rows {
-            match solve_line(cols, &row_rules[r], &current_grid[r]) {
-                Ok(new_line) => {
-                    // ラインに変化があれば、盤面を更新し、変更フラグを立てる
-                    if new_line != current_grid[r] {
-                        current_grid[r] = new_line;
-                        changed_in_this_iteration = true;
+    /// ノードの総数
+    fn count(&self) -> usize {
+        match self {
+            QuadNode::Leaf(_) => 1,
+            QuadNode::Split(children) => 1 + children.iter().map(QuadNode::count).sum::<usize>(),
+        }
+    }
+
+    /// 四分木の内容を、盤面の`(top, left)`を左上とする一辺`size`の領域に書き戻す（盤面の外の部分は捨てる）
+    fn fill(&self, grid: &mut [Vec<CellState>], top: usize, left: usize, size: usize) {
+        match self {
+            QuadNode::Leaf(state) => {
+                for row in grid.iter_mut().skip(top).take(size) {
+                    for cell in row.iter_mut().skip(left).take(size) {
+                        *cell = *state;
                     }
                 }
-                // `solve_line`がエラーを返した場合、エラーメッセージを含んだ結果を返して即時終了
-                Err(e) => {
-                    let result = SolveResult {
-                        grid: original_grid,
-                        message: format!("行 {}: {}", r + 1, e),
-                        error: true,
-                    };
-                    return Ok(serde_wasm_bindgen::to_value(&result)?);
+            }
+            QuadNode::Split(children) => {
+                let half = size / 2;
+                for (child, (dr, dc)) in children.iter().zip(quadrants(half)) {
+                    child.fill(grid, top + dr, left + dc, half);
                 }
             }
         }
+    }
 
-        // ステップB: 全ての列を解析する
-        // グリッドを転置することで、`solve_line`を列解析に再利用する
-        let mut transposed = transpose(current_grid.clone());
-        for c in 0..cols {
-            match solve_line(rows, &col_rules[c], &transposed[c]) {
-                Ok(new_line) => {
-                    if new_line != transposed[c] {
-                        transposed[c] = new_line;
-                        changed_in_this_iteration = true;
+    /// `(top, left)`を左上とする一辺`size`のこのノードの領域に、`axis`の`index`番目のラインが全て「空」で含まれるか
+    /// ラインと交わらない小領域は調べないので、一様な大領域が多いほど速い
+    fn is_blank_line(
+        &self,
+        axis: Axis,
+        index: usize,
+        (top, left, size): (usize, usize, usize),
+    ) -> bool {
+        match self {
+            QuadNode::Leaf(state) => *state == CellState::Empty,
+            QuadNode::Split(children) => {
+                let half = size / 2;
+                children
+                    .iter()
+                    .zip(quadrants(half))
+                    .all(|(child, (dr, dc))| {
+                        !crosses(axis, index, top + dr, left + dc, half)
+                            || child.is_blank_line(axis, index, (top + dr, left + dc, half))
+                    })
+            }
+        }
+    }
+
+    /// このノードの領域に含まれる、`axis`の`index`番目のラインの部分を`line`に書き出す
+    fn read_line(
+        &self,
+        axis: Axis,
+        index: usize,
+        (top, left, size): (usize, usize, usize),
+        line: &mut [CellState],
+    ) {
+        match self {
+            QuadNode::Leaf(state) => {
+                let start = along(axis, top, left);
+                for cell in line.iter_mut().skip(start).take(size) {
+                    *cell = *state;
+                }
+            }
+            QuadNode::Split(children) => {
+                let half = size / 2;
+                for (child, (dr, dc)) in children.iter().zip(quadrants(half)) {
+                    if crosses(axis, index, top + dr, left + dc, half) {
+                        child.read_line(axis, index, (top + dr, left + dc, half), line);
                     }
                 }
-                Err(e) => {
-                    let result = SolveResult {
-                        grid: original_grid,
-                        message: format!("列 {}: {}", c + 1, e),
-                        error: true,
-                    };
-                    return Ok(serde_wasm_bindgen::to_value(&result)?);
+            }
+        }
+    }
+
+    /// このノードの領域に含まれる、`axis`の`index`番目のラインの部分を`line`の内容に書き換える
+    /// 書き換えた後に一様になった小領域は、1つの葉にまとめ直す
+    fn write_line(
+        &mut self,
+        axis: Axis,
+        index: usize,
+        (top, left, size): (usize, usize, usize),
+        line: &[CellState],
+    ) {
+        if let QuadNode::Leaf(state) = *self {
+            let start = along(axis, top, left);
+            // 書き込む部分が既に葉と同じ状態なら、木を変える必要はない
+            if line
+                .iter()
+                .skip(start)
+                .take(size)
+                .all(|&cell| cell == state)
+            {
+                return;
+            }
+            if size == 1 {
+                *self = QuadNode::Leaf(line[start]);
+                return;
+            }
+            *self = QuadNode::Split(Box::new([
+                QuadNode::Leaf(state),
+                QuadNode::Leaf(state),
+                QuadNode::Leaf(state),
+                QuadNode::Leaf(state),
+            ]));
+        }
+        if let QuadNode::Split(children) = self {
+            let half = size / 2;
+            for (child, (dr, dc)) in children.iter_mut().zip(quadrants(half)) {
+                if crosses(axis, index, top + dr, left + dc, half) {
+                    child.write_line(axis, index, (top + dr, left + dc, half), line);
                 }
             }
         }
-        // 解析が終わったら、再度転置して盤面を元の向きに戻す
-        current_grid = transpose(transposed);
+        self.merge();
+    }
+}
 
-        // 3. ループの終了条件をチェック
-        iteration += 1;
+/// 一辺`2 * half`の領域を4つに分けたときの、左上、右上、左下、右下の小領域の位置（行, 列のずれ）
+fn quadrants(half: usize) -> [(usize, usize); 4] {
+    [(0, 0), (0, half), (half, 0), (half, half)]
+}
 
-        // このイテレーションで盤面に何も変化がなかった場合、解析は完了
-        if !changed_in_this_iteration {
-            let message = if current_grid == original_grid {
-                // 呼び出し時点から何も変化がなければ、これ以上進展はない
-                "これ以上自動で確定できるマスはありません".to_string()
+/// `(top, left)`を左上とする一辺`size`の領域が、`axis`の`index`番目のラインと交わるか
+fn crosses(axis: Axis, index: usize, top: usize, left: usize, size: usize) -> bool {
+    let start = match axis {
+        Axis::Row => top,
+        Axis::Column => left,
+    };
+    (start..start + size).contains(&index)
+}
+
+/// `(top, left)`を左上とする領域が、`axis`のラインのどの位置から始まるか
+fn along(axis: Axis, top: usize, left: usize) -> usize {
+    match axis {
+        Axis::Row => left,
+        Axis::Column => top,
+    }
+}
+
+/// 盤面全体を四分木で表した、疎な盤面のための内部表現
+/// 全て「空」のラインの判定や、ライン単位の読み書きを、一様な大領域を飛ばしながら行える
+#[derive(Clone, Debug, PartialEq)]
+struct QuadGrid {
+    rows: usize,    // 盤面の行数
+    cols: usize,    // 盤面の列数
+    size: usize,    // 根のノードが覆う正方形の一辺（2の累乗で、盤面の外の部分は「空」）
+    root: QuadNode, // 盤面全体を覆う四分木
+}
+
+impl QuadGrid {
+    /// 盤面を四分木にする
+    /// 四分木のノードが占める大きさが、マス1つを1バイトで持つ通常の形より小さい疎な盤面だけを対象にし、
+    /// 密な盤面や、行の長さが揃っていない盤面では`None`を返す（呼び出し側は通常の表現を使う）
+    fn sparse(grid: &[Vec<CellState>]) -> Option<Self> {
+        let cols = grid_width(grid).ok()?;
+        Some(QuadGrid::new(grid, cols)).filter(QuadGrid::is_sparse)
+    }
+
+    /// 行の長さが`cols`に揃った盤面を、密かどうかに関わらず四分木にする
+    fn new(grid: &[Vec<CellState>], cols: usize) -> Self {
+        let rows = grid.len();
+        let size = rows.max(cols).max(1).next_power_of_two();
+        QuadGrid {
+            rows,
+            cols,
+            size,
+            root: QuadNode::build(grid, 0, 0, size),
+        }
+    }
+
+    /// 通常の2次元配列より小さく表せているか（確定が進んで密になったら、通常の表現に戻す目安）
+    fn is_sparse(&self) -> bool {
+        self.root.count() * std::mem::size_of::<QuadNode>() < self.rows * self.cols
+    }
+
+    /// 通常の2次元配列に戻す
+    fn to_grid(&self) -> Vec<Vec<CellState>> {
+        let mut grid = vec![vec![CellState::Empty; self.cols]; self.rows];
+        self.root.fill(&mut grid, 0, 0, self.size);
+        grid
+    }
+
+    /// `axis`の`index`番目のラインが全て「空」か
+    fn is_blank_line(&self, axis: Axis, index: usize) -> bool {
+        self.root.is_blank_line(axis, index, (0, 0, self.size))
+    }
+
+    /// `axis`の`index`番目のラインを読み出す
+    fn line(&self, axis: Axis, index: usize) -> Vec<CellState> {
+        let length = match axis {
+            Axis::Row => self.cols,
+            Axis::Column => self.rows,
+        };
+        let mut line = vec![CellState::Empty; length];
+        self.root
+            .read_line(axis, index, (0, 0, self.size), &mut line);
+        line
+    }
+
+    /// `axis`の`index`番目のラインを書き換える
+    fn set_line(&mut self, axis: Axis, index: usize, line: &[CellState]) {
+        self.root.write_line(axis, index, (0, 0, self.size), line);
+    }
+}
+
+/// ラインの向き（行か列か）を表すenum
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Axis {
+    Row,
+    Column,
+}
+
+impl Axis {
+    /// メッセージに埋め込むための日本語の名称を返す
+    fn label(self) -> &'static str {
+        match self {
+            Axis::Row => "行",
+            Axis::Column => "列",
+        }
+    }
+
+    /// ログなどの機械可読な出力に使う英語の名称を返す
+    fn key(self) -> &'static str {
+        match self {
+            Axis::Row => "row",
+            Axis::Column => "column",
+        }
+    }
+}
+
+/// 盤面全体の解析（ライン伝播）が失敗した理由を表すenum
+enum PropagationError {
+    /// あるラインがルールと矛盾した
+    Conflict {
+        axis: Axis,      // 矛盾したラインの向き
+        index: usize,    // 矛盾したラインの番号（0始まり）
+        message: String, // ライン解析が返したエラーメッセージ
+    },
+    /// 最大反復回数に達した
+    IterationLimit,
+}
+
+impl std::fmt::Display for PropagationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PropagationError::Conflict {
+                axis,
+                index,
+                message,
+            } => write!(f, "{} {}: {}", axis.label(), index + 1, message),
+            PropagationError::IterationLimit => f.write_str(
+                "反復回数が上限に達しましたロジックが複雑すぎるか、矛盾があるかもしれません",
+            ),
+        }
+    }
+}
+
+/// ライン1本の解析が終わるたびに、解析ループから観測者へ通知される出来事
+struct LineEvent<'a> {
+    iteration: usize,               // 何イテレーション目の解析か（1始まり）
+    axis: Axis,                     // 解析したラインの向き
+    index: usize,                   // 解析したラインの番号（0始まり）
+    before: &'a [CellState],        // 解析前のラインの状態
+    after: Option<&'a [CellState]>, // 解析後のラインの状態（矛盾した場合は`None`）
+}
+
+impl LineEvent<'_> {
+    /// 解析によって新たに確定したマスの、ライン上の位置を返す
+    fn changed_cells(&self) -> Vec<usize> {
+        self.after.map_or_else(Vec::new, |after| {
+            (0..self.before.len())
+                .filter(|&i| self.before[i] != after[i])
+                .collect()
+        })
+    }
+}
+
+/// 解析の結果、全マスが確定したラインと、前回の解析から変化していないラインを記録するフラグ
+/// 確定済みのラインは一度ルールとの整合を確かめれば二度と変化しないため、以降の解析を省略できる
+/// ライン解析は、同じラインに2回続けて適用しても結果が変わらないので、
+/// 前回の解析以降に交差するラインの解析でマスが変化していないラインも、解析を省略できる
+struct SettledLines {
+    rows: Vec<bool>,       // 各行が確定済みかどうか
+    cols: Vec<bool>,       // 各列が確定済みかどうか
+    stale_rows: Vec<bool>, // 各行が、前回の解析以降に変化したか（まだ一度も解析していなければ`true`）
+    stale_cols: Vec<bool>, // 各列が、前回の解析以降に変化したか（まだ一度も解析していなければ`true`）
+}
+
+impl SettledLines {
+    /// まだどのラインも確定していない状態で初期化する
+    fn new(rows: usize, cols: usize) -> Self {
+        SettledLines {
+            rows: vec![false; rows],
+            cols: vec![false; cols],
+            stale_rows: vec![true; rows],
+            stale_cols: vec![true; cols],
+        }
+    }
+
+    /// ラインを解析し直す必要があるか（未確定で、前回の解析以降に変化している場合）
+    fn needs_solve(&self, axis: Axis, index: usize) -> bool {
+        match axis {
+            Axis::Row => !self.rows[index] && self.stale_rows[index],
+            Axis::Column => !self.cols[index] && self.stale_cols[index],
+        }
+    }
+
+    /// ラインを確定済みとして扱い、以降の解析から外す
+    /// `record`は確定済みのフラグを解析したラインについてしか書き換えないため、一度外したラインは最後まで解析されない
+    fn lock(&mut self, axis: Axis, index: usize) {
+        match axis {
+            Axis::Row => self.rows[index] = true,
+            Axis::Column => self.cols[index] = true,
+        }
+    }
+
+    /// ラインを解析した結果を記録する
+    /// 解析したラインは変化していない状態に戻し、新たに確定したマスに交差するラインを解析し直す対象にする
+    fn record(&mut self, axis: Axis, index: usize, before: &[CellState], after: &[CellState]) {
+        let is_settled = !after.contains(&CellState::Empty);
+        let (settled, stale, crossing) = match axis {
+            Axis::Row => (&mut self.rows, &mut self.stale_rows, &mut self.stale_cols),
+            Axis::Column => (&mut self.cols, &mut self.stale_cols, &mut self.stale_rows),
+        };
+        settled[index] = is_settled;
+        stale[index] = false;
+        for (i, (old, new)) in before.iter().zip(after).enumerate() {
+            if old != new {
+                crossing[i] = true;
+            }
+        }
+    }
+}
+
+/// 行・列のルールをまとめて保持し、盤面全体にライン解析を繰り返し適用する構造体
+/// `solve_puzzle`の解析ループと、完全解の探索の両方から使われる
+struct Propagator {
+    rows: usize,
+    cols: usize,
+    row_rules: Vec<CompiledRule>, // 各行のコンパイル済みルール
+    col_rules: Vec<CompiledRule>, // 各列のコンパイル済みルール
+    order: LineOrder,             // 1イテレーション内でラインを解析する順序
+    symmetric: bool,              // 転置対称なパズルとして行の解析結果を列に流用するかどうか
+    scratch: std::cell::RefCell<LineScratch>, // 全てのラインの解析で使い回す一時バッファ
+    #[cfg(feature = "profiling")]
+    timings: std::cell::RefCell<PhaseTimings>, // 行解析・列解析・転置にかかった時間の累計
+}
+
+impl Propagator {
+    /// 各ラインのルールを一度だけパターン集合へ変換し、解析の準備をする
+    /// 同じルールのラインは、行・列を問わずパターン集合を共有する
+    fn new(rows: usize, cols: usize, row_rules: &[Vec<usize>], col_rules: &[Vec<usize>]) -> Self {
+        let mut compiler = RuleCompiler::default();
+        Propagator {
+            rows,
+            cols,
+            row_rules: row_rules
+                .iter()
+                .map(|rule| compiler.compile(cols, rule))
+                .collect(),
+            col_rules: col_rules
+                .iter()
+                .map(|rule| compiler.compile(rows, rule))
+                .collect(),
+            order: LineOrder::default(),
+            symmetric: false,
+            scratch: Default::default(),
+            #[cfg(feature = "profiling")]
+            timings: Default::default(),
+        }
+    }
+
+    /// ラインを解析する順序を変更する
+    fn with_order(mut self, order: LineOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// 転置対称なパズルとして、行の解析結果を列にも流用するかどうかを設定する
+    /// 盤面とルールが`is_transpose_symmetric`を満たす場合にだけ有効にすること
+    fn with_symmetry(mut self, symmetric: bool) -> Self {
+        self.symmetric = symmetric;
+        self
+    }
+
+    /// 1本のラインを解析し、確定済みのマスが書き換えられていないかも併せて検査する
+    fn solve_line(
+        &self,
+        rule: &CompiledRule,
+        axis: Axis,
+        index: usize,
+        line: &[CellState],
+    ) -> Result<Vec<CellState>, PropagationError> {
+        let conflict = |message: String| PropagationError::Conflict {
+            axis,
+            index,
+            message,
+        };
+        let new_line = rule
+            .apply_with(line, &mut self.scratch.borrow_mut())
+            .map_err(conflict)?;
+        // 確定済みのマスが書き換えられていたら、アルゴリズムの不具合として扱う
+        if let Some(i) = find_non_monotonic_change(line, &new_line) {
+            return Err(conflict(format!(
+                "{}マス目の確定済みの状態が変化しました（内部エラー）",
+                i + 1
+            )));
+        }
+        Ok(new_line)
+    }
+
+    /// 全ての行、続いて全ての列を1回ずつ解析する（1イテレーション分）
+    ///
+    /// # Arguments
+    /// * `grid` - 解析対象の盤面（その場で更新される）
+    /// * `iteration` - 何イテレーション目の解析か（観測者への通知に使う）
+    /// * `settled` - 全マスが確定済みのラインの記録（該当するラインは解析を省略し、その場で更新される）
+    /// * `observer` - ライン1本の解析が終わるたびに呼ばれる関数
+    ///
+    /// # Returns
+    /// * `Ok(bool)` - 盤面に変化があったかどうか
+    /// * `Err(PropagationError)` - 矛盾が見つかった場合のエラー
+    fn pass(
+        &self,
+        grid: &mut Vec<Vec<CellState>>,
+        iteration: usize,
+        settled: &mut SettledLines,
+        observer: &mut dyn FnMut(&LineEvent),
+    ) -> Result<bool, PropagationError> {
+        if self.symmetric {
+            return self.pass_symmetric(grid, iteration, settled, observer);
+        }
+        if self.order == LineOrder::MostConstrainedFirst {
+            return self.pass_most_constrained(grid, iteration, settled, observer);
+        }
+        let mut changed = false;
+
+        // ステップA: 全ての行を解析する
+        #[cfg(feature = "profiling")]
+        let started = now_ms();
+        for (r, (rule, line)) in self.row_rules.iter().zip(grid.iter_mut()).enumerate() {
+            // 全マスが確定済みのラインや、前回の解析から変化していないラインは、解析しても変化しないので省略する
+            if !settled.needs_solve(Axis::Row, r) {
+                continue;
+            }
+            let result = self.solve_line(rule, Axis::Row, r, line);
+            observer(&LineEvent {
+                iteration,
+                axis: Axis::Row,
+                index: r,
+                before: line,
+                after: result.as_deref().ok(),
+            });
+            let new_line = result?;
+            settled.record(Axis::Row, r, line, &new_line);
+            // ラインに変化があれば、盤面を更新し、変更フラグを立てる
+            if new_line != *line {
+                *line = new_line;
+                changed = true;
+            }
+        }
+
+        #[cfg(feature = "profiling")]
+        let started = self.record(|timings| &mut timings.row_ms, started);
+
+        // ステップB: 全ての列を解析する
+        // 列が1本もない盤面を転置すると行数の情報が失われるため、その場合は何もしない
+        if grid.first().is_none_or(|row| row.is_empty()) {
+            return Ok(changed);
+        }
+        // グリッドを転置することで、行と同じ`apply`を列解析に再利用する
+        let mut transposed = transpose(grid.clone());
+        #[cfg(feature = "profiling")]
+        let started = self.record(|timings| &mut timings.transpose_ms, started);
+        for (c, (rule, line)) in self.col_rules.iter().zip(transposed.iter_mut()).enumerate() {
+            if !settled.needs_solve(Axis::Column, c) {
+                continue;
+            }
+            let result = self.solve_line(rule, Axis::Column, c, line);
+            observer(&LineEvent {
+                iteration,
+                axis: Axis::Column,
+                index: c,
+                before: line,
+                after: result.as_deref().ok(),
+            });
+            let new_line = result?;
+            settled.record(Axis::Column, c, line, &new_line);
+            if new_line != *line {
+                *line = new_line;
+                changed = true;
+            }
+        }
+        #[cfg(feature = "profiling")]
+        let started = self.record(|timings| &mut timings.column_ms, started);
+        // 解析が終わったら、再度転置して盤面を元の向きに戻す
+        *grid = transpose(transposed);
+        #[cfg(feature = "profiling")]
+        self.record(|timings| &mut timings.transpose_ms, started);
+
+        Ok(changed)
+    }
+
+    /// 疎な盤面での`LineOrder::Sequential`の1イテレーション分の解析
+    /// `pass`と同じく全ての行、続いて全ての列を解析し、同じ順序で`observer`へ通知する
+    /// 全て「空」のラインは四分木で判定して盤面から読み出さず、列は転置せずに盤面から直接読み書きする
+    /// 列の解析結果は、`pass`と同じく全ての列の解析に成功してから盤面へ書き込む
+    fn pass_sparse(
+        &self,
+        grid: &mut [Vec<CellState>],
+        quad: &mut QuadGrid,
+        iteration: usize,
+        settled: &mut SettledLines,
+        observer: &mut dyn FnMut(&LineEvent),
+    ) -> Result<bool, PropagationError> {
+        let mut changed = false;
+
+        // ステップA: 全ての行を解析する
+        #[cfg(feature = "profiling")]
+        let started = now_ms();
+        let blank_row = vec![CellState::Empty; self.cols];
+        for (r, rule) in self.row_rules.iter().enumerate() {
+            if !settled.needs_solve(Axis::Row, r) {
+                continue;
+            }
+            let line = if quad.is_blank_line(Axis::Row, r) {
+                &blank_row
             } else {
-                // 呼び出し時点から変化していれば、更新があったことを伝える
-                "確定できるマスを更新しました".to_string()
+                &grid[r]
             };
-            let result = SolveResult {
-                grid: current_grid,
-                message,
-                error: false,
+            let result = self.solve_line(rule, Axis::Row, r, line);
+            observer(&LineEvent {
+                iteration,
+                axis: Axis::Row,
+                index: r,
+                before: line,
+                after: result.as_deref().ok(),
+            });
+            let new_line = result?;
+            settled.record(Axis::Row, r, line, &new_line);
+            if new_line != *line {
+                quad.set_line(Axis::Row, r, &new_line);
+                grid[r] = new_line;
+                changed = true;
+            }
+        }
+
+        #[cfg(feature = "profiling")]
+        let started = self.record(|timings| &mut timings.row_ms, started);
+
+        // ステップB: 全ての列を解析する
+        let blank_column = vec![CellState::Empty; self.rows];
+        let mut updates = Vec::new();
+        for (c, rule) in self.col_rules.iter().enumerate() {
+            if !settled.needs_solve(Axis::Column, c) {
+                continue;
+            }
+            let read;
+            let line = if quad.is_blank_line(Axis::Column, c) {
+                &blank_column
+            } else {
+                read = quad.line(Axis::Column, c);
+                &read
             };
-            return Ok(serde_wasm_bindgen::to_value(&result)?);
+            let result = self.solve_line(rule, Axis::Column, c, line);
+            observer(&LineEvent {
+                iteration,
+                axis: Axis::Column,
+                index: c,
+                before: line,
+                after: result.as_deref().ok(),
+            });
+            let new_line = result?;
+            settled.record(Axis::Column, c, line, &new_line);
+            if new_line != *line {
+                updates.push((c, new_line));
+            }
         }
+        for (c, line) in &updates {
+            quad.set_line(Axis::Column, *c, line);
+            set_line(grid, Axis::Column, *c, line);
+        }
+        #[cfg(feature = "profiling")]
+        self.record(|timings| &mut timings.column_ms, started);
 
-        // 最大反復回数に達した場合、エラーとして終了
-        if iteration >= max_iterations {
-            let result = SolveResult {
-                grid: current_grid,
-                message:
-                    "反復回数が上限に達しましたロジックが複雑すぎるか、矛盾があるかもしれません"
-                        .to_string(),
-                error: true,
+        Ok(changed || !updates.is_empty())
+    }
+
+    /// `started`からの経過時間を、`phase`で選んだフェーズの累計に加える
+    ///
+    /// # Returns
+    /// * `f64` - 現在時刻（次のフェーズの開始時刻として使う）
+    #[cfg(feature = "profiling")]
+    fn record(&self, phase: impl FnOnce(&mut PhaseTimings) -> &mut f64, started: f64) -> f64 {
+        let now = now_ms();
+        *phase(&mut self.timings.borrow_mut()) += now - started;
+        now
+    }
+
+    /// `LineOrder::MostConstrainedFirst`での1イテレーション分の解析
+    /// 未確定のマスの割合が小さい（制約の強い）ラインから順に、行と列を区別せずに解析する
+    /// 転置を介さず盤面を直接更新するため、先に解析したラインの結果がすぐ後のラインに反映される
+    fn pass_most_constrained(
+        &self,
+        grid: &mut [Vec<CellState>],
+        iteration: usize,
+        settled: &mut SettledLines,
+        observer: &mut dyn FnMut(&LineEvent),
+    ) -> Result<bool, PropagationError> {
+        // 未確定のラインを集め、「空」のマスの割合が小さい順に並べる
+        let mut order: Vec<(Axis, usize, usize, usize)> = Vec::new();
+        for (axis, count, size) in [
+            (Axis::Row, self.row_rules.len(), self.cols),
+            (Axis::Column, self.col_rules.len(), self.rows),
+        ] {
+            for index in 0..count {
+                let is_settled = match axis {
+                    Axis::Row => settled.rows[index],
+                    Axis::Column => settled.cols[index],
+                };
+                if is_settled {
+                    continue;
+                }
+                let empty = get_line(grid, axis, index)
+                    .iter()
+                    .filter(|&&cell| cell == CellState::Empty)
+                    .count();
+                order.push((axis, index, empty, size.max(1)));
+            }
+        }
+        // 割合の比較は、分母を掛け合わせて整数のまま行う（同率なら行→列の順を保つ）
+        order.sort_by(|a, b| (a.2 * b.3).cmp(&(b.2 * a.3)));
+
+        let mut changed = false;
+        for (axis, index, _, _) in order {
+            // 並べた後に交差するラインが変化することもあるので、解析の直前に変化の有無を確かめる
+            if !settled.needs_solve(axis, index) {
+                continue;
+            }
+            let rule = match axis {
+                Axis::Row => &self.row_rules[index],
+                Axis::Column => &self.col_rules[index],
             };
-            return Ok(serde_wasm_bindgen::to_value(&result)?);
+            let line = get_line(grid, axis, index);
+            let result = self.solve_line(rule, axis, index, &line);
+            observer(&LineEvent {
+                iteration,
+                axis,
+                index,
+                before: &line,
+                after: result.as_deref().ok(),
+            });
+            let new_line = result?;
+            settled.record(axis, index, &line, &new_line);
+            if new_line != line {
+                set_line(grid, axis, index, &new_line);
+                changed = true;
+            }
+        }
+        Ok(changed)
+    }
+
+    /// 転置対称なパズルでの1イテレーション分の解析
+    /// 対称な盤面では`r`列目が`r`行目と同じラインになるため、行だけを解析して結果を同じ番号の列にも書き込む
+    /// 行と列を1本ずつ交互に解析したのと同じ結果になり、書き込み後も盤面の対称性は保たれる
+    fn pass_symmetric(
+        &self,
+        grid: &mut [Vec<CellState>],
+        iteration: usize,
+        settled: &mut SettledLines,
+        observer: &mut dyn FnMut(&LineEvent),
+    ) -> Result<bool, PropagationError> {
+        let mut changed = false;
+        for (r, rule) in self.row_rules.iter().enumerate() {
+            // 確定済みの行や、前回の解析から変化していない行は省略する
+            if !settled.needs_solve(Axis::Row, r) {
+                continue;
+            }
+            let line = grid[r].clone();
+            let result = self.solve_line(rule, Axis::Row, r, &line);
+            // 列の解析は省略するが、経過の通知は行・列の両方について行う
+            for axis in [Axis::Row, Axis::Column] {
+                observer(&LineEvent {
+                    iteration,
+                    axis,
+                    index: r,
+                    before: &line,
+                    after: result.as_deref().ok(),
+                });
+            }
+            let new_line = result?;
+            // 列への書き込みで変化したマスは、同じ番号の行も変化させるため、列としても記録する
+            // 解析した行自身を解析し直す対象に戻さないよう、行としての記録を後に行う
+            settled.record(Axis::Column, r, &line, &new_line);
+            settled.record(Axis::Row, r, &line, &new_line);
+            if new_line != line {
+                set_line(grid, Axis::Row, r, &new_line);
+                set_line(grid, Axis::Column, r, &new_line);
+                changed = true;
+            }
+        }
+        Ok(changed)
+    }
+
+    /// 盤面に変化がなくなるまで`pass`を繰り返す
+    ///
+    /// # Returns
+    /// * `Ok(usize)` - 収束までに要したイテレーション数
+    /// * `Err(PropagationError)` - 矛盾が見つかったか、反復回数が上限に達した場合のエラー
+    fn propagate(&self, grid: &mut Vec<Vec<CellState>>) -> Result<usize, PropagationError> {
+        self.propagate_with(grid, &mut |_| {})
+    }
+
+    /// `propagate`と同じ解析を行いながら、ライン1本の解析ごとに`observer`へ経過を通知する
+    fn propagate_with(
+        &self,
+        grid: &mut Vec<Vec<CellState>>,
+        observer: &mut dyn FnMut(&LineEvent),
+    ) -> Result<usize, PropagationError> {
+        // 転置対称の流用は、ラインの解析順序の指定より優先する（ワークリスト方式でも行単位の対称な解析を使う）
+        if self.order == LineOrder::Worklist && !self.symmetric {
+            return self.propagate_worklist(grid, observer);
+        }
+        let mut settled = SettledLines::new(self.rows, self.cols);
+        self.propagate_passes(grid, &mut settled, observer)
+    }
+
+    /// 盤面に変化がなくなるまで`pass`を繰り返す、`propagate_with`の本体
+    /// `settled`で確定済みとされているラインは、最後まで解析しない
+    fn propagate_passes(
+        &self,
+        grid: &mut Vec<Vec<CellState>>,
+        settled: &mut SettledLines,
+        observer: &mut dyn FnMut(&LineEvent),
+    ) -> Result<usize, PropagationError> {
+        // 無限ループを防ぐための最大反復回数を設定
+        let max_iterations = (self.rows + self.cols) * 2;
+        // 疎な盤面では、四分木を索引として持ちながら転置なしで解析する（密な盤面では通常の`pass`を使う）
+        let mut sparse = if self.order == LineOrder::Sequential && !self.symmetric {
+            QuadGrid::sparse(grid)
+        } else {
+            None
+        };
+        let mut iteration = 0;
+        loop {
+            let changed = match sparse.as_mut() {
+                Some(quad) => self.pass_sparse(grid, quad, iteration + 1, settled, observer)?,
+                None => self.pass(grid, iteration + 1, settled, observer)?,
+            };
+            iteration += 1;
+            // 確定が進んで四分木の方が大きくなったら、以降は通常の`pass`に切り替える
+            if sparse.as_ref().is_some_and(|quad| !quad.is_sparse()) {
+                sparse = None;
+            }
+            // このイテレーションで盤面に何も変化がなかった場合、解析は完了
+            if !changed {
+                return Ok(iteration);
+            }
+            if iteration >= max_iterations {
+                return Err(PropagationError::IterationLimit);
+            }
+        }
+    }
+
+    /// 変化のあったマスに交差するラインだけを解析し直す、ワークリスト方式の伝播
+    /// 全ての行を解析し終えるのを待たずに、確定したマスを交差するラインへすぐに反映する
+    /// 最初に積んだ全ラインを1世代目、その解析で積まれたラインを2世代目…として、世代の番号をイテレーション番号として通知する
+    /// 伝播は「空」のマスを減らす方向にしか進まないため、反復回数の上限がなくても必ず停止する
+    ///
+    /// # Returns
+    /// * `Ok(usize)` - 最後に解析したラインの世代の番号
+    /// * `Err(PropagationError)` - 矛盾が見つかった場合のエラー
+    fn propagate_worklist(
+        &self,
+        grid: &mut [Vec<CellState>],
+        observer: &mut dyn FnMut(&LineEvent),
+    ) -> Result<usize, PropagationError> {
+        // キューには（向き, 番号, 世代）を積み、同じラインを二重に積まないようにフラグで管理する
+        let mut queue: VecDeque<(Axis, usize, usize)> = (0..self.row_rules.len())
+            .map(|r| (Axis::Row, r, 1))
+            .chain((0..self.col_rules.len()).map(|c| (Axis::Column, c, 1)))
+            .collect();
+        let mut queued_rows = vec![true; self.row_rules.len()];
+        let mut queued_cols = vec![true; self.col_rules.len()];
+
+        let mut last_generation = 0;
+        while let Some((axis, index, generation)) = queue.pop_front() {
+            let rule = match axis {
+                Axis::Row => {
+                    queued_rows[index] = false;
+                    &self.row_rules[index]
+                }
+                Axis::Column => {
+                    queued_cols[index] = false;
+                    &self.col_rules[index]
+                }
+            };
+            last_generation = generation;
+
+            let line = get_line(grid, axis, index);
+            let result = self.solve_line(rule, axis, index, &line);
+            observer(&LineEvent {
+                iteration: generation,
+                axis,
+                index,
+                before: &line,
+                after: result.as_deref().ok(),
+            });
+            let new_line = result?;
+            if new_line == line {
+                continue;
+            }
+            set_line(grid, axis, index, &new_line);
+
+            // 新たに確定したマスごとに、そのマスに交差するラインを次の世代として積む
+            for i in (0..line.len()).filter(|&i| line[i] != new_line[i]) {
+                let (crossing, flag) = match axis {
+                    Axis::Row => (Axis::Column, &mut queued_cols[i]),
+                    Axis::Column => (Axis::Row, &mut queued_rows[i]),
+                };
+                if !*flag {
+                    *flag = true;
+                    queue.push_back((crossing, i, generation + 1));
+                }
+            }
+        }
+        Ok(last_generation)
+    }
+
+    /// 盤面を互いに影響し合わないゾーンに分け、ゾーンごとに収束するまで解析する
+    /// 最初に全ラインを1回ずつ解析して端から確定できるマスを埋めてから、ゾーン分割を行う
+    /// 「空」のマスを共有しない行と列は互いの解析結果に影響しないため、結果は`propagate`と一致する
+    ///
+    /// # Returns
+    /// * `Ok(usize)` - 分割したゾーンの数
+    /// * `Err(PropagationError)` - 矛盾が見つかった場合のエラー
+    fn propagate_zoned(&self, grid: &mut Vec<Vec<CellState>>) -> Result<usize, PropagationError> {
+        let mut settled = SettledLines::new(self.rows, self.cols);
+        self.pass(grid, 1, &mut settled, &mut |_| {})?;
+
+        let zones = find_zones(grid, &settled);
+        for zone in &zones {
+            // ゾーン内のラインだけを、変化がなくなるまで繰り返し解析する
+            // 伝播は「空」のマスを減らす方向にしか進まないため、必ず停止する
+            loop {
+                let mut changed = false;
+                for &(axis, index) in zone {
+                    let (rule, is_settled) = match axis {
+                        Axis::Row => (&self.row_rules[index], &mut settled.rows[index]),
+                        Axis::Column => (&self.col_rules[index], &mut settled.cols[index]),
+                    };
+                    if *is_settled {
+                        continue;
+                    }
+                    let line = get_line(grid, axis, index);
+                    let new_line = self.solve_line(rule, axis, index, &line)?;
+                    *is_settled = !new_line.contains(&CellState::Empty);
+                    if new_line != line {
+                        set_line(grid, axis, index, &new_line);
+                        changed = true;
+                    }
+                }
+                if !changed {
+                    break;
+                }
+            }
+        }
+        Ok(zones.len())
+    }
+
+    /// 反復回数の上限に達しても打ち切らず、盤面が収束するか矛盾が見つかるまで`propagate`を続ける
+    /// 伝播は「空」のマスを減らす方向にしか進まないため、必ず停止する
+    fn propagate_to_fixpoint(
+        &self,
+        grid: &mut Vec<Vec<CellState>>,
+    ) -> Result<(), PropagationError> {
+        loop {
+            match self.propagate(grid) {
+                Err(PropagationError::IterationLimit) => continue,
+                other => return other.map(|_| ()),
+            }
+        }
+    }
+}
+
+/// パズルが転置対称（行ルールと列ルールが一致し、盤面も対角線について対称）かどうかを判定する
+/// 対称なパズルでは、列の解析結果は行の解析結果を転置したものと必ず一致する
+fn is_transpose_symmetric(
+    row_rules: &[Vec<usize>],
+    col_rules: &[Vec<usize>],
+    grid: &[Vec<CellState>],
+) -> bool {
+    let size = grid.len();
+    row_rules == col_rules
+        && row_rules.len() == size
+        && grid.iter().all(|row| row.len() == size)
+        && (0..size).all(|r| (0..r).all(|c| grid[r][c] == grid[c][r]))
+}
+
+/// 未確定のラインを、「空」のマスを共有するもの同士が同じゾーンになるようにまとめる関数
+/// 行と列を頂点、「空」のマスを辺とみなしたグラフの連結成分が、そのままゾーンになる
+/// 「空」のマスが残っていなくても、まだ整合を確かめていないラインは単独のゾーンとして残す
+fn find_zones(grid: &[Vec<CellState>], settled: &SettledLines) -> Vec<Vec<(Axis, usize)>> {
+    let rows = settled.rows.len();
+    let cols = settled.cols.len();
+
+    // 行を`0..rows`、列を`rows..rows + cols`の番号で扱うUnion-Find
+    fn find(parent: &mut [usize], mut x: usize) -> usize {
+        while parent[x] != x {
+            parent[x] = parent[parent[x]];
+            x = parent[x];
+        }
+        x
+    }
+    let mut parent: Vec<usize> = (0..rows + cols).collect();
+    for (r, line) in grid.iter().enumerate() {
+        for (c, &cell) in line.iter().enumerate() {
+            if cell == CellState::Empty {
+                let a = find(&mut parent, r);
+                let b = find(&mut parent, rows + c);
+                parent[a] = b;
+            }
+        }
+    }
+
+    // 代表元ごとにラインを集める（ゾーンの順序は、最初に現れたラインの順）
+    let mut zone_of_root: Vec<Option<usize>> = vec![None; rows + cols];
+    let mut zones: Vec<Vec<(Axis, usize)>> = Vec::new();
+    let lines = (0..rows)
+        .filter(|&r| !settled.rows[r])
+        .map(|r| (Axis::Row, r, r))
+        .chain(
+            (0..cols)
+                .filter(|&c| !settled.cols[c])
+                .map(|c| (Axis::Column, c, rows + c)),
+        );
+    for (axis, index, node) in lines {
+        let root = find(&mut parent, node);
+        let zone = *zone_of_root[root].get_or_insert_with(|| {
+            zones.push(Vec::new());
+            zones.len() - 1
+        });
+        zones[zone].push((axis, index));
+    }
+    zones
+}
+
+/// 盤面から、指定した向き・番号のラインを取り出すヘルパー関数
+fn get_line(grid: &[Vec<CellState>], axis: Axis, index: usize) -> Vec<CellState> {
+    match axis {
+        Axis::Row => grid[index].clone(),
+        Axis::Column => grid.iter().map(|row| row[index]).collect(),
+    }
+}
+
+/// 盤面の指定した向き・番号のラインを、`line`の内容で書き換えるヘルパー関数
+fn set_line(grid: &mut [Vec<CellState>], axis: Axis, index: usize, line: &[CellState]) {
+    match axis {
+        Axis::Row => grid[index].copy_from_slice(line),
+        Axis::Column => {
+            for (row, &state) in grid.iter_mut().zip(line) {
+                row[index] = state;
+            }
+        }
+    }
+}
+
+/// 盤面の列数（各行の長さ）を求めるヘルパー関数
+/// 先頭行の長さを基準とし、長さの違う行が混ざっていればエラーを返す
+fn grid_width(grid: &[Vec<CellState>]) -> Result<usize, String> {
+    let width = grid.first().map_or(0, |row| row.len());
+    if grid.iter().any(|row| row.len() != width) {
+        return Err("盤面の各行の長さが揃っていません".to_string());
+    }
+    Ok(width)
+}
+
+/// 行数・列数と、ルールの本数・盤面の形が一致しているかを検査する関数
+/// 幅と高さの取り違えはインデックスの範囲外アクセスにつながるため、解析の前に弾いておく
+fn check_dimensions(
+    rows: usize,
+    cols: usize,
+    row_rules: &[Vec<usize>],
+    col_rules: &[Vec<usize>],
+    grid: &[Vec<CellState>],
+) -> Result<(), String> {
+    if row_rules.len() != rows {
+        return Err(format!(
+            "行ルールの数（{}）が行数（{}）と一致しません",
+            row_rules.len(),
+            rows
+        ));
+    }
+    if col_rules.len() != cols {
+        return Err(format!(
+            "列ルールの数（{}）が列数（{}）と一致しません",
+            col_rules.len(),
+            cols
+        ));
+    }
+    if grid.len() != rows {
+        return Err(format!(
+            "盤面の行数（{}）が指定した行数（{}）と一致しません",
+            grid.len(),
+            rows
+        ));
+    }
+    if let Some(r) = grid.iter().position(|row| row.len() != cols) {
+        return Err(format!(
+            "盤面の{}行目の長さ（{}）が指定した列数（{}）と一致しません",
+            r + 1,
+            grid[r].len(),
+            cols
+        ));
+    }
+    Ok(())
+}
+
+/// 1本のラインの「塗り」の並びから、そのラインのルール（連続する塗りの長さの列）を求める関数
+/// 例: `[塗り, 塗り, ×, 塗り]` → `[2, 1]`
+fn line_to_rule(line: &[CellState]) -> Vec<usize> {
+    let mut rule = Vec::new();
+    let mut run = 0;
+    for &cell in line {
+        if cell == CellState::Filled {
+            run += 1;
+        } else if run > 0 {
+            rule.push(run);
+            run = 0;
+        }
+    }
+    if run > 0 {
+        rule.push(run);
+    }
+    rule
+}
+
+/// ラインの「塗り」の並びがルールと一致しているかを、アロケーションなしで判定する関数
+/// 「×」と「空」はどちらも「塗りではない」マスとして扱う
+/// 完成判定は全マスが確定している前提で使う
+/// ルール中の0は無視するので、`[]`と`[0]`はどちらも「塗りがない」ラインと一致する
+fn rule_matches(rule: &[usize], line: &[CellState]) -> bool {
+    let mut blocks = rule.iter().copied().filter(|&n| n > 0);
+    let mut run = 0;
+    for &cell in line {
+        if cell == CellState::Filled {
+            run += 1;
+        } else if run > 0 {
+            if blocks.next() != Some(run) {
+                return false;
+            }
+            run = 0;
+        }
+    }
+    if run > 0 && blocks.next() != Some(run) {
+        return false;
+    }
+    blocks.next().is_none()
+}
+
+/// 1本のラインが、既にルールを満たしているかを判定する関数
+/// 「塗り」のマスだけを見て判定し、「×」と「空」は区別しない（全マスが確定した完成判定を前提とする）
+///
+/// # Arguments
+/// * `rule_js` - ルール（例: `[2, 1]`）
+/// * `line_js` - ラインの状態（`CellState`の配列）
+///
+/// # Returns
+/// * `Ok(bool)` - 「塗り」の並びがルールと一致していれば`true`
+/// * `Err(JsValue)` - 入力の形式が不正な場合のエラー
+#[wasm_bindgen]
+pub fn line_satisfied(rule_js: JsValue, line_js: JsValue) -> Result<bool, JsValue> {
+    let rule: Vec<usize> = serde_wasm_bindgen::from_value(rule_js)?;
+    let line: Vec<CellState> = serde_wasm_bindgen::from_value(line_js)?;
+    Ok(rule_matches(&rule, &line))
+}
+
+/// ラインの中で、ルールに明らかに違反している「塗り」のマスを探す関数
+/// ラインの全ての配置を調べる`solve_line`とは違い、次の局所的な違反だけを1回の走査で検出する
+/// * 連続する「塗り」が、ルールの最大のブロック長より長い
+/// * 「×」で区切られた「塗り」を含む区間の数が、ルールのブロック数より多い
+/// * 「塗り」のマスの数が、ルールのブロック長の合計より多い
+///
+/// # Returns
+/// * 違反の種類と説明、違反の原因になっている「塗り」のマスの位置の組（違反がなければ空）
+fn line_violations(rule: &[usize], line: &[CellState]) -> Vec<(LineIssueKind, String, Vec<usize>)> {
+    let blocks = normalize_rule(rule);
+    let filled: Vec<usize> = (0..line.len())
+        .filter(|&i| line[i] == CellState::Filled)
+        .collect();
+    // ブロックのないラインは、「塗り」が1つでもあれば違反になる
+    if blocks.is_empty() {
+        if filled.is_empty() {
+            return Vec::new();
+        }
+        return vec![(
+            LineIssueKind::FilledInBlankLine,
+            "ブロックのないラインに「塗り」があります".to_string(),
+            filled,
+        )];
+    }
+    let max_block = blocks.iter().copied().max().unwrap_or(0);
+    let mut violations = Vec::new();
+
+    // 最大のブロック長を超えて連続している「塗り」
+    let mut start = 0;
+    for (i, &cell) in line.iter().chain([&CellState::Empty]).enumerate() {
+        if cell != CellState::Filled {
+            if i - start > max_block {
+                violations.push((
+                    LineIssueKind::BlockTooLong,
+                    format!(
+                        "{}マス連続した「塗り」が、最大のブロック長（{}）を超えています",
+                        i - start,
+                        max_block
+                    ),
+                    (start..i).collect(),
+                ));
+            }
+            start = i + 1;
+        }
+    }
+
+    // 「×」で区切られた区間は別のブロックになるので、「塗り」を含む区間の数はブロック数を超えられない
+    let segments = line
+        .split(|&cell| cell == CellState::Crossed)
+        .filter(|segment| segment.contains(&CellState::Filled))
+        .count();
+    if segments > blocks.len() {
+        violations.push((
+            LineIssueKind::TooManySegments,
+            format!(
+                "「×」で区切られた{}個の区間に「塗り」があり、ブロック数（{}）より多くなっています",
+                segments,
+                blocks.len()
+            ),
+            filled.clone(),
+        ));
+    }
+
+    let total: usize = blocks.iter().sum();
+    if filled.len() > total {
+        violations.push((
+            LineIssueKind::TooManyFilled,
+            format!(
+                "「塗り」のマスが{}個あり、ブロック長の合計（{}）より多くなっています",
+                filled.len(),
+                total
+            ),
+            filled,
+        ));
+    }
+    violations
+}
+
+/// ルール違反として検出したラインの情報
+#[derive(Serialize, Deserialize)]
+pub struct LineViolation {
+    axis: String,    // ラインの向き（"row"または"column"）
+    index: usize,    // ラインの番号（0始まり）
+    message: String, // 違反の内容
+}
+
+/// 明白なルール違反の検出結果をJavaScriptに返すためのデータ構造
+#[derive(Serialize, Deserialize)]
+pub struct ViolationMarks {
+    cells: Vec<Vec<bool>>, // 盤面と同じ形の、違反の原因になっている「塗り」のマスなら`true`
+    lines: Vec<LineViolation>, // 違反を検出したラインの一覧（行、列の順）
+}
+
+/// ユーザーが手で塗っている最中の盤面から、明らかにルールに違反している「塗り」のマスを検出する関数
+/// 解の完全な検証ではなく、ラインごとの局所的な違反（最大のブロック長を超える連続した「塗り」など）だけを調べるので、
+/// 配置パターンを生成する解析より軽く、入力のたびに呼び出すリアルタイムのフィードバックに使える
+///
+/// # Arguments
+/// * `rows` - 盤面の行数
+/// * `cols` - 盤面の列数
+/// * `row_rules_js` - 行ルールの配列
+/// * `col_rules_js` - 列ルールの配列
+/// * `grid_js` - 盤面の状態
+///
+/// # Returns
+/// * `Ok(JsValue)` - 違反のマスと、違反を検出したライン（`ViolationMarks`）
+/// * `Err(JsValue)` - 入力が不正な場合のエラー
+#[wasm_bindgen]
+pub fn mark_violations(
+    rows: usize,
+    cols: usize,
+    row_rules_js: JsValue,
+    col_rules_js: JsValue,
+    grid_js: JsValue,
+) -> Result<JsValue, JsValue> {
+    let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+    let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+    let grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(grid_js)?;
+    check_dimensions(rows, cols, &row_rules, &col_rules, &grid)
+        .map_err(|e| JsValue::from_str(&e))?;
+
+    let mut marks = ViolationMarks {
+        cells: vec![vec![false; cols]; rows],
+        lines: Vec::new(),
+    };
+    for (axis, rules) in [(Axis::Row, &row_rules), (Axis::Column, &col_rules)] {
+        for (index, rule) in rules.iter().enumerate() {
+            for (_, message, positions) in line_violations(rule, &get_line(&grid, axis, index)) {
+                for i in positions {
+                    match axis {
+                        Axis::Row => marks.cells[index][i] = true,
+                        Axis::Column => marks.cells[i][index] = true,
+                    }
+                }
+                marks.lines.push(LineViolation {
+                    axis: axis.key().to_string(),
+                    index,
+                    message,
+                });
+            }
+        }
+    }
+    Ok(serde_wasm_bindgen::to_value(&marks)?)
+}
+
+/// ラインの矛盾の原因の種類
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LineIssueKind {
+    /// ブロックのないラインに「塗り」がある
+    FilledInBlankLine,
+    /// 連続した「塗り」が、ルールの最大のブロック長より長い
+    BlockTooLong,
+    /// 「×」で区切られた「塗り」を含む区間の数が、ルールのブロック数より多い
+    TooManySegments,
+    /// 「塗り」のマスの数が、ルールのブロック長の合計より多い
+    TooManyFilled,
+    /// 「×」でないマスの数が、ルールのブロック長の合計より少ない
+    TooFewCells,
+    /// 両端が確定した「塗り」のブロックと同じ長さのブロックが、ルールにない
+    UnmatchedBlock,
+    /// 「×」で区切られたどの区間にも、ルールの最大のブロックが収まらない
+    NoRoomForBlock,
+    /// ルールがラインの長さに収まらない
+    RuleTooLong,
+    /// 個々の条件は満たすが、全てのブロックを矛盾なく配置する方法がない
+    PlacementImpossible,
+}
+
+/// `diagnose_line`で見つかった矛盾の原因1つ分
+#[derive(Serialize, Deserialize)]
+pub struct LineIssue {
+    kind: LineIssueKind, // 原因の種類
+    message: String,     // ユーザーに表示する説明
+    cells: Vec<usize>, // 原因になっているマスの位置（ライン上の0始まりの番号、特定できなければ空）
+}
+
+/// `diagnose_line`の結果
+#[derive(Serialize, Deserialize)]
+pub struct LineDiagnosis {
+    consistent: bool, // ラインがルールと矛盾していないか（`solve_line`が成功するか）
+    issues: Vec<LineIssue>, // 矛盾の原因（矛盾していなければ空）
+}
+
+/// ラインがルールと矛盾している場合に、その原因を診断する関数
+/// `line_violations`の局所的な違反に加えて、塗れるマスの不足、両端が確定したブロックとルールの照合、
+/// 最大のブロックを置ける区間の有無を調べ、どれにも当てはまらなければ「配置が不可能」とだけ報告する
+///
+/// # Arguments
+/// * `size` - ラインの長さ
+/// * `rule_js` - ルール（例: `[2, 1]`）
+/// * `user_line_js` - ラインの状態（`CellState`の配列）
+///
+/// # Returns
+/// * `Ok(JsValue)` - 診断結果（`LineDiagnosis`）
+/// * `Err(JsValue)` - 入力の形式が不正な場合や、ラインの長さが`size`と一致しない場合のエラー
+#[wasm_bindgen]
+pub fn diagnose_line(
+    size: usize,
+    rule_js: JsValue,
+    user_line_js: JsValue,
+) -> Result<JsValue, JsValue> {
+    let rule: Vec<usize> = serde_wasm_bindgen::from_value(rule_js)?;
+    let line: Vec<CellState> = serde_wasm_bindgen::from_value(user_line_js)?;
+    if line.len() != size {
+        return Err(JsValue::from_str(&format!(
+            "ラインの長さ（{}）が指定した長さ（{}）と一致しません",
+            line.len(),
+            size
+        )));
+    }
+    let consistent = compile_rule(size, &rule).apply(&line).is_ok();
+    let mut issues: Vec<LineIssue> = Vec::new();
+    if consistent {
+        return Ok(serde_wasm_bindgen::to_value(&LineDiagnosis {
+            consistent,
+            issues,
+        })?);
+    }
+
+    let blocks = match ValidatedRule::new(&rule, size) {
+        Ok(validated) => validated.blocks,
+        Err(message) => {
+            issues.push(LineIssue {
+                kind: LineIssueKind::RuleTooLong,
+                message,
+                cells: Vec::new(),
+            });
+            return Ok(serde_wasm_bindgen::to_value(&LineDiagnosis {
+                consistent,
+                issues,
+            })?);
+        }
+    };
+    issues.extend(
+        line_violations(&blocks, &line)
+            .into_iter()
+            .map(|(kind, message, cells)| LineIssue {
+                kind,
+                message,
+                cells,
+            }),
+    );
+
+    // 「×」でないマスを全て塗っても、ブロック長の合計に届かない
+    let total: usize = blocks.iter().sum();
+    let open: Vec<usize> = (0..size)
+        .filter(|&i| line[i] != CellState::Crossed)
+        .collect();
+    if open.len() < total {
+        issues.push(LineIssue {
+            kind: LineIssueKind::TooFewCells,
+            message: format!(
+                "「×」でないマスが{}個しかなく、ブロック長の合計（{}）に足りません",
+                open.len(),
+                total
+            ),
+            cells: (0..size)
+                .filter(|&i| line[i] == CellState::Crossed)
+                .collect(),
+        });
+    }
+
+    // 両端が「×」か盤面の端で閉じた「塗り」のブロックは、長さが確定している
+    // 最大のブロック長を超えるものは`line_violations`で報告済みなので、ここでは除く
+    let max_block = blocks.iter().copied().max().unwrap_or(0);
+    let mut start = 0;
+    for (i, &cell) in line.iter().chain([&CellState::Crossed]).enumerate() {
+        if cell == CellState::Filled {
+            continue;
+        }
+        let closed_before = start == 0 || line[start - 1] == CellState::Crossed;
+        let length = i - start;
+        if length > 0
+            && length <= max_block
+            && closed_before
+            && cell == CellState::Crossed
+            && !blocks.contains(&length)
+        {
+            issues.push(LineIssue {
+                kind: LineIssueKind::UnmatchedBlock,
+                message: format!(
+                    "長さ{}で確定した「塗り」のブロックに対応するブロックが、ルールにありません",
+                    length
+                ),
+                cells: (start..i).collect(),
+            });
+        }
+        start = i + 1;
+    }
+
+    // 最大のブロックは、「×」で区切られたいずれかの区間に収まらなければならない
+    let widest = line
+        .split(|&cell| cell == CellState::Crossed)
+        .map(|segment| segment.len())
+        .max()
+        .unwrap_or(0);
+    if widest < max_block {
+        issues.push(LineIssue {
+            kind: LineIssueKind::NoRoomForBlock,
+            message: format!(
+                "長さ{}のブロックを置ける区間がありません（「×」で区切られた区間は最長で{}マスです）",
+                max_block, widest
+            ),
+            cells: (0..size).filter(|&i| line[i] == CellState::Crossed).collect(),
+        });
+    }
+
+    if issues.is_empty() {
+        issues.push(LineIssue {
+            kind: LineIssueKind::PlacementImpossible,
+            message: "個々の条件は満たしていますが、ルールの全てのブロックを矛盾なく配置する方法がありません".to_string(),
+            cells: Vec::new(),
+        });
+    }
+    Ok(serde_wasm_bindgen::to_value(&LineDiagnosis {
+        consistent,
+        issues,
+    })?)
+}
+
+/// 完成盤面から、行ルールと列ルールをまとめて求める関数
+fn rules_from_grid(grid: &[Vec<CellState>]) -> (Vec<Vec<usize>>, Vec<Vec<usize>>) {
+    let row_rules = grid.iter().map(|row| line_to_rule(row)).collect();
+    let col_rules = transpose(grid.to_vec())
+        .iter()
+        .map(|col| line_to_rule(col))
+        .collect();
+    (row_rules, col_rules)
+}
+
+/// 変換済みのルールで盤面全体を解析し、経過の統計を含む結果を組み立てる
+fn solve_with(propagator: &Propagator, mut current_grid: Vec<Vec<CellState>>) -> SolveResult {
+    // 呼び出し時点の盤面を、後で比較するために保存しておく
+    let original_grid = current_grid.clone();
+
+    // 盤面に変化がなくなるまで解析を繰り返す
+    // 矛盾で打ち切った場合にも経過を返せるよう、解析したラインを数えておく
+    let (mut last_iteration, mut lines_processed) = (0, 0);
+    let outcome = propagator.propagate_with(&mut current_grid, &mut |event| {
+        last_iteration = event.iteration;
+        lines_processed += 1;
+    });
+    let metrics = SolveMetrics::new(&outcome, last_iteration, lines_processed);
+    let mut result = SolveResult::from_propagation(outcome, original_grid, current_grid);
+    result.metrics = Some(metrics);
+    result
+}
+
+/// `solve_puzzle`と同じ解析を、`JsValue`を介さずにRustのデータ構造のまま行う関数
+/// WASMに依存しないので、Rustのテストやサーバサイド、他のRustプロジェクトから直接呼び出せる
+///
+/// # Arguments
+/// * `rows` - 盤面の行数
+/// * `cols` - 盤面の列数
+/// * `row_rules` - 行ルールの配列
+/// * `col_rules` - 列ルールの配列
+/// * `grid` - 解析を始める盤面
+///
+/// # Returns
+/// * 解析結果（ルールの本数や盤面の形が行数・列数と食い違う場合は、渡された盤面とエラーメッセージを持つ結果）
+pub fn solve_puzzle_native(
+    rows: usize,
+    cols: usize,
+    row_rules: &[Vec<usize>],
+    col_rules: &[Vec<usize>],
+    grid: Vec<Vec<CellState>>,
+) -> SolveResult {
+    if let Err(message) = check_dimensions(rows, cols, row_rules, col_rules, &grid) {
+        return SolveResult {
+            grid,
+            message,
+            error: true,
+            metrics: None,
+        };
+    }
+    // 各ラインのルールを、解析に入る前に一度だけパターン集合へ変換しておく
+    let propagator = Propagator::new(rows, cols, row_rules, col_rules);
+    solve_with(&propagator, grid)
+}
+
+/// JavaScriptから呼び出されるメインの関数パズル全体の解析を行う
+/// 引数を変換して`solve_puzzle_native`を呼び出す
+#[wasm_bindgen]
+pub fn solve_puzzle(
+    rows: usize,
+    cols: usize,
+    row_rules_js: JsValue,
+    col_rules_js: JsValue,
+    initial_grid_js: JsValue,
+) -> Result<JsValue, JsValue> {
+    // デバッグ用のパニックフックを設定
+    #[cfg(feature = "console_error_panic_hook")]
+    set_panic_hook();
+
+    #[cfg(feature = "profiling")]
+    let started = now_ms();
+
+    // JavaScriptから渡されたJsValueを、Rustのデータ構造に変換（デシリアライズ）する
+    let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+    let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+    // 盤面は全体を読み込んでから検証し、不正なマスがあればその位置をエラーメッセージで伝える
+    let current_grid = grid_from_js(initial_grid_js)?;
+
+    // ルールの本数や盤面の形が食い違っている場合は、結果ではなく例外としてJavaScriptに伝える
+    check_dimensions(rows, cols, &row_rules, &col_rules, &current_grid)
+        .map_err(|e| JsValue::from_str(&e))?;
+
+    // 計測する場合は、フェーズごとの時間を`Propagator`から読み出すため、同じ手順をここで行う
+    #[cfg(feature = "profiling")]
+    {
+        let propagator = Propagator::new(rows, cols, &row_rules, &col_rules);
+        let deserialize_ms = now_ms() - started;
+        let result = solve_with(&propagator, current_grid);
+        ProfiledSolveResult::new(result, deserialize_ms, &propagator).into_js()
+    }
+
+    #[cfg(not(feature = "profiling"))]
+    {
+        let result = solve_puzzle_native(rows, cols, &row_rules, &col_rules, current_grid);
+        Ok(serde_wasm_bindgen::to_value(&result)?)
+    }
+}
+
+impl Propagator {
+    /// ロックしたラインを解析せずに、`propagate_with`と同じ解析を行う
+    /// ロックしたラインのマスは交差するラインの解析ではそのまま使われ、交差するラインの解析で埋まることもある
+    /// 解析の前後でロックしたラインがルールと合っているかを確かめ、合わなければ矛盾として返す
+    /// 並べ方の指定や転置対称の流用には対応しないため、既定の設定の`Propagator`で使うこと
+    ///
+    /// # Arguments
+    /// * `grid` - 解析対象の盤面（その場で更新される）
+    /// * `locked_rows` - ロックする行の番号（0始まり、範囲内であること）
+    /// * `locked_cols` - ロックする列の番号（0始まり、範囲内であること）
+    /// * `observer` - ライン1本の解析が終わるたびに呼ばれる関数
+    fn propagate_locked(
+        &self,
+        grid: &mut Vec<Vec<CellState>>,
+        locked_rows: &[usize],
+        locked_cols: &[usize],
+        observer: &mut dyn FnMut(&LineEvent),
+    ) -> Result<usize, PropagationError> {
+        let locked: Vec<(Axis, usize)> = locked_rows
+            .iter()
+            .map(|&r| (Axis::Row, r))
+            .chain(locked_cols.iter().map(|&c| (Axis::Column, c)))
+            .collect();
+        let check_locked = |grid: &[Vec<CellState>]| {
+            for &(axis, index) in &locked {
+                let rule = match axis {
+                    Axis::Row => &self.row_rules[index],
+                    Axis::Column => &self.col_rules[index],
+                };
+                if let Err(message) = rule.apply(&get_line(grid, axis, index)) {
+                    return Err(PropagationError::Conflict {
+                        axis,
+                        index,
+                        message: format!("ロックしたラインがルールと矛盾しています（{}）", message),
+                    });
+                }
+            }
+            Ok(())
+        };
+
+        check_locked(grid)?;
+        let mut settled = SettledLines::new(self.rows, self.cols);
+        for &(axis, index) in &locked {
+            settled.lock(axis, index);
+        }
+        let iterations = self.propagate_passes(grid, &mut settled, observer)?;
+        // 交差するラインの解析で埋まったマスが、ロックしたラインと合わなくなっていないかを確かめる
+        check_locked(grid)?;
+        Ok(iterations)
+    }
+}
+
+/// 指定した行・列をロックして、それ以外のラインだけを解析する関数
+/// 大きな盤面を段階的に解くときに、確認済みのラインを解析対象から外して解析を軽くする
+/// ロックの状態は保持しないので、ロックを続ける間は呼び出しのたびに同じ番号を渡すこと
+/// ロックしたラインの解析は省略するが、そのマスは交差するラインの解析に使われる
+///
+/// # Arguments
+/// * `rows` - 盤面の行数
+/// * `cols` - 盤面の列数
+/// * `row_rules_js` - 行ルールの配列
+/// * `col_rules_js` - 列ルールの配列
+/// * `initial_grid_js` - 解析を始める盤面
+/// * `locked_rows_js` - ロックする行の番号の配列（0始まり）
+/// * `locked_cols_js` - ロックする列の番号の配列（0始まり）
+///
+/// # Returns
+/// * `Ok(JsValue)` - `solve_puzzle`と同じ形式の解析結果（ロックしたラインがルールと矛盾する場合は、そのラインを示すエラー）
+/// * `Err(JsValue)` - 入力の形式が不正な場合や、ロックする番号が盤面の範囲外の場合のエラー
+#[wasm_bindgen]
+pub fn solve_puzzle_with_locks(
+    rows: usize,
+    cols: usize,
+    row_rules_js: JsValue,
+    col_rules_js: JsValue,
+    initial_grid_js: JsValue,
+    locked_rows_js: JsValue,
+    locked_cols_js: JsValue,
+) -> Result<JsValue, JsValue> {
+    let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+    let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+    let mut grid = grid_from_js(initial_grid_js)?;
+    let locked_rows: Vec<usize> = serde_wasm_bindgen::from_value(locked_rows_js)?;
+    let locked_cols: Vec<usize> = serde_wasm_bindgen::from_value(locked_cols_js)?;
+
+    check_dimensions(rows, cols, &row_rules, &col_rules, &grid)
+        .map_err(|e| JsValue::from_str(&e))?;
+    for (axis, locked, count) in [
+        (Axis::Row, &locked_rows, rows),
+        (Axis::Column, &locked_cols, cols),
+    ] {
+        if let Some(&index) = locked.iter().find(|&&index| index >= count) {
+            return Err(JsValue::from_str(&format!(
+                "ロックする{}の番号（{}）が盤面の範囲外です",
+                axis.label(),
+                index
+            )));
+        }
+    }
+    let propagator = Propagator::new(rows, cols, &row_rules, &col_rules);
+
+    let original_grid = grid.clone();
+    let (mut last_iteration, mut lines_processed) = (0, 0);
+    let outcome =
+        propagator.propagate_locked(&mut grid, &locked_rows, &locked_cols, &mut |event| {
+            last_iteration = event.iteration;
+            lines_processed += 1;
+        });
+    let metrics = SolveMetrics::new(&outcome, last_iteration, lines_processed);
+    let mut result = SolveResult::from_propagation(outcome, original_grid, grid);
+    result.metrics = Some(metrics);
+    Ok(serde_wasm_bindgen::to_value(&result)?)
+}
+
+/// 1イテレーション内でラインを解析する順序を表すenum
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LineOrder {
+    /// 全ての行を上から、続いて全ての列を左から解析する（従来の順序）
+    #[default]
+    Sequential,
+    /// 未確定のマスの割合が小さい（制約の強い）ラインから順に解析する
+    MostConstrainedFirst,
+    /// イテレーション単位ではなく、マスが確定するたびに交差するラインをキューに積んで解析する（ワークリスト方式）
+    /// `Board::solve_step`のような1イテレーション単位の解析では`Sequential`と同じ順序になる
+    Worklist,
+}
+
+/// `solve_puzzle_with_options`で指定できる解析オプション
+/// 省略したフィールドは既定値になり、全て既定値なら`solve_puzzle`と同じ挙動になる
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SolveOptions {
+    reverse_row_rules: bool, // 行ルールを右→左の順に書かれたものとして解釈する
+    reverse_col_rules: bool, // 列ルールを下→上の順に書かれたものとして解釈する
+    line_order: LineOrder, // ラインを解析する順序（"sequential"、"most_constrained_first"、"worklist"）
+    share_symmetric: bool, // 転置対称なパズルを検出したら、行の解析結果を列に流用して計算を省く（`line_order`より優先）
+}
+
+impl SolveOptions {
+    /// JavaScriptから渡されたオプションを読み込む`undefined`や`null`の場合は既定値を使う
+    fn from_js(options_js: JsValue) -> Result<Self, JsValue> {
+        if options_js.is_undefined() || options_js.is_null() {
+            return Ok(SolveOptions::default());
+        }
+        Ok(serde_wasm_bindgen::from_value(options_js)?)
+    }
+}
+
+/// オプションを指定して盤面を解析する関数
+/// 列ルールを下から上に読む形式など、入力形式の違いを内部で吸収してから`solve_puzzle`と同じ解析を行う
+///
+/// # Arguments
+/// * `rows` - 盤面の行数
+/// * `cols` - 盤面の列数
+/// * `row_rules_js` - 行ルールの配列
+/// * `col_rules_js` - 列ルールの配列
+/// * `initial_grid_js` - 現在の盤面
+/// * `options_js` - 解析オプション（`SolveOptions`、省略可）
+#[wasm_bindgen]
+pub fn solve_puzzle_with_options(
+    rows: usize,
+    cols: usize,
+    row_rules_js: JsValue,
+    col_rules_js: JsValue,
+    initial_grid_js: JsValue,
+    options_js: JsValue,
+) -> Result<JsValue, JsValue> {
+    let mut row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+    let mut col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+    let mut current_grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(initial_grid_js)?;
+    let options = SolveOptions::from_js(options_js)?;
+
+    // 逆順に書かれたルールは、内部の解析順（左→右・上→下）に並べ直す
+    if options.reverse_row_rules {
+        row_rules.iter_mut().for_each(|rule| rule.reverse());
+    }
+    if options.reverse_col_rules {
+        col_rules.iter_mut().for_each(|rule| rule.reverse());
+    }
+
+    // 対称性の検出は、ルールの向きを揃え、盤面の形を検証した後に行う
+    check_dimensions(rows, cols, &row_rules, &col_rules, &current_grid)
+        .map_err(|e| JsValue::from_str(&e))?;
+    let symmetric =
+        options.share_symmetric && is_transpose_symmetric(&row_rules, &col_rules, &current_grid);
+    let propagator = Propagator::new(rows, cols, &row_rules, &col_rules)
+        .with_order(options.line_order)
+        .with_symmetry(symmetric);
+    let original_grid = current_grid.clone();
+    let outcome = propagator.propagate(&mut current_grid);
+    let result = SolveResult::from_propagation(outcome, original_grid, current_grid);
+    Ok(serde_wasm_bindgen::to_value(&result)?)
+}
+
+/// 盤面を互いに影響し合わないゾーンに分けて解析する関数
+/// 大きな盤面で、独立した領域ごとに収束させることで無駄なラインの再解析を減らす
+/// 結果は`solve_puzzle`と同じ形式で、盤面も`solve_puzzle`と一致する
+///
+/// # Arguments
+/// * `rows` - 盤面の行数
+/// * `cols` - 盤面の列数
+/// * `row_rules_js` - 行ルールの配列
+/// * `col_rules_js` - 列ルールの配列
+/// * `initial_grid_js` - 現在の盤面
+#[wasm_bindgen]
+pub fn solve_zoned(
+    rows: usize,
+    cols: usize,
+    row_rules_js: JsValue,
+    col_rules_js: JsValue,
+    initial_grid_js: JsValue,
+) -> Result<JsValue, JsValue> {
+    let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+    let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+    let mut current_grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(initial_grid_js)?;
+
+    check_dimensions(rows, cols, &row_rules, &col_rules, &current_grid)
+        .map_err(|e| JsValue::from_str(&e))?;
+    let propagator = Propagator::new(rows, cols, &row_rules, &col_rules);
+    let original_grid = current_grid.clone();
+    let outcome = propagator.propagate_zoned(&mut current_grid);
+    let result = SolveResult::from_propagation(outcome, original_grid, current_grid);
+    Ok(serde_wasm_bindgen::to_value(&result)?)
+}
+
+/// ×の付け手を区別した解析結果をJavaScriptに返すためのデータ構造
+#[derive(Serialize, Deserialize)]
+pub struct MarkedSolveResult {
+    grid: Vec<Vec<MarkedCell>>, // 更新された盤面の状態（自動解析が付けた×は`3`）
+    message: String,            // ユーザーに表示するメッセージ
+    error: bool,                // エラーが発生したかどうかを示すフラグ
+}
+
+/// ユーザーが付けた×と自動解析が付けた×を区別したまま盤面を解析する関数
+/// ユーザーの×は解析で上書きされず、ルールと矛盾していればエラーになる
+/// 新たに確定した×と、入力時点で既に自動解析の×だったマスは`AutoCrossed`として返す
+///
+/// # Arguments
+/// * `rows` - 盤面の行数
+/// * `cols` - 盤面の列数
+/// * `row_rules_js` - 行ルールの配列
+/// * `col_rules_js` - 列ルールの配列
+/// * `initial_grid_js` - 現在の盤面（`MarkedCell`の2次元配列）
+#[wasm_bindgen]
+pub fn solve_puzzle_marked(
+    rows: usize,
+    cols: usize,
+    row_rules_js: JsValue,
+    col_rules_js: JsValue,
+    initial_grid_js: JsValue,
+) -> Result<JsValue, JsValue> {
+    let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+    let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+    let marked_grid: Vec<Vec<MarkedCell>> = serde_wasm_bindgen::from_value(initial_grid_js)?;
+
+    // 解析中は、どちらの×も同じ`Crossed`として扱う
+    let mut current_grid: Vec<Vec<CellState>> = marked_grid
+        .iter()
+        .map(|row| row.iter().map(|&cell| cell.into()).collect())
+        .collect();
+    check_dimensions(rows, cols, &row_rules, &col_rules, &current_grid)
+        .map_err(|e| JsValue::from_str(&e))?;
+    let propagator = Propagator::new(rows, cols, &row_rules, &col_rules);
+    let original_grid = current_grid.clone();
+    let outcome = propagator.propagate(&mut current_grid);
+    let result = SolveResult::from_propagation(outcome, original_grid, current_grid);
+
+    // ユーザーが付けた×以外の×は、全て自動解析が付けたものとして出力する
+    let grid = result
+        .grid
+        .iter()
+        .zip(&marked_grid)
+        .map(|(row, marked_row)| {
+            row.iter()
+                .zip(marked_row)
+                .map(|(&state, &marked)| match state {
+                    CellState::Empty => MarkedCell::Empty,
+                    CellState::Filled => MarkedCell::Filled,
+                    CellState::Crossed if marked == MarkedCell::Crossed => MarkedCell::Crossed,
+                    CellState::Crossed => MarkedCell::AutoCrossed,
+                })
+                .collect()
+        })
+        .collect();
+    let result = MarkedSolveResult {
+        grid,
+        message: result.message,
+        error: result.error,
+    };
+    Ok(serde_wasm_bindgen::to_value(&result)?)
+}
+
+/// 解析ログ付きの結果をJavaScriptに返すためのデータ構造
+#[derive(Serialize, Deserialize)]
+pub struct SolveLogResult {
+    result: SolveResult, // `solve_puzzle`と同じ解析結果
+    log: String,         // 解析の経過（JSON Lines形式）
+}
+
+/// `solve_puzzle`と同じ解析を行い、各ラインの解析経過をJSON Lines（1行1イベント）として記録する関数
+/// 各行は`{"iter":1,"axis":"row","index":0,"changed_cells":[2,3],"outcome":"changed"}`の形式で、
+/// `outcome`は`"changed"`（確定マスあり）、`"unchanged"`（変化なし）、`"conflict"`（矛盾）のいずれか
+/// ログを保存しておけば、後から解析過程を再現・検証できる
+#[wasm_bindgen]
+pub fn solve_with_log(
+    rows: usize,
+    cols: usize,
+    row_rules_js: JsValue,
+    col_rules_js: JsValue,
+    initial_grid_js: JsValue,
+) -> Result<JsValue, JsValue> {
+    let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+    let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+    let mut current_grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(initial_grid_js)?;
+
+    check_dimensions(rows, cols, &row_rules, &col_rules, &current_grid)
+        .map_err(|e| JsValue::from_str(&e))?;
+    let propagator = Propagator::new(rows, cols, &row_rules, &col_rules);
+    let original_grid = current_grid.clone();
+
+    let mut log = String::new();
+    let outcome = propagator.propagate_with(&mut current_grid, &mut |event| {
+        let changed = event.changed_cells();
+        let outcome = match event.after {
+            None => "conflict",
+            Some(_) if changed.is_empty() => "unchanged",
+            Some(_) => "changed",
+        };
+        let cells: Vec<String> = changed.iter().map(usize::to_string).collect();
+        log.push_str(&format!(
+            "{{\"iter\":{},\"axis\":\"{}\",\"index\":{},\"changed_cells\":[{}],\"outcome\":\"{}\"}}\n",
+            event.iteration,
+            event.axis.key(),
+            event.index,
+            cells.join(","),
+            outcome
+        ));
+    });
+
+    let result = SolveResult::from_propagation(outcome, original_grid, current_grid);
+    Ok(serde_wasm_bindgen::to_value(&SolveLogResult {
+        result,
+        log,
+    })?)
+}
+
+/// マスが確定したタイミングを表す、タイムラインの1項目
+#[derive(Serialize, Deserialize)]
+pub struct TimelineEntry {
+    row: usize,        // 確定したマスの行番号
+    col: usize,        // 確定したマスの列番号
+    state: CellState,  // 確定した状態
+    iteration: usize,  // 何イテレーション目で確定したか（1始まり）
+    axis: String,      // 確定させたラインの向き（"row"または"column"）
+    line_index: usize, // 確定させたラインの番号（0始まり）
+    order: usize, // 確定させたライン解析が、全体の何回目の解析か（1始まり、同じ解析で確定したマスは同じ値）
+}
+
+/// 確定順のタイムライン付きの解析結果をJavaScriptに返すためのデータ構造
+#[derive(Serialize, Deserialize)]
+pub struct TimelineResult {
+    result: SolveResult,          // `solve_puzzle`と同じ解析結果
+    timeline: Vec<TimelineEntry>, // 確定したマスの一覧（確定した順）
+}
+
+/// `solve_puzzle`と同じ解析を行い、各マスがどのイテレーションのどのライン解析で確定したかを記録する関数
+/// `solve_with_log`がライン単位の記録なのに対し、こちらはマス単位で確定の順序を返すので、
+/// 解けていく様子をマスごとに忠実に再生するアニメーションに使える
+/// 矛盾が見つかった場合も、それまでに確定したマスのタイムラインを返す
+///
+/// # Arguments
+/// * `rows` - 盤面の行数
+/// * `cols` - 盤面の列数
+/// * `row_rules_js` - 行ルールの配列
+/// * `col_rules_js` - 列ルールの配列
+/// * `initial_grid_js` - 現在の盤面
+#[wasm_bindgen]
+pub fn solve_with_timeline(
+    rows: usize,
+    cols: usize,
+    row_rules_js: JsValue,
+    col_rules_js: JsValue,
+    initial_grid_js: JsValue,
+) -> Result<JsValue, JsValue> {
+    let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+    let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+    let mut current_grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(initial_grid_js)?;
+
+    check_dimensions(rows, cols, &row_rules, &col_rules, &current_grid)
+        .map_err(|e| JsValue::from_str(&e))?;
+    let propagator = Propagator::new(rows, cols, &row_rules, &col_rules);
+    let original_grid = current_grid.clone();
+
+    let mut timeline = Vec::new();
+    let mut order = 0;
+    let outcome = propagator.propagate_with(&mut current_grid, &mut |event| {
+        order += 1;
+        let Some(after) = event.after else {
+            return;
+        };
+        for i in event.changed_cells() {
+            let (row, col) = match event.axis {
+                Axis::Row => (event.index, i),
+                Axis::Column => (i, event.index),
+            };
+            timeline.push(TimelineEntry {
+                row,
+                col,
+                state: after[i],
+                iteration: event.iteration,
+                axis: event.axis.key().to_string(),
+                line_index: event.index,
+                order,
+            });
+        }
+    });
+
+    let result = SolveResult::from_propagation(outcome, original_grid, current_grid);
+    Ok(serde_wasm_bindgen::to_value(&TimelineResult {
+        result,
+        timeline,
+    })?)
+}
+
+/// 波及グラフのノード（解析で確定した1マス）
+#[derive(Serialize, Deserialize)]
+pub struct CausalNode {
+    row: usize,        // 確定したマスの行番号
+    col: usize,        // 確定したマスの列番号
+    state: CellState,  // 確定した状態
+    iteration: usize,  // 何イテレーション目で確定したか（1始まり）
+    axis: String,      // 確定させたラインの向き（"row"または"column"）
+    line_index: usize, // 確定させたラインの番号（0始まり）
+}
+
+/// 波及グラフのエッジ（`from`のマスの確定が、`to`のマスの確定を引き起こした）
+#[derive(Serialize, Deserialize)]
+pub struct CausalEdge {
+    from: usize, // 原因となったノードの番号（`nodes`の添字）
+    to: usize,   // 結果として確定したノードの番号（`nodes`の添字）
+}
+
+/// 波及グラフ付きの解析結果をJavaScriptに返すためのデータ構造
+#[derive(Serialize, Deserialize)]
+pub struct CausalityResult {
+    result: SolveResult,    // `solve_puzzle`と同じ解析結果
+    nodes: Vec<CausalNode>, // 確定したマスの一覧（確定した順）
+    edges: Vec<CausalEdge>, // 確定の波及関係
+}
+
+/// `solve_puzzle`と同じ解析を行い、どのマスの確定が次にどのマスの確定を引き起こしたかを波及グラフとして返す関数
+/// 「この1マスが決まったから、こっちも決まった」という連鎖を可視化する学習支援に使う
+/// ライン解析でマスが確定したとき、そのラインを前回解析してから交差するラインの解析で確定した同じライン上のマスを、
+/// 新たに確定したマスの原因とみなしてエッジを張る
+/// 原因のないノードは、ルールと呼び出し時点の盤面だけから確定したマスである
+/// 呼び出し時点で確定済みのマスはノードに含めない
+/// 矛盾が見つかった場合も、それまでのグラフを返す
+///
+/// # Arguments
+/// * `rows` - 盤面の行数
+/// * `cols` - 盤面の列数
+/// * `row_rules_js` - 行ルールの配列
+/// * `col_rules_js` - 列ルールの配列
+/// * `initial_grid_js` - 現在の盤面
+#[wasm_bindgen]
+pub fn solve_with_causality(
+    rows: usize,
+    cols: usize,
+    row_rules_js: JsValue,
+    col_rules_js: JsValue,
+    initial_grid_js: JsValue,
+) -> Result<JsValue, JsValue> {
+    let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+    let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+    let mut current_grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(initial_grid_js)?;
+
+    check_dimensions(rows, cols, &row_rules, &col_rules, &current_grid)
+        .map_err(|e| JsValue::from_str(&e))?;
+    let propagator = Propagator::new(rows, cols, &row_rules, &col_rules);
+    let original_grid = current_grid.clone();
+
+    // ラインごとに、前回そのラインを解析してから確定した、そのライン上のマス（ノード番号）を溜めておく
+    let mut pending_rows: Vec<Vec<usize>> = vec![Vec::new(); rows];
+    let mut pending_cols: Vec<Vec<usize>> = vec![Vec::new(); cols];
+    let mut nodes = Vec::new();
+    let mut edges = Vec::new();
+    let outcome = propagator.propagate_with(&mut current_grid, &mut |event| {
+        let causes = std::mem::take(match event.axis {
+            Axis::Row => &mut pending_rows[event.index],
+            Axis::Column => &mut pending_cols[event.index],
+        });
+        let Some(after) = event.after else {
+            return;
+        };
+        for i in event.changed_cells() {
+            let (row, col) = match event.axis {
+                Axis::Row => (event.index, i),
+                Axis::Column => (i, event.index),
+            };
+            let node = nodes.len();
+            nodes.push(CausalNode {
+                row,
+                col,
+                state: after[i],
+                iteration: event.iteration,
+                axis: event.axis.key().to_string(),
+                line_index: event.index,
+            });
+            edges.extend(causes.iter().map(|&from| CausalEdge { from, to: node }));
+            // 確定したマスは、交差するラインの次の解析の原因になる
+            match event.axis {
+                Axis::Row => pending_cols[col].push(node),
+                Axis::Column => pending_rows[row].push(node),
+            }
+        }
+    });
+
+    let result = SolveResult::from_propagation(outcome, original_grid, current_grid);
+    Ok(serde_wasm_bindgen::to_value(&CausalityResult {
+        result,
+        nodes,
+        edges,
+    })?)
+}
+
+/// 解きやすさのヒートマップ付きの解析結果をJavaScriptに返すためのデータ構造
+#[derive(Serialize, Deserialize)]
+pub struct SolvabilityHeatmap {
+    result: SolveResult,              // `solve_puzzle`と同じ解析結果
+    heatmap: Vec<Vec<Option<usize>>>, // 各マスが確定したイテレーション（呼び出し時点で確定済みなら0、最後まで確定しなければ`null`）
+    max_iteration: usize, // ヒートマップ中の最大のイテレーション（色分けの正規化に使う）
+}
+
+/// 盤面の各マスが何イテレーション目で確定するかを、解きやすさのヒートマップとして返す関数
+/// 早く確定するマスは「簡単」、遅く確定するマスや最後まで確定しないマスは「難しい」と色分けでき、
+/// どこから手を付けるべきかの視覚的なガイドになる
+/// 矛盾が見つかった場合も、それまでに確定したマスのイテレーションを返す
+///
+/// # Arguments
+/// * `rows` - 盤面の行数
+/// * `cols` - 盤面の列数
+/// * `row_rules_js` - 行ルールの配列
+/// * `col_rules_js` - 列ルールの配列
+/// * `initial_grid_js` - 現在の盤面
+#[wasm_bindgen]
+pub fn solvability_heatmap(
+    rows: usize,
+    cols: usize,
+    row_rules_js: JsValue,
+    col_rules_js: JsValue,
+    initial_grid_js: JsValue,
+) -> Result<JsValue, JsValue> {
+    let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+    let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+    let mut current_grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(initial_grid_js)?;
+
+    check_dimensions(rows, cols, &row_rules, &col_rules, &current_grid)
+        .map_err(|e| JsValue::from_str(&e))?;
+    let propagator = Propagator::new(rows, cols, &row_rules, &col_rules);
+    let original_grid = current_grid.clone();
+
+    let mut heatmap: Vec<Vec<Option<usize>>> = original_grid
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|&cell| (cell != CellState::Empty).then_some(0))
+                .collect()
+        })
+        .collect();
+    let mut max_iteration = 0;
+    let outcome = propagator.propagate_with(&mut current_grid, &mut |event| {
+        for i in event.changed_cells() {
+            let (row, col) = match event.axis {
+                Axis::Row => (event.index, i),
+                Axis::Column => (i, event.index),
+            };
+            heatmap[row][col] = Some(event.iteration);
+            max_iteration = max_iteration.max(event.iteration);
+        }
+    });
+
+    let result = SolveResult::from_propagation(outcome, original_grid, current_grid);
+    Ok(serde_wasm_bindgen::to_value(&SolvabilityHeatmap {
+        result,
+        heatmap,
+        max_iteration,
+    })?)
+}
+
+/// 1イテレーション分の解析の進み具合
+#[derive(Serialize, Deserialize)]
+pub struct ProgressPoint {
+    iter: usize, // 何イテレーション目か（1始まり）
+    #[serde(rename = "total_filled")]
+    total_determined: usize, // このイテレーションまでに確定したマス（「塗り」と「×」の両方）の累計（呼び出し時点で確定済みのマスは含まない、JavaScriptでは`total_filled`）
+    delta: usize, // このイテレーションで新たに確定したマス（「塗り」と「×」の両方）の数
+}
+
+/// 進み具合の推移付きの解析結果をJavaScriptに返すためのデータ構造
+#[derive(Serialize, Deserialize)]
+pub struct ProgressCurveResult {
+    result: SolveResult,       // `solve_puzzle`と同じ解析結果
+    curve: Vec<ProgressPoint>, // イテレーションごとの進み具合（最後の要素は変化のなかったイテレーション）
+}
+
+/// `solve_puzzle`と同じ解析を行い、イテレーションごとに確定したマスの数を記録する関数
+/// どこで解析が失速したかのグラフ化や、パズルの解きごたえの定量化に使う
+///
+/// # Arguments
+/// * `rows` - 盤面の行数
+/// * `cols` - 盤面の列数
+/// * `row_rules_js` - 行ルールの配列
+/// * `col_rules_js` - 列ルールの配列
+/// * `initial_grid_js` - 現在の盤面
+#[wasm_bindgen]
+pub fn solve_with_progress_curve(
+    rows: usize,
+    cols: usize,
+    row_rules_js: JsValue,
+    col_rules_js: JsValue,
+    initial_grid_js: JsValue,
+) -> Result<JsValue, JsValue> {
+    let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+    let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+    let mut current_grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(initial_grid_js)?;
+
+    check_dimensions(rows, cols, &row_rules, &col_rules, &current_grid)
+        .map_err(|e| JsValue::from_str(&e))?;
+    let propagator = Propagator::new(rows, cols, &row_rules, &col_rules);
+    let original_grid = current_grid.clone();
+
+    // イテレーションごとの増分を数える（添字はイテレーション番号 - 1）
+    let mut deltas: Vec<usize> = Vec::new();
+    let outcome = propagator.propagate_with(&mut current_grid, &mut |event| {
+        if deltas.len() < event.iteration {
+            deltas.resize(event.iteration, 0);
+        }
+        deltas[event.iteration - 1] += event.changed_cells().len();
+    });
+    // 全ラインが確定済みで1本も解析しなかったイテレーションも、増分0として残す
+    if let Ok(iterations) = outcome {
+        deltas.resize(iterations.max(deltas.len()), 0);
+    }
+
+    let mut total_determined = 0;
+    let curve = deltas
+        .into_iter()
+        .enumerate()
+        .map(|(i, delta)| {
+            total_determined += delta;
+            ProgressPoint {
+                iter: i + 1,
+                total_determined,
+                delta,
+            }
+        })
+        .collect();
+    let result = SolveResult::from_propagation(outcome, original_grid, current_grid);
+    Ok(serde_wasm_bindgen::to_value(&ProgressCurveResult {
+        result,
+        curve,
+    })?)
+}
+
+/// ラインのマスを確定させた手法を表すenum
+/// 解説では、より単純な手法で説明できるものを優先する（`Overlap` → `Edge` → `Elimination`の順）
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Technique {
+    /// オーバーラップ法: 盤面の状態を見ずに、ルールだけから左寄せと右寄せの重なりで確定する
+    Overlap,
+    /// 端確定: 確定済みのマスを踏まえた左寄せと右寄せの配置を比べて確定する
+    Edge,
+    /// 消去法: 矛盾しない全ての配置を突き合わせて、初めて確定する
+    Elimination,
+}
+
+impl Technique {
+    /// 機械可読な出力に使う英語の名称を返す
+    fn key(self) -> &'static str {
+        match self {
+            Technique::Overlap => "overlap",
+            Technique::Edge => "edge",
+            Technique::Elimination => "elimination",
+        }
+    }
+
+    /// 解説文に埋め込むための日本語の名称を返す
+    fn label(self) -> &'static str {
+        match self {
+            Technique::Overlap => "オーバーラップ法",
+            Technique::Edge => "端確定",
+            Technique::Elimination => "消去法",
+        }
+    }
+}
+
+/// ライン1本の解析で新たに確定したマスを、それぞれ最も単純な手法に分類する
+fn classify_techniques(
+    rule: &CompiledRule,
+    before: &[CellState],
+    after: &[CellState],
+) -> Vec<(usize, Technique)> {
+    let overlap = rule.left_right(&vec![CellState::Empty; before.len()]);
+    let edge = rule.left_right(before);
+    (0..before.len())
+        .filter(|&i| before[i] != after[i])
+        .map(|i| {
+            let technique = if overlap[i] == Some(after[i]) {
+                Technique::Overlap
+            } else if edge[i] == Some(after[i]) {
+                Technique::Edge
+            } else {
+                Technique::Elimination
+            };
+            (i, technique)
+        })
+        .collect()
+}
+
+/// ライン1本で、1つの手法によって確定したマスの記録
+#[derive(Serialize, Deserialize)]
+pub struct TechniqueStep {
+    iteration: usize,    // 何イテレーション目の解析か（1始まり）
+    axis: String,        // ラインの向き（"row" または "column"）
+    index: usize,        // ラインの番号（0始まり）
+    technique: String,   // 手法の名前（"overlap"、"edge"、"elimination"）
+    cells: Vec<usize>,   // この手法で確定したマスの、ライン上の位置
+    description: String, // 「行 3: オーバーラップ法で2マス確定」のような解説文
+}
+
+/// 手法付きの解析結果をJavaScriptに返すためのデータ構造
+#[derive(Serialize, Deserialize)]
+pub struct TechniqueResult {
+    result: SolveResult,       // `solve_puzzle`と同じ解析結果
+    steps: Vec<TechniqueStep>, // 確定の経過（解析順、同じラインの中では手法の優先順）
+}
+
+/// `solve_puzzle`と同じ解析を行い、各ラインのマスがどの手法で確定したかを記録する関数
+/// ノノグラムの解き方を解説するチュートリアルなどに使う
+///
+/// # Arguments
+/// * `rows` - 盤面の行数
+/// * `cols` - 盤面の列数
+/// * `row_rules_js` - 行ルールの配列
+/// * `col_rules_js` - 列ルールの配列
+/// * `initial_grid_js` - 現在の盤面
+#[wasm_bindgen]
+pub fn solve_with_techniques(
+    rows: usize,
+    cols: usize,
+    row_rules_js: JsValue,
+    col_rules_js: JsValue,
+    initial_grid_js: JsValue,
+) -> Result<JsValue, JsValue> {
+    let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+    let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+    let mut current_grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(initial_grid_js)?;
+
+    check_dimensions(rows, cols, &row_rules, &col_rules, &current_grid)
+        .map_err(|e| JsValue::from_str(&e))?;
+    let propagator = Propagator::new(rows, cols, &row_rules, &col_rules);
+    let original_grid = current_grid.clone();
+
+    let mut steps = Vec::new();
+    let outcome = propagator.propagate_with(&mut current_grid, &mut |event| {
+        let Some(after) = event.after else {
+            return;
+        };
+        let rule = match event.axis {
+            Axis::Row => &propagator.row_rules[event.index],
+            Axis::Column => &propagator.col_rules[event.index],
+        };
+        let classified = classify_techniques(rule, event.before, after);
+        for technique in [Technique::Overlap, Technique::Edge, Technique::Elimination] {
+            let cells: Vec<usize> = classified
+                .iter()
+                .filter(|&&(_, t)| t == technique)
+                .map(|&(i, _)| i)
+                .collect();
+            if cells.is_empty() {
+                continue;
+            }
+            steps.push(TechniqueStep {
+                iteration: event.iteration,
+                axis: event.axis.key().to_string(),
+                index: event.index,
+                technique: technique.key().to_string(),
+                description: format!(
+                    "{} {}: {}で{}マス確定",
+                    event.axis.label(),
+                    event.index + 1,
+                    technique.label(),
+                    cells.len()
+                ),
+                cells,
+            });
+        }
+    });
+
+    let result = SolveResult::from_propagation(outcome, original_grid, current_grid);
+    Ok(serde_wasm_bindgen::to_value(&TechniqueResult {
+        result,
+        steps,
+    })?)
+}
+
+/// 対角線1本に含まれるマスの座標を、上の行から順に返すヘルパー関数
+/// 対角線は`0..rows + cols - 1`の番号で区別し、左下の角から右上の角へ（逆対角線は左上の角から右下の角へ）順に番号を振る
+///
+/// # Arguments
+/// * `rows` - 盤面の行数
+/// * `cols` - 盤面の列数
+/// * `index` - 対角線の番号
+/// * `anti` - `false`なら左上→右下方向の対角線、`true`なら右上→左下方向の対角線
+fn diagonal_cells(rows: usize, cols: usize, index: usize, anti: bool) -> Vec<(usize, usize)> {
+    (0..rows)
+        .filter_map(|r| {
+            let c = if anti {
+                // 逆対角線は r + c = index を満たすマス
+                index.checked_sub(r)?
+            } else {
+                // 対角線は c - r = index - (rows - 1) を満たすマス
+                (index + r).checked_sub(rows - 1)?
+            };
+            (c < cols).then_some((r, c))
+        })
+        .collect()
+}
+
+/// `rows`×`cols`の盤面にある、1方向の対角線の本数（マスのない盤面では0本）
+fn diagonal_count(rows: usize, cols: usize) -> usize {
+    if rows == 0 || cols == 0 {
+        0
+    } else {
+        rows + cols - 1
+    }
+}
+
+/// 行・列のルールに加えて、対角線方向のルールも使って盤面を解析する関数
+/// 対角線も1本のラインとして扱い、行・列の伝播が収束するたびに対角線の解析を挟む
+///
+/// # Arguments
+/// * `rows` - 盤面の行数
+/// * `cols` - 盤面の列数
+/// * `row_rules_js` - 行ルールの配列
+/// * `col_rules_js` - 列ルールの配列
+/// * `diag_rules_js` - 左上→右下方向の対角線ルール（`rows + cols - 1`本、ヒントのない対角線は`null`）
+/// * `anti_diag_rules_js` - 右上→左下方向の対角線ルール（同上）
+/// * `initial_grid_js` - 現在の盤面
+///
+/// # Returns
+/// * `Ok(JsValue)` - `solve_puzzle`と同じ形式の解析結果
+/// * `Err(JsValue)` - 入力の形式が不正な場合や、対角線ルールの本数が対角線の本数と一致しない場合のエラー
+#[wasm_bindgen]
+pub fn solve_puzzle_with_diagonals(
+    rows: usize,
+    cols: usize,
+    row_rules_js: JsValue,
+    col_rules_js: JsValue,
+    diag_rules_js: JsValue,
+    anti_diag_rules_js: JsValue,
+    initial_grid_js: JsValue,
+) -> Result<JsValue, JsValue> {
+    let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+    let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+    let diag_rules: Vec<Option<Vec<usize>>> = serde_wasm_bindgen::from_value(diag_rules_js)?;
+    let anti_diag_rules: Vec<Option<Vec<usize>>> =
+        serde_wasm_bindgen::from_value(anti_diag_rules_js)?;
+    let mut current_grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(initial_grid_js)?;
+
+    check_dimensions(rows, cols, &row_rules, &col_rules, &current_grid)
+        .map_err(|e| JsValue::from_str(&e))?;
+    let count = diagonal_count(rows, cols);
+    for (name, rules) in [("対角線", &diag_rules), ("逆対角線", &anti_diag_rules)] {
+        if rules.len() != count {
+            return Err(JsValue::from_str(&format!(
+                "{}ルールの数（{}）が{}の本数（{}）と一致しません",
+                name,
+                rules.len(),
+                name,
+                count
+            )));
+        }
+    }
+    let propagator = Propagator::new(rows, cols, &row_rules, &col_rules);
+    let original_grid = current_grid.clone();
+
+    // ヒントのある対角線だけを、マスの座標とコンパイル済みルールの組にしておく
+    let mut diagonals = Vec::new();
+    for (anti, rules) in [(false, &diag_rules), (true, &anti_diag_rules)] {
+        for (index, rule) in rules.iter().enumerate() {
+            if let Some(rule) = rule {
+                let cells = diagonal_cells(rows, cols, index, anti);
+                let compiled = compile_rule(cells.len(), rule);
+                diagonals.push((anti, index, cells, compiled));
+            }
+        }
+    }
+
+    loop {
+        // 1. 行・列のルールで確定できるところまで進める
+        let outcome = propagator.propagate(&mut current_grid);
+        if outcome.is_err() {
+            let result = SolveResult::from_propagation(outcome, original_grid, current_grid);
+            return Ok(serde_wasm_bindgen::to_value(&result)?);
+        }
+
+        // 2. 対角線を1本ずつ取り出して解析し、確定したマスを盤面に書き戻す
+        let mut changed = false;
+        for (anti, index, cells, compiled) in &diagonals {
+            let line: Vec<CellState> = cells.iter().map(|&(r, c)| current_grid[r][c]).collect();
+            let new_line = match compiled.apply(&line) {
+                Ok(new_line) => new_line,
+                Err(e) => {
+                    let name = if *anti { "逆対角線" } else { "対角線" };
+                    let result = SolveResult {
+                        grid: original_grid,
+                        message: format!("{} {}: {}", name, index + 1, e),
+                        error: true,
+                        metrics: None,
+                    };
+                    return Ok(serde_wasm_bindgen::to_value(&result)?);
+                }
+            };
+            for (&(r, c), state) in cells.iter().zip(new_line) {
+                if current_grid[r][c] != state {
+                    current_grid[r][c] = state;
+                    changed = true;
+                }
+            }
+        }
+
+        // 対角線の解析で何も確定しなければ、盤面は収束している
+        if !changed {
+            let result = SolveResult::from_propagation(Ok(0), original_grid, current_grid);
+            return Ok(serde_wasm_bindgen::to_value(&result)?);
+        }
+    }
+}
+
+/// 盤面上の矩形領域を表すデータ構造
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Region {
+    top: usize,    // 領域の上端の行
+    left: usize,   // 領域の左端の列
+    height: usize, // 領域の行数
+    width: usize,  // 領域の列数
+}
+
+impl Region {
+    /// 領域が`rows`×`cols`の盤面に収まるかを判定する
+    /// JavaScriptから渡された値で端の位置の計算があふれる場合も、はみ出しとして扱う
+    fn fits(&self, rows: usize, cols: usize) -> bool {
+        let bottom = self.top.checked_add(self.height);
+        let right = self.left.checked_add(self.width);
+        bottom.is_some_and(|bottom| bottom <= rows) && right.is_some_and(|right| right <= cols)
+    }
+}
+
+/// 指定した矩形領域のマスを解析対象から外して（常に「×」として扱って）盤面を解析する関数
+/// 除外したマスはライン上の「壁」になるため、特定の領域を固定したまま残りを解く実験や、
+/// 変則形状のパズルの土台として使える
+///
+/// # Arguments
+/// * `rows` - 盤面の行数
+/// * `cols` - 盤面の列数
+/// * `row_rules_js` - 行ルールの配列
+/// * `col_rules_js` - 列ルールの配列
+/// * `initial_grid_js` - 現在の盤面
+/// * `excluded_region_js` - 除外する矩形領域（`{ top, left, height, width }`）
+///
+/// # Returns
+/// * `Ok(JsValue)` - `solve_puzzle`と同じ形式の解析結果（除外したマスは「×」になる）
+/// * `Err(JsValue)` - 入力の形式が不正な場合や、領域が盤面からはみ出している場合のエラー
+#[wasm_bindgen]
+pub fn solve_puzzle_with_exclusion(
+    rows: usize,
+    cols: usize,
+    row_rules_js: JsValue,
+    col_rules_js: JsValue,
+    initial_grid_js: JsValue,
+    excluded_region_js: JsValue,
+) -> Result<JsValue, JsValue> {
+    let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+    let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+    let mut current_grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(initial_grid_js)?;
+    let region: Region = serde_wasm_bindgen::from_value(excluded_region_js)?;
+
+    check_dimensions(rows, cols, &row_rules, &col_rules, &current_grid)
+        .map_err(|e| JsValue::from_str(&e))?;
+    if !region.fits(rows, cols) {
+        return Err(JsValue::from_str("除外する領域が盤面からはみ出しています"));
+    }
+
+    // 除外領域のマスは、ユーザーの入力に関わらず「×」で固定する
+    for row in current_grid.iter_mut().skip(region.top).take(region.height) {
+        for cell in row.iter_mut().skip(region.left).take(region.width) {
+            *cell = CellState::Crossed;
+        }
+    }
+
+    let propagator = Propagator::new(rows, cols, &row_rules, &col_rules);
+    let original_grid = current_grid.clone();
+    let outcome = propagator.propagate(&mut current_grid);
+    let result = SolveResult::from_propagation(outcome, original_grid, current_grid);
+    Ok(serde_wasm_bindgen::to_value(&result)?)
+}
+
+// --- 停滞の説明 ---
+
+/// 未確定のマスを含むライン1本についての説明
+#[derive(Serialize, Deserialize)]
+pub struct StuckLine {
+    axis: String,                // ラインの向き（"row" または "column"）
+    index: usize,                // ラインの番号（0始まり）
+    candidate_count: usize,      // 現在の盤面と矛盾しない配置パターンの数
+    ambiguous_cells: Vec<usize>, // このラインだけでは確定できないマスの位置（0始まり）
+    reason: String,              // 確定できない理由の説明
+}
+
+/// 盤面の各ラインについて、未確定のマスが残っている理由を分析する関数
+/// 解析が停滞したときに、各ラインの候補数と曖昧なマスを示し、次の一手を考える手がかりにする
+///
+/// # Arguments
+/// * `rows` - 盤面の行数
+/// * `cols` - 盤面の列数
+/// * `row_rules_js` - 行ルールの配列
+/// * `col_rules_js` - 列ルールの配列
+/// * `grid_js` - 現在の盤面
+///
+/// # Returns
+/// * `Ok(JsValue)` - 未確定のマスを含むラインごとの`StuckLine`の配列（行→列の順）
+/// * `Err(JsValue)` - 入力の形式が不正な場合のエラー
+#[wasm_bindgen]
+pub fn explain_stuck(
+    rows: usize,
+    cols: usize,
+    row_rules_js: JsValue,
+    col_rules_js: JsValue,
+    grid_js: JsValue,
+) -> Result<JsValue, JsValue> {
+    let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+    let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+    let grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(grid_js)?;
+    check_dimensions(rows, cols, &row_rules, &col_rules, &grid)
+        .map_err(|e| JsValue::from_str(&e))?;
+    let propagator = Propagator::new(rows, cols, &row_rules, &col_rules);
+
+    let mut lines = Vec::new();
+    for (axis, rules, crossing) in [
+        (Axis::Row, &propagator.row_rules, Axis::Column),
+        (Axis::Column, &propagator.col_rules, Axis::Row),
+    ] {
+        for (index, rule) in rules.iter().enumerate() {
+            let line = get_line(&grid, axis, index);
+            if !line.contains(&CellState::Empty) {
+                continue;
+            }
+            let candidate_count = rule.count_consistent(&line);
+            let (ambiguous_cells, reason) = match rule.apply(&line) {
+                Err(message) => (Vec::new(), message),
+                Ok(solved) => {
+                    let ambiguous: Vec<usize> = solved
+                        .iter()
+                        .enumerate()
+                        .filter(|&(_, &cell)| cell == CellState::Empty)
+                        .map(|(i, _)| i)
+                        .collect();
+                    // 解析で1マスでも確定したなら、このラインはまだ停滞していない
+                    let reason = if solved != line {
+                        "このラインだけで確定できるマスがあります".to_string()
+                    } else {
+                        format!(
+                            "候補が{}通りあるため確定できません（交差する{}の情報待ち）",
+                            candidate_count,
+                            crossing.label()
+                        )
+                    };
+                    (ambiguous, reason)
+                }
+            };
+            lines.push(StuckLine {
+                axis: axis.key().to_string(),
+                index,
+                candidate_count,
+                ambiguous_cells,
+                reason,
+            });
+        }
+    }
+    Ok(serde_wasm_bindgen::to_value(&lines)?)
+}
+
+// --- 矛盾の修正提案 ---
+
+/// `suggest_fix`で1本のラインについて返す修正候補の最大数
+const MAX_FIX_CANDIDATES: usize = 5;
+
+impl CompiledRule {
+    /// 確定済みのマスをできるだけ少なく書き換えてルールに合わせる方法を探す
+    ///
+    /// # Returns
+    /// * `(usize, Vec<Vec<usize>>)` - 最小の書き換え数と、その数で済む書き換え位置の組（最大`limit`通り）
+    ///   ルールがラインに収まらず、どう書き換えても合わない場合は書き換え位置の組が空になる
+    fn nearest_fixes(&self, user_line: &[CellState], limit: usize) -> (usize, Vec<Vec<usize>>) {
+        let (filled, crossed) = self.masks(user_line);
+        let mut best = usize::MAX;
+        let mut fixes: Vec<Vec<usize>> = Vec::new();
+        for p in self.patterns.chunks(self.words) {
+            // パターンと食い違う確定済みのマス（「塗り」なのにパターンでは0、「×」なのにパターンでは1）
+            let mismatch: Vec<u64> = (0..self.words)
+                .map(|w| (filled[w] & !p[w]) | (crossed[w] & p[w]))
+                .collect();
+            let count = mismatch.iter().map(|bits| bits.count_ones() as usize).sum();
+            if count > best {
+                continue;
+            }
+            if count < best {
+                best = count;
+                fixes.clear();
+            }
+            let cells: Vec<usize> = (0..self.size)
+                .filter(|&i| mismatch[i / 64] >> (i % 64) & 1 == 1)
+                .collect();
+            if fixes.len() < limit && !fixes.contains(&cells) {
+                fixes.push(cells);
+            }
+        }
+        (best, fixes)
+    }
+}
+
+/// 矛盾しているライン1本についての修正候補
+#[derive(Serialize, Deserialize)]
+pub struct LineFix {
+    axis: String,                     // ラインの向き（"row" または "column"）
+    index: usize,                     // ラインの番号（0始まり）
+    min_changes: Option<usize>, // 矛盾を解消するのに必要な、書き換えるマスの最小数（書き換えでは解消できなければ`null`）
+    candidates: Vec<Vec<CellPatch>>, // 最小の書き換えで矛盾が解消する修正案（`apply_patch`にそのまま渡せる）
+    suspicious_cells: Vec<CellPatch>, // いずれかの修正案で書き換えられるマス（書き換え後の状態付き）
+}
+
+/// 盤面とルールが矛盾しているときに、どのマスを書き換えれば矛盾が解消するかを提案する関数
+/// 盤面全体での最小修正は求めず、矛盾しているラインごとに、確定済みのマスの書き換えが最も少なく済む案を返す
+///
+/// # Arguments
+/// * `rows` - 盤面の行数
+/// * `cols` - 盤面の列数
+/// * `row_rules_js` - 行ルールの配列
+/// * `col_rules_js` - 列ルールの配列
+/// * `grid_js` - 現在の盤面
+///
+/// # Returns
+/// * `Ok(JsValue)` - 矛盾しているラインごとの`LineFix`の配列（矛盾がなければ空の配列）
+/// * `Err(JsValue)` - 入力の形式が不正な場合のエラー
+#[wasm_bindgen]
+pub fn suggest_fix(
+    rows: usize,
+    cols: usize,
+    row_rules_js: JsValue,
+    col_rules_js: JsValue,
+    grid_js: JsValue,
+) -> Result<JsValue, JsValue> {
+    let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+    let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+    let grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(grid_js)?;
+
+    check_dimensions(rows, cols, &row_rules, &col_rules, &grid)
+        .map_err(|e| JsValue::from_str(&e))?;
+    let propagator = Propagator::new(rows, cols, &row_rules, &col_rules);
+
+    let mut fixes = Vec::new();
+    for (axis, rules) in [
+        (Axis::Row, &propagator.row_rules),
+        (Axis::Column, &propagator.col_rules),
+    ] {
+        for (index, rule) in rules.iter().enumerate() {
+            let line = get_line(&grid, axis, index);
+            if rule.apply(&line).is_ok() {
+                continue;
+            }
+            let (min_changes, positions) = rule.nearest_fixes(&line, MAX_FIX_CANDIDATES);
+
+            // ライン上の位置を盤面の座標に直し、書き換え後の状態（塗り⇔×）を付ける
+            let to_patch = |i: usize| {
+                let (row, col) = match axis {
+                    Axis::Row => (index, i),
+                    Axis::Column => (i, index),
+                };
+                let state = if line[i] == CellState::Filled {
+                    CellState::Crossed
+                } else {
+                    CellState::Filled
+                };
+                CellPatch { row, col, state }
+            };
+            let mut suspicious: Vec<usize> = positions.iter().flatten().copied().collect();
+            suspicious.sort_unstable();
+            suspicious.dedup();
+
+            fixes.push(LineFix {
+                axis: axis.key().to_string(),
+                index,
+                min_changes: (!positions.is_empty()).then_some(min_changes),
+                candidates: positions
+                    .iter()
+                    .map(|cells| cells.iter().map(|&i| to_patch(i)).collect())
+                    .collect(),
+                suspicious_cells: suspicious.into_iter().map(to_patch).collect(),
+            });
+        }
+    }
+    Ok(serde_wasm_bindgen::to_value(&fixes)?)
+}
+
+impl Propagator {
+    /// 確定済みのマスを`Empty`に戻して、伝播で矛盾が見つからない盤面にする
+    /// 伝播は単調なので、戻すマスを増やすほど矛盾は起きにくくなる。これを利用して、候補の先頭から何個戻せば
+    /// 矛盾が消えるかを二分探索で求め、その後で戻さなくても済むマスを元の状態に復元する
+    ///
+    /// # Arguments
+    /// * `grid` - 矛盾している盤面
+    /// * `candidates` - `Empty`に戻してよいマスの座標（優先して戻したい順）
+    ///
+    /// # Returns
+    /// * `Some(Vec<(usize, usize)>)` - 戻したマスの座標（`candidates`での順）。どれを復元しても再び矛盾する最小の組になっている
+    /// * `None` - 候補を全て戻しても矛盾が消えない場合
+    fn resolve_conflict(
+        &self,
+        grid: &[Vec<CellState>],
+        candidates: &[(usize, usize)],
+    ) -> Option<Vec<(usize, usize)>> {
+        let consistent_without = |cleared: &[(usize, usize)]| {
+            let mut trial = grid.to_vec();
+            for &(row, col) in cleared {
+                trial[row][col] = CellState::Empty;
+            }
+            self.propagate_to_fixpoint(&mut trial).is_ok()
+        };
+        if !consistent_without(candidates) {
+            return None;
+        }
+
+        // 先頭から`len`個を戻せば矛盾が消える、最小の`len`を求める
+        let (mut low, mut high) = (0, candidates.len());
+        while low < high {
+            let mid = (low + high) / 2;
+            if consistent_without(&candidates[..mid]) {
+                high = mid;
+            } else {
+                low = mid + 1;
+            }
+        }
+        let mut cleared = candidates[..low].to_vec();
+
+        // 最後の1個は必ず必要なので、それより前のマスを優先度の低い順に復元してみる
+        for i in (0..low.saturating_sub(1)).rev() {
+            let without: Vec<(usize, usize)> = cleared
+                .iter()
+                .filter(|&&cell| cell != candidates[i])
+                .copied()
+                .collect();
+            if consistent_without(&without) {
+                cleared = without;
+            }
+        }
+        Some(cleared)
+    }
+}
+
+/// `auto_resolve_conflict`の結果
+#[derive(Serialize, Deserialize)]
+pub struct AutoResolveResult {
+    grid: Vec<Vec<CellState>>, // 矛盾を解消した盤面
+    cleared: Vec<CellPatch>,   // `Empty`に戻したマスと、戻す前の状態（最近編集した順）
+}
+
+/// 盤面とルールが矛盾しているときに、矛盾の原因になっている確定済みのマスを自動で`Empty`に戻す関数
+/// 最近編集したマスほど優先して戻し、どれを復元しても再び矛盾するところまで戻すマスを絞り込む
+/// 矛盾の判定には伝播を使うため、伝播で見つからない矛盾（解がないだけの盤面）はそのまま残る
+///
+/// # Arguments
+/// * `rows` - 盤面の行数
+/// * `cols` - 盤面の列数
+/// * `row_rules_js` - 行ルールの配列
+/// * `col_rules_js` - 列ルールの配列
+/// * `grid_js` - 現在の盤面
+/// * `recent_edits_js` - 編集したマスの`[行, 列]`の配列（古い順）。省略時は全ての確定済みのマスを同じ優先度で扱う
+///
+/// # Returns
+/// * `Ok(JsValue)` - `AutoResolveResult`（矛盾がなければ盤面はそのままで、`cleared`は空になる）
+/// * `Err(JsValue)` - 入力の形式が不正な場合や、ルール自体が矛盾していて盤面の書き換えでは解消できない場合のエラー
+#[wasm_bindgen]
+pub fn auto_resolve_conflict(
+    rows: usize,
+    cols: usize,
+    row_rules_js: JsValue,
+    col_rules_js: JsValue,
+    grid_js: JsValue,
+    recent_edits_js: JsValue,
+) -> Result<JsValue, JsValue> {
+    let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+    let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+    let mut grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(grid_js)?;
+    let recent_edits: Vec<(usize, usize)> =
+        if recent_edits_js.is_undefined() || recent_edits_js.is_null() {
+            Vec::new()
+        } else {
+            serde_wasm_bindgen::from_value(recent_edits_js)?
+        };
+
+    check_dimensions(rows, cols, &row_rules, &col_rules, &grid)
+        .map_err(|e| JsValue::from_str(&e))?;
+    if let Some(&(row, col)) = recent_edits
+        .iter()
+        .find(|&&(row, col)| row >= rows || col >= cols)
+    {
+        return Err(JsValue::from_str(&format!(
+            "編集したマス({}, {})が盤面の範囲外です",
+            row, col
+        )));
+    }
+    let propagator = Propagator::new(rows, cols, &row_rules, &col_rules);
+
+    // 最近編集したマスを新しい順に並べ、残りの確定済みのマスをその後ろに続ける
+    let all_cells = (0..rows).flat_map(|row| (0..cols).map(move |col| (row, col)));
+    let mut seen = vec![vec![false; cols]; rows];
+    let mut candidates: Vec<(usize, usize)> = Vec::new();
+    for (row, col) in recent_edits.iter().rev().copied().chain(all_cells) {
+        if grid[row][col] != CellState::Empty && !seen[row][col] {
+            seen[row][col] = true;
+            candidates.push((row, col));
+        }
+    }
+
+    let cleared = propagator
+        .resolve_conflict(&grid, &candidates)
+        .ok_or_else(|| {
+            JsValue::from_str("ルール自体が矛盾しているため、盤面の書き換えでは解消できません")
+        })?;
+    let cleared: Vec<CellPatch> = cleared
+        .into_iter()
+        .map(|(row, col)| {
+            let state = std::mem::replace(&mut grid[row][col], CellState::Empty);
+            CellPatch { row, col, state }
+        })
+        .collect();
+    Ok(serde_wasm_bindgen::to_value(&AutoResolveResult {
+        grid,
+        cleared,
+    })?)
+}
+
+// --- ブロックの対応づけ ---
+
+impl CompiledRule {
+    /// ラインの各「塗り」のマスが、ルールの何番目のブロックに属するかを求める
+    /// 現在のラインと矛盾しない全ての配置パターンで同じブロックに属する場合にだけ、そのブロックの番号（0始まり）を返す
+    ///
+    /// # Returns
+    /// * `Some(Vec<Option<usize>>)` - マスごとのブロック番号（「塗り」でないマスや、パターンによってブロックが変わるマスは`None`）
+    /// * `None` - 矛盾しない配置パターンが1つもない場合
+    fn block_indices(&self, user_line: &[CellState]) -> Option<Vec<Option<usize>>> {
+        let mut patterns = self.consistent_patterns(user_line);
+        let first = self.region_codes(patterns.next()?);
+        // パターンごとの番号が全て一致するマスだけを残す（一致しなくなったマスは`None`にする）
+        let mut codes: Vec<Option<usize>> = first.into_iter().map(Some).collect();
+        for pattern in patterns {
+            for (code, other) in codes.iter_mut().zip(self.region_codes(pattern)) {
+                if *code != Some(other) {
+                    *code = None;
+                }
+            }
+        }
+        Some(
+            codes
+                .into_iter()
+                .zip(user_line)
+                .map(|(code, &cell)| match (code, cell) {
+                    (Some(code), CellState::Filled) => Some(code / 2),
+                    _ => None,
+                })
+                .collect(),
+        )
+    }
+}
+
+/// 各マスのブロック番号をJavaScriptに返すためのデータ構造
+/// どちらも盤面と同じ形（行数×列数）で、ブロックを特定できないマスは`null`になる
+#[derive(Serialize, Deserialize)]
+pub struct BlockAssignment {
+    rows: Vec<Vec<Option<usize>>>, // 各マスが属する、その行のルールのブロック番号（0始まり）
+    cols: Vec<Vec<Option<usize>>>, // 各マスが属する、その列のルールのブロック番号（0始まり）
+}
+
+/// 盤面の各「塗り」のマスが、ルールのどのブロック（ヒントのどの数字）に属するかを求める関数
+/// ヒントの数字とマスの対応を色分けして表示する解説やデバッグに使う
+/// 完成盤面では全ての「塗り」のマスのブロックが決まる
+/// 未完成の盤面では、ラインの現在の状態と矛盾しない全ての配置で同じブロックになるマスだけを対応づけ、
+/// 配置によってブロックが変わる曖昧なマスは`null`にする
+///
+/// # Arguments
+/// * `rows` - 盤面の行数
+/// * `cols` - 盤面の列数
+/// * `row_rules_js` - 行ルールの配列
+/// * `col_rules_js` - 列ルールの配列
+/// * `grid_js` - 盤面の状態
+///
+/// # Returns
+/// * `Ok(JsValue)` - 行・列それぞれのブロック番号の盤面（`BlockAssignment`）
+/// * `Err(JsValue)` - 入力が不正な場合、またはルールと矛盾するラインがある場合のエラー
+#[wasm_bindgen]
+pub fn block_assignment(
+    rows: usize,
+    cols: usize,
+    row_rules_js: JsValue,
+    col_rules_js: JsValue,
+    grid_js: JsValue,
+) -> Result<JsValue, JsValue> {
+    let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+    let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+    let grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(grid_js)?;
+    check_dimensions(rows, cols, &row_rules, &col_rules, &grid)
+        .map_err(|e| JsValue::from_str(&e))?;
+    let propagator = Propagator::new(rows, cols, &row_rules, &col_rules);
+
+    // 向きごとに、ラインのブロック番号を求める
+    let assign = |axis: Axis, rules: &[CompiledRule]| {
+        rules
+            .iter()
+            .enumerate()
+            .map(|(index, rule)| {
+                rule.block_indices(&get_line(&grid, axis, index))
+                    .ok_or_else(|| {
+                        JsValue::from_str(&format!(
+                            "{} {}: ルールと矛盾しています",
+                            axis.label(),
+                            index + 1
+                        ))
+                    })
+            })
+            .collect::<Result<Vec<_>, JsValue>>()
+    };
+    let row_blocks = assign(Axis::Row, &propagator.row_rules)?;
+    // 列ごとに求めた番号を、盤面と同じ向きに並べ直す
+    let col_blocks = assign(Axis::Column, &propagator.col_rules)?;
+    let cols_by_row = (0..rows)
+        .map(|r| col_blocks.iter().map(|col| col[r]).collect())
+        .collect();
+
+    let result = BlockAssignment {
+        rows: row_blocks,
+        cols: cols_by_row,
+    };
+    Ok(serde_wasm_bindgen::to_value(&result)?)
+}
+
+/// ブロックが取りうる位置の範囲をJavaScriptに返すためのデータ構造
+#[derive(Serialize, Deserialize)]
+pub struct BlockRange {
+    leftmost_start: usize, // 全てのブロックを左に詰めたときの開始位置（0始まり）
+    rightmost_end: usize,  // 全てのブロックを右に詰めたときの終了位置（最後のマスの次の位置）
+}
+
+/// ルールの各ブロックが取りうる位置の範囲を求める関数
+/// ブロックは必ず`leftmost_start`から`rightmost_end`の手前までのどこかに置かれ、範囲の外には来ない
+/// 重なり法の基礎となる情報で、UIで「このブロックはこの範囲のどこか」を可視化するのに使う
+/// 候補の配置パターンを生成しないので、ラインの長さに対して線形時間で計算できる
+///
+/// # Arguments
+/// * `size` - ラインの長さ
+/// * `rule_js` - ルール（ブロック長の配列）
+///
+/// # Returns
+/// * `Ok(JsValue)` - ブロックごとの範囲（`BlockRange`）の配列（0を除いたブロックの順）
+/// * `Err(JsValue)` - 入力が不正な場合、またはルールがラインに収まらない場合のエラー
+#[wasm_bindgen]
+pub fn block_ranges(size: usize, rule_js: JsValue) -> Result<JsValue, JsValue> {
+    let rule: Vec<usize> = serde_wasm_bindgen::from_value(rule_js)?;
+    let rule = ValidatedRule::new(&rule, size).map_err(|e| JsValue::from_str(&e))?;
+    let ranges: Vec<BlockRange> = rule
+        .block_ranges()
+        .into_iter()
+        .map(|(leftmost_start, rightmost_end)| BlockRange {
+            leftmost_start,
+            rightmost_end,
+        })
+        .collect();
+    Ok(serde_wasm_bindgen::to_value(&ranges)?)
+}
+
+// --- マスの候補 ---
+
+impl CompiledRule {
+    /// 現在のラインの状態と矛盾しない配置パターンの和集合から、各マスが取りうる状態を求める
+    ///
+    /// # Returns
+    /// * マスごとの`(「塗り」になりうるか, 「×」になりうるか)`（矛盾しないパターンがなければ全て`(false, false)`）
+    fn cell_options(&self, user_line: &[CellState]) -> Vec<(bool, bool)> {
+        let mut any_filled = vec![0u64; self.words];
+        let mut all_filled = vec![u64::MAX; self.words];
+        let mut found = false;
+        for pattern in self.consistent_patterns(user_line) {
+            found = true;
+            for ((any, all), &bits) in any_filled.iter_mut().zip(&mut all_filled).zip(pattern) {
+                *any |= bits;
+                *all &= bits;
+            }
+        }
+        (0..self.size)
+            .map(|i| {
+                let (word, bit) = (i / 64, i % 64);
+                let can_fill = any_filled[word] >> bit & 1 == 1;
+                let can_cross = found && all_filled[word] >> bit & 1 == 0;
+                (can_fill, can_cross)
+            })
+            .collect()
+    }
+}
+
+/// 1マスが取りうる状態
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CellCandidate {
+    /// 「塗り」に確定している
+    Filled,
+    /// 「×」に確定している
+    Crossed,
+    /// 「塗り」と「×」のどちらにもなりうる
+    Either,
+    /// 行と列のどちらかのルールと矛盾していて、どちらの状態にもなれない
+    Conflict,
+}
+
+/// 盤面の各マスが取りうる状態を求める関数（鉛筆書きのメモのような表示に使う）
+/// 各マスについて、その行と列それぞれで現在の状態と矛盾しない配置パターンの和集合を取り、
+/// 両方で許される状態だけを候補として残す
+/// 盤面全体の解析（ライン伝播）は行わず、現在の盤面の各ラインを1回ずつ調べた結果を返す
+/// 既に「塗り」や「×」になっているマスも、ルールと矛盾していなければその状態が唯一の候補になる
+///
+/// # Arguments
+/// * `rows` - 盤面の行数
+/// * `cols` - 盤面の列数
+/// * `row_rules_js` - 行ルールの配列
+/// * `col_rules_js` - 列ルールの配列
+/// * `grid_js` - 盤面の状態
+///
+/// # Returns
+/// * `Ok(JsValue)` - 盤面と同じ形の、各マスの候補（"filled"、"crossed"、"either"、"conflict"）
+/// * `Err(JsValue)` - 入力が不正な場合のエラー
+#[wasm_bindgen]
+pub fn cell_candidates(
+    rows: usize,
+    cols: usize,
+    row_rules_js: JsValue,
+    col_rules_js: JsValue,
+    grid_js: JsValue,
+) -> Result<JsValue, JsValue> {
+    let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+    let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+    let grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(grid_js)?;
+    check_dimensions(rows, cols, &row_rules, &col_rules, &grid)
+        .map_err(|e| JsValue::from_str(&e))?;
+    let propagator = Propagator::new(rows, cols, &row_rules, &col_rules);
+
+    let row_options: Vec<Vec<(bool, bool)>> = propagator
+        .row_rules
+        .iter()
+        .enumerate()
+        .map(|(r, rule)| rule.cell_options(&get_line(&grid, Axis::Row, r)))
+        .collect();
+    let col_options: Vec<Vec<(bool, bool)>> = propagator
+        .col_rules
+        .iter()
+        .enumerate()
+        .map(|(c, rule)| rule.cell_options(&get_line(&grid, Axis::Column, c)))
+        .collect();
+
+    let candidates: Vec<Vec<CellCandidate>> = (0..rows)
+        .map(|r| {
+            (0..cols)
+                .map(|c| {
+                    let (row_fill, row_cross) = row_options[r][c];
+                    let (col_fill, col_cross) = col_options[c][r];
+                    match (row_fill && col_fill, row_cross && col_cross) {
+                        (true, true) => CellCandidate::Either,
+                        (true, false) => CellCandidate::Filled,
+                        (false, true) => CellCandidate::Crossed,
+                        (false, false) => CellCandidate::Conflict,
+                    }
+                })
+                .collect()
+        })
+        .collect();
+    Ok(serde_wasm_bindgen::to_value(&candidates)?)
+}
+
+/// 重なり法だけで確定するマスの数をJavaScriptに返すためのデータ構造
+#[derive(Serialize, Deserialize)]
+pub struct TrivialCount {
+    filled: usize,  // 「塗り」に確定するマスの数
+    crossed: usize, // 「×」に確定するマスの数（どのブロックの範囲にも入らないマス）
+    total: usize,   // 確定するマスの総数（行と列の両方で確定するマスは1つと数える）
+}
+
+/// 空の盤面で、各ラインに重なり法を1回ずつ適用しただけで確定するマスの数を求める関数
+/// パズルの取っ掛かりの多さを示す指標で、0なら「最初の一手すら自明でない」難しいパズルだと分かる
+/// 配置パターンを生成せず、ブロックの範囲の計算だけで求めるので高速に計算でき、難易度の分類や作問のバランス調整に使える
+///
+/// # Arguments
+/// * `rows` - 盤面の行数
+/// * `cols` - 盤面の列数
+/// * `row_rules_js` - 行ルールの配列
+/// * `col_rules_js` - 列ルールの配列
+///
+/// # Returns
+/// * `Ok(JsValue)` - 確定するマスの数（`TrivialCount`）
+/// * `Err(JsValue)` - 入力が不正な場合、またはラインに収まらないルールがある場合のエラー
+#[wasm_bindgen]
+pub fn trivially_determined_count(
+    rows: usize,
+    cols: usize,
+    row_rules_js: JsValue,
+    col_rules_js: JsValue,
+) -> Result<JsValue, JsValue> {
+    let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+    let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+    let blank = vec![vec![CellState::Empty; cols]; rows];
+    check_dimensions(rows, cols, &row_rules, &col_rules, &blank)
+        .map_err(|e| JsValue::from_str(&e))?;
+
+    // 行と列の結果を1つの盤面に重ねる（両方で確定するマスは、先に書いた行の結果を残す）
+    let mut grid: Vec<Vec<Option<CellState>>> = vec![vec![None; cols]; rows];
+    for (axis, rules, size) in [
+        (Axis::Row, &row_rules, cols),
+        (Axis::Column, &col_rules, rows),
+    ] {
+        for (index, rule) in rules.iter().enumerate() {
+            let rule = ValidatedRule::new(rule, size).map_err(|e| {
+                JsValue::from_str(&format!("{} {}: {}", axis.label(), index + 1, e))
+            })?;
+            for (i, state) in rule.overlap().into_iter().enumerate() {
+                let cell = match axis {
+                    Axis::Row => &mut grid[index][i],
+                    Axis::Column => &mut grid[i][index],
+                };
+                if cell.is_none() {
+                    *cell = state;
+                }
+            }
+        }
+    }
+
+    let count = |target: CellState| {
+        grid.iter()
+            .flatten()
+            .filter(|&&cell| cell == Some(target))
+            .count()
+    };
+    let (filled, crossed) = (count(CellState::Filled), count(CellState::Crossed));
+    let result = TrivialCount {
+        filled,
+        crossed,
+        total: filled + crossed,
+    };
+    Ok(serde_wasm_bindgen::to_value(&result)?)
+}
+
+// --- ヒントの開示 ---
+
+/// 段階的なヒントの開示結果をJavaScriptに返すためのデータ構造
+#[derive(Serialize, Deserialize)]
+pub struct HintResult {
+    grid: Vec<Vec<CellState>>, // 開示したマスを書き込んだ盤面
+    revealed: Vec<CellPatch>,  // 開示したマスの一覧（開示する順）
+    determinable: usize,       // 現在の盤面から確定できるマスの総数
+}
+
+/// 確定できるマスを、ヒントとして開示する順に並べる関数
+/// シード値を指定した場合、同じ行に開示が偏らないよう、行の順序と行内の順序をそれぞれシャッフルしてから
+/// 各行から1マスずつ順番に取り出す
+/// そのため少しだけ開示する場合でも、ヒントが多くのラインに散らばる
+/// 指定しない場合は、左上から行ごとに順番に並べる
+fn hint_order(cells: Vec<CellPatch>, rows: usize, seed: Option<u32>) -> Vec<CellPatch> {
+    let Some(seed) = seed else {
+        return cells;
+    };
+    let mut rng = SeededRng::new(seed as u64);
+
+    // 行ごとに分け、行の順序と行内の順序をシャッフルする
+    let mut by_row: Vec<Vec<CellPatch>> = (0..rows).map(|_| Vec::new()).collect();
+    for cell in cells {
+        by_row[cell.row].push(cell);
+    }
+    rng.shuffle(&mut by_row);
+    for row in by_row.iter_mut() {
+        rng.shuffle(row);
+    }
+
+    // 各行の先頭から1マスずつ順番に取り出す
+    let mut iters: Vec<_> = by_row.into_iter().map(Vec::into_iter).collect();
+    let mut ordered = Vec::new();
+    loop {
+        let before = ordered.len();
+        ordered.extend(iters.iter_mut().filter_map(Iterator::next));
+        if ordered.len() == before {
+            return ordered;
+        }
+    }
+}
+
+/// `solve_puzzle`で確定できるマスのうち、指定した割合だけをヒントとして開示する関数
+/// 「少しだけ教える」「かなり教える」のように、ユーザーが自力で解く余地を残しながら詰まりを解消できる
+/// 開示するマスの数は`fraction`×確定できるマスの総数を切り上げた数なので、`fraction`が0より大きければ最低1マスは開示する
+///
+/// # Arguments
+/// * `rows` - 盤面の行数
+/// * `cols` - 盤面の列数
+/// * `row_rules_js` - 行ルール
+/// * `col_rules_js` - 列ルール
+/// * `grid_js` - 現在の盤面
+/// * `fraction` - 開示する割合（0.0〜1.0）
+/// * `seed` - 指定した場合はこのシード値でランダムに開示する（同じシードなら同じマスを開示する）
+///   省略した場合は左上から順に開示する
+///
+/// # Returns
+/// * `Ok(JsValue)` - 開示後の盤面と開示したマス（`HintResult`）
+/// * `Err(JsValue)` - 入力が不正な場合、`fraction`が範囲外の場合、または盤面が矛盾している場合のエラー
+#[wasm_bindgen]
+pub fn reveal_hints(
+    rows: usize,
+    cols: usize,
+    row_rules_js: JsValue,
+    col_rules_js: JsValue,
+    grid_js: JsValue,
+    fraction: f64,
+    seed: Option<u32>,
+) -> Result<JsValue, JsValue> {
+    let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+    let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+    let mut grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(grid_js)?;
+    check_dimensions(rows, cols, &row_rules, &col_rules, &grid)
+        .map_err(|e| JsValue::from_str(&e))?;
+    if !(0.0..=1.0).contains(&fraction) {
+        return Err(JsValue::from_str(&format!(
+            "開示する割合（{}）は0.0〜1.0の範囲で指定してください",
+            fraction
+        )));
+    }
+
+    // 確定できる全てのマスを求める
+    let mut solved = grid.clone();
+    Propagator::new(rows, cols, &row_rules, &col_rules)
+        .propagate(&mut solved)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    let cells: Vec<CellPatch> = (0..rows)
+        .flat_map(|r| (0..cols).map(move |c| (r, c)))
+        .filter(|&(r, c)| grid[r][c] == CellState::Empty && solved[r][c] != CellState::Empty)
+        .map(|(r, c)| CellPatch {
+            row: r,
+            col: c,
+            state: solved[r][c],
+        })
+        .collect();
+    let determinable = cells.len();
+
+    let count = ((determinable as f64) * fraction).ceil() as usize;
+    let mut revealed = hint_order(cells, rows, seed);
+    revealed.truncate(count.min(determinable));
+    for cell in &revealed {
+        grid[cell.row][cell.col] = cell.state;
+    }
+
+    let result = HintResult {
+        grid,
+        revealed,
+        determinable,
+    };
+    Ok(serde_wasm_bindgen::to_value(&result)?)
+}
+
+/// `make_hinted_grid`で"easy"のときに残すヒントの割合（全マスに対する割合）
+const HINT_FRACTION_EASY: f64 = 0.25;
+/// `make_hinted_grid`で"medium"のときに残すヒントの割合
+const HINT_FRACTION_MEDIUM: f64 = 0.1;
+/// `make_hinted_grid`で"hard"のときに残すヒントの割合
+const HINT_FRACTION_HARD: f64 = 0.0;
+
+/// ヒント付きの初期盤面をJavaScriptに返すためのデータ構造
+#[derive(Serialize, Deserialize)]
+pub struct HintedGrid {
+    grid: Vec<Vec<CellState>>,   // ヒントだけを残し、残りを「空」にした初期盤面
+    row_rules: Vec<Vec<usize>>,  // 完成盤面から計算した行ルール
+    col_rules: Vec<Vec<usize>>,  // 完成盤面から計算した列ルール
+    hints: usize,                // 残したヒントの数
+    added_for_uniqueness: usize, // 難易度で決まる数に加えて、論理だけで解けるように追加したヒントの数
+    logic_solvable: bool,        // 初期盤面から、仮定を置かずに解析だけで解き切れるか
+}
+
+/// 完成盤面から、一部のマスを初期ヒントとして残したヒント付きの初期盤面を作る関数
+/// `difficulty`に応じた割合のマスを、同じ行に偏らないように選んでヒントとして残す
+/// `ensure_unique`を指定した場合は、解析だけで完成盤面まで解き切れるようになるまでヒントを追加するので、
+/// 推測なしで解ける（解が1つに定まる）ことが保証される
+///
+/// # Arguments
+/// * `grid_js` - 完成盤面（「塗り」以外のマスは「×」として扱う）
+/// * `difficulty` - "easy"（多めに残す）、"medium"、"hard"（難易度による最低限のヒントは残さない）のいずれか
+/// * `ensure_unique` - `true`の場合、論理だけで一意に解けるまでヒントを追加する
+/// * `seed` - ヒントを選ぶ乱数のシード値（省略した場合は左上から順に選ぶ）
+///
+/// # Returns
+/// * `Ok(JsValue)` - ヒント付きの初期盤面とルール（`HintedGrid`）
+/// * `Err(JsValue)` - 盤面の形式が不正な場合や、`difficulty`が不明な値の場合のエラー
+#[wasm_bindgen]
+pub fn make_hinted_grid(
+    grid_js: JsValue,
+    difficulty: &str,
+    ensure_unique: bool,
+    seed: Option<u32>,
+) -> Result<JsValue, JsValue> {
+    let grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(grid_js)?;
+    let cols = grid_width(&grid).map_err(|e| JsValue::from_str(&e))?;
+    let rows = grid.len();
+    let fraction = match difficulty {
+        "easy" => HINT_FRACTION_EASY,
+        "medium" => HINT_FRACTION_MEDIUM,
+        "hard" => HINT_FRACTION_HARD,
+        _ => {
+            return Err(JsValue::from_str(&format!(
+                "不明な難易度です: \"{}\"（easy、medium、hardのいずれかを指定してください）",
+                difficulty
+            )));
+        }
+    };
+
+    // 「塗り」以外のマスを「×」に揃えた完成盤面から、ルールとヒントの候補を作る
+    let solution: Vec<Vec<CellState>> = grid
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|&cell| {
+                    if cell == CellState::Filled {
+                        CellState::Filled
+                    } else {
+                        CellState::Crossed
+                    }
+                })
+                .collect()
+        })
+        .collect();
+    let (row_rules, col_rules) = rules_from_grid(&solution);
+    let cells: Vec<CellPatch> = (0..rows)
+        .flat_map(|r| (0..cols).map(move |c| (r, c)))
+        .map(|(row, col)| CellPatch {
+            row,
+            col,
+            state: solution[row][col],
+        })
+        .collect();
+    let order = hint_order(cells, rows, seed);
+
+    let count = ((order.len() as f64) * fraction).ceil() as usize;
+    let mut hinted = vec![vec![CellState::Empty; cols]; rows];
+    for cell in order.iter().take(count) {
+        hinted[cell.row][cell.col] = cell.state;
+    }
+
+    // 解析で決まらないマスが残る間、ヒントの順序で最初に決まらなかったマスをヒントに加える
+    // ヒントを加えるたびに決まらないマスは減るので、最悪でも全マスをヒントにした時点で止まる
+    let propagator = Propagator::new(rows, cols, &row_rules, &col_rules);
+    let solve = |hinted: &[Vec<CellState>]| {
+        let mut solved = hinted.to_vec();
+        propagator.propagate_to_fixpoint(&mut solved).ok()?;
+        Some(solved)
+    };
+    let is_solved = |solved: &Option<Vec<Vec<CellState>>>| solved.as_ref() == Some(&solution);
+    let mut solved = solve(&hinted);
+    let mut added_for_uniqueness = 0;
+    if ensure_unique {
+        while !is_solved(&solved) {
+            let Some(cell) = order.iter().find(|cell| {
+                solved
+                    .as_ref()
+                    .is_none_or(|solved| solved[cell.row][cell.col] == CellState::Empty)
+                    && hinted[cell.row][cell.col] == CellState::Empty
+            }) else {
+                break;
+            };
+            hinted[cell.row][cell.col] = cell.state;
+            added_for_uniqueness += 1;
+            solved = solve(&hinted);
+        }
+    }
+
+    let result = HintedGrid {
+        hints: count + added_for_uniqueness,
+        logic_solvable: is_solved(&solved),
+        grid: hinted,
+        row_rules,
+        col_rules,
+        added_for_uniqueness,
+    };
+    Ok(serde_wasm_bindgen::to_value(&result)?)
+}
+
+// --- 完全解の探索 ---
+
+/// 探索を外部から中断するためのキャンセルフラグ
+/// フラグはクローンしたトークン同士で共有され、ネイティブでは別スレッドから`cancel`して探索を止められる
+/// WASMのメモリはWeb Worker間で共有されないため、別のWorkerで作ったトークンを立てても探索中のWorkerには届かない
+/// 探索ループは1ステップごとにこのフラグを確認し、立っていれば「キャンセルされました」を返す
+#[wasm_bindgen]
+#[derive(Clone, Default)]
+pub struct CancelToken {
+    flag: Arc<AtomicBool>,
+}
+
+#[wasm_bindgen]
+impl CancelToken {
+    /// 中断されていない状態のキャンセルフラグを作る
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    /// 探索の中断を要求する
+    pub fn cancel(&self) {
+        self.flag.store(true, Ordering::Relaxed);
+    }
+
+    /// 中断が要求されているかどうかを返す
+    pub fn is_cancelled(&self) -> bool {
+        self.flag.load(Ordering::Relaxed)
+    }
+
+    /// フラグを下ろし、次の探索で再利用できるようにする
+    pub fn reset(&self) {
+        self.flag.store(false, Ordering::Relaxed);
+    }
+}
+
+/// バックトラッキング探索の分岐点（仮定を置いたマス）を表すデータ構造
+#[derive(Serialize, Deserialize)]
+struct SearchFrame {
+    grid: Vec<Vec<CellState>>, // 仮定を置く直前の、伝播済みの盤面
+    row: usize,                // 仮定を置くマスの行
+    col: usize,                // 仮定を置くマスの列
+    next: u8,                  // 次に試す選択肢（0: 1番目, 1: 2番目, 2: 試し尽くした）
+}
+
+/// 探索の結果を表すenum
+enum SearchOutcome {
+    /// ルールを満たす完成盤面が見つかった
+    Solved(Vec<Vec<CellState>>),
+    /// 全ての分岐を試し尽くした（これ以上解はない）
+    Exhausted,
+    /// キャンセルフラグによって中断された
+    Cancelled,
+}
+
+/// ライン伝播と仮定（バックトラッキング）を組み合わせて、完成盤面を探す構造体
+/// 再帰ではなく明示的なスタックで深さ優先探索を行うので、1ステップごとに中断点を設けられる
+struct SolutionSearch<'a> {
+    propagator: &'a Propagator,
+    stack: Vec<SearchFrame>,                    // 仮定を置いたマスの履歴
+    pending: Option<Vec<Vec<CellState>>>,       // 次に伝播させる盤面
+    guess_order: [CellState; 2],                // 分岐点で仮定を試す順序
+    best: Option<(usize, Vec<Vec<CellState>>)>, // これまでに作った分岐点のうち、「空」のマスが最も少ない盤面とその数
+}
+
+impl<'a> SolutionSearch<'a> {
+    /// 指定した盤面を出発点とする探索を準備する
+    fn new(propagator: &'a Propagator, grid: Vec<Vec<CellState>>) -> Self {
+        SolutionSearch {
+            propagator,
+            stack: Vec::new(),
+            pending: Some(grid),
+            guess_order: SearchPolicy::FilledFirst.guess_order(),
+            best: None,
+        }
+    }
+
+    /// 分岐点で仮定を試す順序を指定する
+    fn with_guess_order(mut self, guess_order: [CellState; 2]) -> Self {
+        self.guess_order = guess_order;
+        self
+    }
+
+    /// 次の解が見つかるまで探索を進める
+    /// 続けて呼び出すと、前回の解の続きから別の解を探す
+    ///
+    /// # Arguments
+    /// * `is_cancelled` - 1ステップごとに呼ばれ、`true`を返すと探索を中断する
+    fn next_solution(&mut self, is_cancelled: &dyn Fn() -> bool) -> SearchOutcome {
+        loop {
+            if is_cancelled() {
+                return SearchOutcome::Cancelled;
+            }
+
+            // 伝播待ちの盤面があれば、まず論理で確定できるところまで進める
+            if let Some(mut grid) = self.pending.take() {
+                if self.propagator.propagate_to_fixpoint(&mut grid).is_err() {
+                    // 矛盾した場合はこの分岐を捨て、次の選択肢へ
+                    continue;
+                }
+                match first_empty_cell(&grid) {
+                    // 全てのマスが確定していれば、それが解
+                    None => return SearchOutcome::Solved(grid),
+                    // 未確定のマスが残っていれば、そこに仮定を置く分岐点を作る
+                    Some((row, col)) => {
+                        self.remember_if_best(&grid);
+                        self.stack.push(SearchFrame {
+                            grid,
+                            row,
+                            col,
+                            next: 0,
+                        })
+                    }
+                }
+                continue;
+            }
+
+            // 直近の分岐点で、まだ試していない選択肢を取り出す
+            let Some(frame) = self.stack.last_mut() else {
+                return SearchOutcome::Exhausted;
+            };
+            let guess = match frame.next {
+                0 | 1 => self.guess_order[frame.next as usize],
+                _ => {
+                    // 両方試し終えた分岐点は捨てて、一つ前の分岐点に戻る
+                    self.stack.pop();
+                    continue;
+                }
+            };
+            frame.next += 1;
+            let mut grid = frame.grid.clone();
+            grid[frame.row][frame.col] = guess;
+            self.pending = Some(grid);
+        }
+    }
+
+    /// 分岐点の盤面が、これまでで最も多くのマスが埋まった盤面なら覚えておく
+    fn remember_if_best(&mut self, grid: &[Vec<CellState>]) {
+        let empty = grid
+            .iter()
+            .flatten()
+            .filter(|&&cell| cell == CellState::Empty)
+            .count();
+        if self.best.as_ref().is_none_or(|(fewest, _)| empty < *fewest) {
+            self.best = Some((empty, grid.to_vec()));
+        }
+    }
+
+    /// 探索中に最も多くのマスが埋まった分岐点の盤面（まだ分岐点を作っていなければ`None`）
+    /// 最初の分岐点より深い盤面は仮定を含むので、解の一部とは限らない
+    fn best_partial(&self) -> Option<&Vec<Vec<CellState>>> {
+        self.best.as_ref().map(|(_, grid)| grid)
+    }
+}
+
+/// 行優先で走査して、最初に見つかった「空」のマスの座標を返すヘルパー関数
+fn first_empty_cell(grid: &[Vec<CellState>]) -> Option<(usize, usize)> {
+    grid.iter().enumerate().find_map(|(r, row)| {
+        row.iter()
+            .position(|&cell| cell == CellState::Empty)
+            .map(|c| (r, c))
+    })
+}
+
+/// 複数の解があるパズルで、どの解を返すかを決める探索方針
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchPolicy {
+    /// 分岐点で「塗り」から先に試し、最初に見つかった解を返す（従来の探索）
+    /// 分岐点は常に行優先で最初の未確定のマスなので、盤面を行優先のビット列（「塗り」が1）とみなしたときの
+    /// 辞書順最大の解になる（"lexicographic_max"でも指定できる）
+    #[default]
+    #[serde(alias = "lexicographic_max")]
+    FilledFirst,
+    /// 分岐点で「×」から先に試し、最初に見つかった解を返す
+    /// `FilledFirst`と同様に、辞書順最小の解になる（"lexicographic_min"でも指定できる）
+    #[serde(alias = "lexicographic_min")]
+    CrossedFirst,
+    /// 解を最大`MAX_POLICY_SOLUTIONS`個まで集め、「塗り」のマスの連結領域（上下左右のつながり）が最も少ない解を返す
+    /// 各ラインのブロック数や「塗り」の総数はルールで決まり、どの解でも同じなので、
+    /// 盤面全体での塊の少なさを「絵としてのまとまり」の目安にする
+    FewestRegions,
+}
+
+/// `SearchPolicy::FewestRegions`で比較する解の最大数
+const MAX_POLICY_SOLUTIONS: usize = 200;
+
+impl SearchPolicy {
+    /// JavaScriptから渡された探索方針を読み込む`undefined`や`null`の場合は既定値を使う
+    fn from_js(policy_js: JsValue) -> Result<Self, JsValue> {
+        if policy_js.is_undefined() || policy_js.is_null() {
+            return Ok(SearchPolicy::default());
+        }
+        Ok(serde_wasm_bindgen::from_value(policy_js)?)
+    }
+
+    /// 分岐点で仮定を試す順序
+    fn guess_order(self) -> [CellState; 2] {
+        match self {
+            SearchPolicy::CrossedFirst => [CellState::Crossed, CellState::Filled],
+            SearchPolicy::FilledFirst | SearchPolicy::FewestRegions => {
+                [CellState::Filled, CellState::Crossed]
+            }
+        }
+    }
+}
+
+/// 「塗り」のマスが上下左右につながった領域の数を数えるヘルパー関数
+fn filled_regions(grid: &[Vec<CellState>]) -> usize {
+    let mut visited: Vec<Vec<bool>> = grid.iter().map(|row| vec![false; row.len()]).collect();
+    let mut regions = 0;
+    for r in 0..grid.len() {
+        for c in 0..grid[r].len() {
+            if grid[r][c] != CellState::Filled || visited[r][c] {
+                continue;
+            }
+            // 未訪問の「塗り」のマスから、つながっているマスを全て塗りつぶす
+            regions += 1;
+            visited[r][c] = true;
+            let mut stack = vec![(r, c)];
+            while let Some((r, c)) = stack.pop() {
+                let neighbors = [
+                    (r.wrapping_sub(1), c),
+                    (r + 1, c),
+                    (r, c.wrapping_sub(1)),
+                    (r, c + 1),
+                ];
+                for (nr, nc) in neighbors {
+                    if grid.get(nr).and_then(|row| row.get(nc)) == Some(&CellState::Filled)
+                        && !visited[nr][nc]
+                    {
+                        visited[nr][nc] = true;
+                        stack.push((nr, nc));
+                    }
+                }
+            }
+        }
+    }
+    regions
+}
+
+/// 論理で確定できないマスには仮定を置きながら探索し、ルールを満たす完成盤面を1つ求める関数
+///
+/// # Arguments
+/// * `rows` - 盤面の行数
+/// * `cols` - 盤面の列数
+/// * `row_rules_js` - 行ルールの配列
+/// * `col_rules_js` - 列ルールの配列
+/// * `initial_grid_js` - 現在の盤面
+/// * `policy_js` - 複数の解がある場合の探索方針（"filled_first"（"lexicographic_max"）、"crossed_first"（"lexicographic_min"）、"fewest_regions"）
+///   省略した場合は"filled_first"（最初に見つかった解）になる
+#[wasm_bindgen]
+pub fn solve_complete(
+    rows: usize,
+    cols: usize,
+    row_rules_js: JsValue,
+    col_rules_js: JsValue,
+    initial_grid_js: JsValue,
+    policy_js: JsValue,
+) -> Result<JsValue, JsValue> {
+    let policy = SearchPolicy::from_js(policy_js)?;
+    complete_search(
+        rows,
+        cols,
+        row_rules_js,
+        col_rules_js,
+        initial_grid_js,
+        policy,
+        &CancelToken::new(),
+    )
+}
+
+/// `solve_complete`と同じ探索を、キャンセルフラグで中断できるようにした関数
+/// 中断された場合は、呼び出し時点の盤面と「キャンセルされました」のメッセージを返す
+#[wasm_bindgen]
+pub fn solve_complete_with_cancel(
+    rows: usize,
+    cols: usize,
+    row_rules_js: JsValue,
+    col_rules_js: JsValue,
+    initial_grid_js: JsValue,
+    token: &CancelToken,
+) -> Result<JsValue, JsValue> {
+    complete_search(
+        rows,
+        cols,
+        row_rules_js,
+        col_rules_js,
+        initial_grid_js,
+        SearchPolicy::default(),
+        token,
+    )
+}
+
+/// `solve_complete`と`solve_complete_with_cancel`の共通の処理
+/// `SearchPolicy::FewestRegions`で中断された場合は、それまでに見つかった解のうち最もよいものを返す
+fn complete_search(
+    rows: usize,
+    cols: usize,
+    row_rules_js: JsValue,
+    col_rules_js: JsValue,
+    initial_grid_js: JsValue,
+    policy: SearchPolicy,
+    token: &CancelToken,
+) -> Result<JsValue, JsValue> {
+    #[cfg(feature = "console_error_panic_hook")]
+    set_panic_hook();
+
+    let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+    let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+    let initial_grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(initial_grid_js)?;
+
+    check_dimensions(rows, cols, &row_rules, &col_rules, &initial_grid)
+        .map_err(|e| JsValue::from_str(&e))?;
+    let propagator = Propagator::new(rows, cols, &row_rules, &col_rules);
+    let mut search = SolutionSearch::new(&propagator, initial_grid.clone())
+        .with_guess_order(policy.guess_order());
+    let is_cancelled = || token.is_cancelled();
+
+    let outcome = if policy == SearchPolicy::FewestRegions {
+        // 見つかった解の中から、連結領域の数が最も少ないもの（同数なら先に見つかったもの）を選ぶ
+        let mut best: Option<(usize, Vec<Vec<CellState>>)> = None;
+        let mut last = SearchOutcome::Exhausted;
+        for _ in 0..MAX_POLICY_SOLUTIONS {
+            match search.next_solution(&is_cancelled) {
+                SearchOutcome::Solved(grid) => {
+                    let regions = filled_regions(&grid);
+                    if best.as_ref().is_none_or(|(fewest, _)| regions < *fewest) {
+                        best = Some((regions, grid));
+                    }
+                }
+                other => {
+                    last = other;
+                    break;
+                }
+            }
+        }
+        best.map_or(last, |(_, grid)| SearchOutcome::Solved(grid))
+    } else {
+        search.next_solution(&is_cancelled)
+    };
+
+    let result = match outcome {
+        SearchOutcome::Solved(grid) => SolveResult {
+            grid,
+            message: "解が見つかりました".to_string(),
+            error: false,
+            metrics: None,
+        },
+        SearchOutcome::Exhausted => SolveResult {
+            grid: initial_grid,
+            message: "ルールを満たす解が存在しません".to_string(),
+            error: true,
+            metrics: None,
+        },
+        SearchOutcome::Cancelled => SolveResult {
+            grid: initial_grid,
+            message: "キャンセルされました".to_string(),
+            error: true,
+            metrics: None,
+        },
+    };
+    Ok(serde_wasm_bindgen::to_value(&result)?)
+}
+
+/// `solve_complete`と同じ探索を、指定した時間で打ち切るようにした関数
+/// 時間切れの場合は、探索中に最も多くのマスが埋まった盤面を部分解として返す
+/// 部分解は仮定を置いた後の盤面であることもあり、その場合は解の一部とは限らない（進み具合の表示用）
+///
+/// # Arguments
+/// * `rows` - 盤面の行数
+/// * `cols` - 盤面の列数
+/// * `row_rules_js` - 行ルールの配列
+/// * `col_rules_js` - 列ルールの配列
+/// * `initial_grid_js` - 現在の盤面
+/// * `timeout_ms` - 探索を打ち切るまでの時間（ミリ秒）
+#[wasm_bindgen]
+pub fn solve_complete_with_timeout(
+    rows: usize,
+    cols: usize,
+    row_rules_js: JsValue,
+    col_rules_js: JsValue,
+    initial_grid_js: JsValue,
+    timeout_ms: u32,
+) -> Result<JsValue, JsValue> {
+    let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+    let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+    let initial_grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(initial_grid_js)?;
+
+    check_dimensions(rows, cols, &row_rules, &col_rules, &initial_grid)
+        .map_err(|e| JsValue::from_str(&e))?;
+    let propagator = Propagator::new(rows, cols, &row_rules, &col_rules);
+    let result = search_with_timeout(&propagator, initial_grid, timeout_ms);
+    Ok(serde_wasm_bindgen::to_value(&result)?)
+}
+
+/// `solve_complete_with_timeout`の本体で、`JsValue`を介さずに時間制限付きの探索を行う
+fn search_with_timeout(
+    propagator: &Propagator,
+    initial_grid: Vec<Vec<CellState>>,
+    timeout_ms: u32,
+) -> SolveResult {
+    let mut search = SolutionSearch::new(propagator, initial_grid.clone());
+
+    // 探索ループの1ステップごとに経過時間を確認する
+    let deadline = now_ms() + timeout_ms as f64;
+    match search.next_solution(&|| now_ms() >= deadline) {
+        SearchOutcome::Solved(grid) => SolveResult {
+            grid,
+            message: "解が見つかりました".to_string(),
+            error: false,
+            metrics: None,
+        },
+        SearchOutcome::Exhausted => SolveResult {
+            grid: initial_grid,
+            message: "ルールを満たす解が存在しません".to_string(),
+            error: true,
+            metrics: None,
+        },
+        SearchOutcome::Cancelled => SolveResult {
+            grid: search.best_partial().cloned().unwrap_or(initial_grid),
+            message: "時間切れです（探索中に最も多くのマスが埋まった盤面を返します。仮定を含む場合があります）"
+                .to_string(),
+            error: true,
+            metrics: None,
+        },
+    }
+}
+
+/// マスごとの確定の信頼度を付けた解析結果をJavaScriptに返すためのデータ構造
+/// `SolveResult`のフィールドに`certainty`を加えた形になる
+#[derive(Serialize, Deserialize)]
+pub struct CertaintyResult {
+    grid: Vec<Vec<CellState>>, // 更新された盤面の状態
+    message: String,           // ユーザーに表示するメッセージ
+    error: bool,               // エラーが発生したかどうかを示すフラグ
+    certainty: Vec<Vec<u8>>,   // 各マスの信頼度（0: 未確定, 1: 論理で確定, 2: 推測を経て確定）
+}
+
+/// `solve_complete`と同じ探索を行い、各マスが論理だけで確定したのか、推測（仮定）を経て確定したのかを区別して返す関数
+/// 呼び出し時点で確定済みのマスは、論理で確定したマスとして扱う
+///
+/// # Arguments
+/// * `rows` - 盤面の行数
+/// * `cols` - 盤面の列数
+/// * `row_rules_js` - 行ルールの配列
+/// * `col_rules_js` - 列ルールの配列
+/// * `initial_grid_js` - 現在の盤面
+#[wasm_bindgen]
+pub fn solve_complete_with_certainty(
+    rows: usize,
+    cols: usize,
+    row_rules_js: JsValue,
+    col_rules_js: JsValue,
+    initial_grid_js: JsValue,
+) -> Result<JsValue, JsValue> {
+    let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+    let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+    let initial_grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(initial_grid_js)?;
+
+    check_dimensions(rows, cols, &row_rules, &col_rules, &initial_grid)
+        .map_err(|e| JsValue::from_str(&e))?;
+    let propagator = Propagator::new(rows, cols, &row_rules, &col_rules);
+
+    // 仮定を置かずに確定できるマスを先に求めておく（矛盾する場合は解もないので、そのまま探索に任せる）
+    let mut logical_grid = initial_grid.clone();
+    if propagator.propagate_to_fixpoint(&mut logical_grid).is_err() {
+        logical_grid = initial_grid.clone();
+    }
+
+    let mut search = SolutionSearch::new(&propagator, logical_grid.clone());
+    let result = match search.next_solution(&|| false) {
+        SearchOutcome::Solved(grid) => SolveResult {
+            grid,
+            message: "解が見つかりました".to_string(),
+            error: false,
+            metrics: None,
+        },
+        SearchOutcome::Exhausted | SearchOutcome::Cancelled => SolveResult {
+            grid: initial_grid,
+            message: "ルールを満たす解が存在しません".to_string(),
+            error: true,
+            metrics: None,
+        },
+    };
+
+    let certainty = result
+        .grid
+        .iter()
+        .zip(&logical_grid)
+        .map(|(row, logical_row)| {
+            row.iter()
+                .zip(logical_row)
+                .map(|(&cell, &logical)| match (cell, logical) {
+                    (CellState::Empty, _) => 0,
+                    (_, CellState::Empty) => 2,
+                    _ => 1,
+                })
+                .collect()
+        })
+        .collect();
+    Ok(serde_wasm_bindgen::to_value(&CertaintyResult {
+        grid: result.grid,
+        message: result.message,
+        error: result.error,
+        certainty,
+    })?)
+}
+
+/// 探索を進めて、最大`limit`個までの解を集めるヘルパー関数
+fn find_solutions(
+    propagator: &Propagator,
+    grid: Vec<Vec<CellState>>,
+    limit: usize,
+) -> Vec<Vec<Vec<CellState>>> {
+    let mut search = SolutionSearch::new(propagator, grid);
+    let mut solutions = Vec::new();
+    while solutions.len() < limit {
+        match search.next_solution(&|| false) {
+            SearchOutcome::Solved(solution) => solutions.push(solution),
+            SearchOutcome::Exhausted | SearchOutcome::Cancelled => break,
+        }
+    }
+    solutions
+}
+
+/// 中断・再開できる探索の、保存用の内部状態
+/// ルールと盤面に加えて、探索木の現在位置（仮定の履歴と各分岐点の盤面）を全て含む
+#[derive(Serialize, Deserialize)]
+struct SearchSnapshot {
+    rows: usize,
+    cols: usize,
+    row_rules: Vec<Vec<usize>>,
+    col_rules: Vec<Vec<usize>>,
+    stack: Vec<SearchFrame>,              // 仮定を置いたマスの履歴
+    pending: Option<Vec<Vec<CellState>>>, // 次に伝播させる盤面
+}
+
+/// `ResumableSearch::run`の結果をJavaScriptに返すためのデータ構造
+#[derive(Serialize, Deserialize)]
+pub struct SearchProgress {
+    status: String, // "solved"（解が見つかった）、"exhausted"（解が残っていない）、"paused"（ステップ数の上限で中断）
+    grid: Option<Vec<Vec<CellState>>>, // 見つかった解（"solved"のときだけ）
+    message: String, // ユーザーに表示するメッセージ
+}
+
+/// `solve_complete`と同じ探索を、少しずつ進めたり状態を保存して後で再開したりできるようにした構造体
+/// `save_state`で得た値をストレージに保存しておけば、別のセッションで`resume`して続きから探索できる
+#[wasm_bindgen]
+pub struct ResumableSearch {
+    snapshot: SearchSnapshot, // ルールと探索の現在位置
+    propagator: Propagator,   // `snapshot`のルールから作ったコンパイル済みルール
+}
+
+#[wasm_bindgen]
+impl ResumableSearch {
+    /// 指定した盤面を出発点とする探索を準備する
+    ///
+    /// # Arguments
+    /// * `rows` - 盤面の行数
+    /// * `cols` - 盤面の列数
+    /// * `row_rules_js` - 行ルールの配列
+    /// * `col_rules_js` - 列ルールの配列
+    /// * `initial_grid_js` - 探索を始める盤面
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        rows: usize,
+        cols: usize,
+        row_rules_js: JsValue,
+        col_rules_js: JsValue,
+        initial_grid_js: JsValue,
+    ) -> Result<ResumableSearch, JsValue> {
+        let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+        let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+        let initial_grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(initial_grid_js)?;
+        check_dimensions(rows, cols, &row_rules, &col_rules, &initial_grid)
+            .map_err(|e| JsValue::from_str(&e))?;
+        Ok(ResumableSearch::from_snapshot(SearchSnapshot {
+            rows,
+            cols,
+            row_rules,
+            col_rules,
+            stack: Vec::new(),
+            pending: Some(initial_grid),
+        }))
+    }
+
+    /// `save_state`で保存した状態から探索を再開する
+    /// 保存された値は書き換えられている可能性があるので、ルールと盤面の形を検証してから使う
+    pub fn resume(state_js: JsValue) -> Result<ResumableSearch, JsValue> {
+        let snapshot: SearchSnapshot = serde_wasm_bindgen::from_value(state_js)?;
+        snapshot.check().map_err(|e| JsValue::from_str(&e))?;
+        Ok(ResumableSearch::from_snapshot(snapshot))
+    }
+
+    /// 探索の現在の状態を、ストレージに保存できる値として返す
+    pub fn save_state(&self) -> Result<JsValue, JsValue> {
+        Ok(serde_wasm_bindgen::to_value(&self.snapshot)?)
+    }
+
+    /// 次の解が見つかるか、`max_steps`ステップ進めるまで探索する
+    /// 解が見つかった後に続けて呼び出すと、別の解を探す
+    ///
+    /// # Arguments
+    /// * `max_steps` - この呼び出しで進める最大ステップ数（0なら無制限）
+    pub fn run(&mut self, max_steps: u32) -> Result<JsValue, JsValue> {
+        let steps = std::cell::Cell::new(0u32);
+        let is_paused = || {
+            steps.set(steps.get() + 1);
+            max_steps > 0 && steps.get() > max_steps
+        };
+
+        // 探索の状態を一時的に`SolutionSearch`へ移して進め、終わったら書き戻す
+        let mut search = SolutionSearch {
+            propagator: &self.propagator,
+            stack: std::mem::take(&mut self.snapshot.stack),
+            pending: self.snapshot.pending.take(),
+            guess_order: SearchPolicy::FilledFirst.guess_order(),
+            best: None,
+        };
+        let outcome = search.next_solution(&is_paused);
+        self.snapshot.stack = search.stack;
+        self.snapshot.pending = search.pending;
+
+        let progress = match outcome {
+            SearchOutcome::Solved(grid) => SearchProgress {
+                status: "solved".to_string(),
+                grid: Some(grid),
+                message: "解が見つかりました".to_string(),
+            },
+            SearchOutcome::Exhausted => SearchProgress {
+                status: "exhausted".to_string(),
+                grid: None,
+                message: "ルールを満たす解はこれ以上ありません".to_string(),
+            },
+            SearchOutcome::Cancelled => SearchProgress {
+                status: "paused".to_string(),
+                grid: None,
+                message: "探索を一時停止しました".to_string(),
+            },
+        };
+        Ok(serde_wasm_bindgen::to_value(&progress)?)
+    }
+}
+
+impl SearchSnapshot {
+    /// ルールの本数と、探索中の全ての盤面の形が行数・列数と一致するかを検証する
+    /// 分岐点のマスの座標と、次に試す選択肢の番号が範囲内にあることも確かめる
+    fn check(&self) -> Result<(), String> {
+        let blank = vec![vec![CellState::Empty; self.cols]; self.rows];
+        let grids = self
+            .pending
+            .iter()
+            .chain(self.stack.iter().map(|frame| &frame.grid));
+        for grid in std::iter::once(&blank).chain(grids) {
+            check_dimensions(self.rows, self.cols, &self.row_rules, &self.col_rules, grid)?;
+        }
+        if let Some(frame) = self
+            .stack
+            .iter()
+            .find(|frame| frame.row >= self.rows || frame.col >= self.cols || frame.next > 2)
+        {
+            return Err(format!(
+                "探索の分岐点（{}行{}列）が不正です",
+                frame.row + 1,
+                frame.col + 1
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl ResumableSearch {
+    fn from_snapshot(snapshot: SearchSnapshot) -> Self {
+        let propagator = Propagator::new(
+            snapshot.rows,
+            snapshot.cols,
+            &snapshot.row_rules,
+            &snapshot.col_rules,
+        );
+        ResumableSearch {
+            snapshot,
+            propagator,
+        }
+    }
+}
+
+/// 曖昧なマスの検出結果をJavaScriptに返すためのデータ構造
+#[derive(Serialize, Deserialize)]
+pub struct AmbiguityResult {
+    solution_count: usize, // 見つかった解の数（2で打ち切るため、2は「2つ以上」を意味する）
+    cells: Vec<(usize, usize)>, // 解によって値が食い違うマスの座標（行, 列）
+}
+
+/// 論理では確定せず、解によって値が変わるマス（曖昧なマス）を特定する関数
+/// 解を2つ求めて比較し、値が食い違うマスの座標を返す
+/// 解が1つ以下の場合は、曖昧なマスは存在しないので空のリストを返す
+#[wasm_bindgen]
+pub fn ambiguous_cells(
+    rows: usize,
+    cols: usize,
+    row_rules_js: JsValue,
+    col_rules_js: JsValue,
+    initial_grid_js: JsValue,
+) -> Result<JsValue, JsValue> {
+    let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+    let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+    let initial_grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(initial_grid_js)?;
+
+    check_dimensions(rows, cols, &row_rules, &col_rules, &initial_grid)
+        .map_err(|e| JsValue::from_str(&e))?;
+    let propagator = Propagator::new(rows, cols, &row_rules, &col_rules);
+    let solutions = find_solutions(&propagator, initial_grid, 2);
+
+    let mut cells = Vec::new();
+    if let [first, second] = solutions.as_slice() {
+        for (r, (a, b)) in first.iter().zip(second).enumerate() {
+            for (c, (x, y)) in a.iter().zip(b).enumerate() {
+                if x != y {
+                    cells.push((r, c));
+                }
+            }
+        }
+    }
+
+    let result = AmbiguityResult {
+        solution_count: solutions.len(),
+        cells,
+    };
+    Ok(serde_wasm_bindgen::to_value(&result)?)
+}
+
+/// 一意解への正規化の結果をJavaScriptに返すためのデータ構造
+#[derive(Serialize, Deserialize)]
+pub struct CanonicalPuzzle {
+    grid: Vec<Vec<CellState>>,     // 追加したヒントを書き込んだ初期盤面
+    hints: Vec<CellPatch>,         // 追加したヒント（追加した順）
+    solution: Vec<Vec<CellState>>, // ヒントを追加した後の唯一の解
+}
+
+/// 解が複数あるパズルに、曖昧なマスをヒントとして書き込み、解が一意になるようにする関数
+/// 解を2つ求めて最初に食い違ったマスを、1つ目の解の値で固定する操作を、解が1つになるまで繰り返す
+/// 貪欲な近似なので、追加するヒントの数が最小になるとは限らない
+/// 既に一意解のパズルでは、ヒントを追加せずにそのまま返す
+///
+/// # Arguments
+/// * `rows` - 盤面の行数
+/// * `cols` - 盤面の列数
+/// * `row_rules_js` - 行ルールの配列
+/// * `col_rules_js` - 列ルールの配列
+/// * `initial_grid_js` - 初期盤面（既に開示されているマスがあれば、それも条件に含める）
+///
+/// # Returns
+/// * `Ok(JsValue)` - ヒントを追加した初期盤面と、追加したヒント、唯一の解（`CanonicalPuzzle`）
+/// * `Err(JsValue)` - 入力が不正な場合、またはルールを満たす解が存在しない場合のエラー
+#[wasm_bindgen]
+pub fn canonicalize_puzzle(
+    rows: usize,
+    cols: usize,
+    row_rules_js: JsValue,
+    col_rules_js: JsValue,
+    initial_grid_js: JsValue,
+) -> Result<JsValue, JsValue> {
+    let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+    let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+    let mut grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(initial_grid_js)?;
+
+    check_dimensions(rows, cols, &row_rules, &col_rules, &grid)
+        .map_err(|e| JsValue::from_str(&e))?;
+    let propagator = Propagator::new(rows, cols, &row_rules, &col_rules);
+
+    let mut hints = Vec::new();
+    loop {
+        let solutions = find_solutions(&propagator, grid.clone(), 2);
+        let (first, second) = match solutions.as_slice() {
+            [] => return Err(JsValue::from_str("ルールを満たす解が存在しません")),
+            [solution] => {
+                let result = CanonicalPuzzle {
+                    grid,
+                    hints,
+                    solution: solution.clone(),
+                };
+                return Ok(serde_wasm_bindgen::to_value(&result)?);
+            }
+            [first, second, ..] => (first, second),
+        };
+
+        // 2つの解で最初に食い違うマスを、1つ目の解の値で固定する
+        // 2つ目の解はこのヒントと矛盾するので、次の探索では必ず解の候補が減る
+        // 探索は分岐ごとに異なる盤面を返すので、食い違うマスは必ず存在する
+        let Some((row, col)) = (0..rows)
+            .flat_map(|r| (0..cols).map(move |c| (r, c)))
+            .find(|&(r, c)| first[r][c] != second[r][c])
+        else {
+            return Err(JsValue::from_str("2つの解の違いが見つかりませんでした"));
+        };
+        grid[row][col] = first[row][col];
+        hints.push(CellPatch {
+            row,
+            col,
+            state: first[row][col],
+        });
+    }
+}
+
+/// ヒントの削減結果をJavaScriptに返すためのデータ構造
+#[derive(Serialize, Deserialize)]
+pub struct MinimizedHints {
+    grid: Vec<Vec<CellState>>, // 削減後の初期盤面
+    removed: usize,            // 削除できたヒントの数
+    remaining: usize,          // 残ったヒントの数
+}
+
+/// 解の一意性を保ったまま、初期盤面のヒント（確定済みのマス）をできるだけ取り除く関数
+/// 左上から行優先の順にヒントを1つずつ外してみて、外しても解が1つに定まる場合だけ削除を確定する貪欲法を使う
+/// 削除の順序によって結果が変わるので、残るヒントの数が最小になるとは限らない
+/// ヒント1つごとに解を2つまで探索するため重いが、作問の最終仕上げに使うことを想定している
+///
+/// # Arguments
+/// * `rows` - 盤面の行数
+/// * `cols` - 盤面の列数
+/// * `row_rules_js` - 行ルールの配列
+/// * `col_rules_js` - 列ルールの配列
+/// * `initial_grid_js` - ヒントを書き込んだ初期盤面
+///
+/// # Returns
+/// * `Ok(JsValue)` - 削減後の初期盤面と削除したヒントの数（`MinimizedHints`）
+/// * `Err(JsValue)` - 入力が不正な場合、または初期盤面の時点で解が1つに定まらない場合のエラー
+#[wasm_bindgen]
+pub fn minimize_hints(
+    rows: usize,
+    cols: usize,
+    row_rules_js: JsValue,
+    col_rules_js: JsValue,
+    initial_grid_js: JsValue,
+) -> Result<JsValue, JsValue> {
+    let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+    let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+    let mut grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(initial_grid_js)?;
+
+    check_dimensions(rows, cols, &row_rules, &col_rules, &grid)
+        .map_err(|e| JsValue::from_str(&e))?;
+    let propagator = Propagator::new(rows, cols, &row_rules, &col_rules);
+    let is_unique =
+        |grid: &[Vec<CellState>]| find_solutions(&propagator, grid.to_vec(), 2).len() == 1;
+    if !is_unique(&grid) {
+        return Err(JsValue::from_str(
+            "初期盤面の時点で解が1つに定まらないため、ヒントを削減できません",
+        ));
+    }
+
+    let (mut removed, mut remaining) = (0, 0);
+    for r in 0..rows {
+        for c in 0..cols {
+            let hint = grid[r][c];
+            if hint == CellState::Empty {
+                continue;
+            }
+            grid[r][c] = CellState::Empty;
+            if is_unique(&grid) {
+                removed += 1;
+            } else {
+                // 外すと解が複数になるヒントは残す
+                grid[r][c] = hint;
+                remaining += 1;
+            }
+        }
+    }
+
+    let result = MinimizedHints {
+        grid,
+        removed,
+        remaining,
+    };
+    Ok(serde_wasm_bindgen::to_value(&result)?)
+}
+
+/// ルールを満たす完成盤面が存在するかどうかを判定する関数
+#[wasm_bindgen]
+pub fn has_solution(
+    rows: usize,
+    cols: usize,
+    row_rules_js: JsValue,
+    col_rules_js: JsValue,
+    initial_grid_js: JsValue,
+) -> Result<bool, JsValue> {
+    let witness =
+        has_solution_with_witness(rows, cols, row_rules_js, col_rules_js, initial_grid_js)?;
+    Ok(!witness.is_undefined())
+}
+
+/// 盤面を回転・鏡映・転置した8通りの形のうち、辞書順で最小のものを正規形として返す
+/// 行数・列数も比較の対象に含めるので、形の違う盤面同士が同じ正規形になることはない
+fn canonical_grid_key(grid: &[Vec<CellState>]) -> Vec<u8> {
+    let rows = grid.len();
+    let cols = grid.first().map_or(0, Vec::len);
+    let mut best: Option<Vec<u8>> = None;
+    for transposed in [false, true] {
+        let (height, width) = if transposed {
+            (cols, rows)
+        } else {
+            (rows, cols)
+        };
+        for mirror_h in [false, true] {
+            for mirror_v in [false, true] {
+                // 変換後の盤面の(r, c)が、元の盤面のどのマスに当たるかを求めて並べる
+                let mut key = Vec::with_capacity(16 + height * width);
+                key.extend_from_slice(&(height as u64).to_be_bytes());
+                key.extend_from_slice(&(width as u64).to_be_bytes());
+                for r in 0..height {
+                    for c in 0..width {
+                        let r = if mirror_v { height - 1 - r } else { r };
+                        let c = if mirror_h { width - 1 - c } else { c };
+                        let (src_r, src_c) = if transposed { (c, r) } else { (r, c) };
+                        key.push(grid[src_r][src_c] as u8);
+                    }
+                }
+                if best.as_ref().is_none_or(|best| key < *best) {
+                    best = Some(key);
+                }
+            }
+        }
+    }
+    best.unwrap_or_default()
+}
+
+/// ルールを満たす完成盤面の数を、`limit`個まで数える関数
+/// `unique_up_to_symmetry`を指定すると、回転・鏡映で互いに重なる解を同じ解とみなして数える
+/// 対称な絵のパズルでは、実質的に同じ絵が複数の解として数えられるのを防げる
+///
+/// # Arguments
+/// * `rows` - 盤面の行数
+/// * `cols` - 盤面の列数
+/// * `row_rules_js` - 行ルールの配列
+/// * `col_rules_js` - 列ルールの配列
+/// * `initial_grid_js` - 現在の盤面
+/// * `limit` - 数える解の上限（これに達したら探索を打ち切る）
+/// * `unique_up_to_symmetry` - `true`の場合、回転・鏡映で同じ形になる解を1つとして数える
+///
+/// # Returns
+/// * `Ok(usize)` - 解の数（`limit`と同じ値なら「`limit`個以上」を意味する）
+/// * `Err(JsValue)` - 入力の形式が不正な場合のエラー
+#[wasm_bindgen]
+pub fn count_solutions(
+    rows: usize,
+    cols: usize,
+    row_rules_js: JsValue,
+    col_rules_js: JsValue,
+    initial_grid_js: JsValue,
+    limit: usize,
+    unique_up_to_symmetry: bool,
+) -> Result<usize, JsValue> {
+    let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+    let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+    let initial_grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(initial_grid_js)?;
+
+    check_dimensions(rows, cols, &row_rules, &col_rules, &initial_grid)
+        .map_err(|e| JsValue::from_str(&e))?;
+    let propagator = Propagator::new(rows, cols, &row_rules, &col_rules);
+    if !unique_up_to_symmetry {
+        return Ok(find_solutions(&propagator, initial_grid, limit).len());
+    }
+
+    // 異なる正規形が`limit`個見つかるまで探索を続ける
+    let mut search = SolutionSearch::new(&propagator, initial_grid);
+    let mut classes: Vec<Vec<u8>> = Vec::new();
+    while classes.len() < limit {
+        match search.next_solution(&|| false) {
+            SearchOutcome::Solved(solution) => {
+                let key = canonical_grid_key(&solution);
+                if !classes.contains(&key) {
+                    classes.push(key);
+                }
+            }
+            SearchOutcome::Exhausted | SearchOutcome::Cancelled => break,
+        }
+    }
+    Ok(classes.len())
+}
+
+/// 解の存在判定に加えて、その根拠となる完成盤面（解の1例）を証明書として返す関数
+/// 呼び出し側は、返された盤面がルールを満たすことを独立に検証できる
+///
+/// # Returns
+/// * `Ok(JsValue)` - 解があればその完成盤面、なければ`undefined`
+/// * `Err(JsValue)` - 入力の形式が不正な場合のエラー
+#[wasm_bindgen]
+pub fn has_solution_with_witness(
+    rows: usize,
+    cols: usize,
+    row_rules_js: JsValue,
+    col_rules_js: JsValue,
+    initial_grid_js: JsValue,
+) -> Result<JsValue, JsValue> {
+    let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+    let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+    let initial_grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(initial_grid_js)?;
+
+    check_dimensions(rows, cols, &row_rules, &col_rules, &initial_grid)
+        .map_err(|e| JsValue::from_str(&e))?;
+    let propagator = Propagator::new(rows, cols, &row_rules, &col_rules);
+    // 探索で最初に見つかった解を、そのまま証明書として使う
+    let witness = find_solutions(&propagator, initial_grid, 1).pop();
+    Ok(serde_wasm_bindgen::to_value(&witness)?)
+}
+
+/// 盤面1つ分の進み具合の評価
+#[derive(Serialize, Deserialize)]
+pub struct ProgressScore {
+    determined: usize, // 確定済み（「空」以外）のマスの数
+    consistent: bool,  // 確定済みのマスを変えずに、ルールを満たす完成盤面まで到達できるかどうか
+}
+
+/// 2つの盤面の進み具合の比較結果をJavaScriptに返すためのデータ構造
+#[derive(Serialize, Deserialize)]
+pub struct ProgressComparison {
+    a: ProgressScore, // 1つ目の盤面の評価
+    b: ProgressScore, // 2つ目の盤面の評価
+    winner: String,   // 完成により近い盤面（"a"、"b"、または引き分けの"tie"）
+}
+
+/// 2つの途中の盤面のうち、どちらが完成により近いかを判定する関数
+/// 競争モードでのプレイヤー同士の比較や、解法の戦略同士の比較に使う
+/// 判定は次の順に行う
+/// 1. 完成盤面まで到達できる（誤りのない）盤面は、到達できない盤面より近い
+/// 2. どちらも到達できる場合は、確定済みのマスが多い盤面のほうが近い（同数なら引き分け）
+/// 3. どちらも到達できない場合は、どちらも完成に近づいていないとみなして引き分けにする
+///
+/// # Arguments
+/// * `a_js` - 1つ目の盤面
+/// * `b_js` - 2つ目の盤面
+/// * `row_rules_js` - 行ルールの配列（盤面の行数はこの本数になる）
+/// * `col_rules_js` - 列ルールの配列（盤面の列数はこの本数になる）
+///
+/// # Returns
+/// * `Ok(JsValue)` - 両方の盤面の評価と判定（`ProgressComparison`）
+/// * `Err(JsValue)` - 入力の形式が不正な場合、または盤面の形がルールと合わない場合のエラー
+#[wasm_bindgen]
+pub fn compare_progress(
+    a_js: JsValue,
+    b_js: JsValue,
+    row_rules_js: JsValue,
+    col_rules_js: JsValue,
+) -> Result<JsValue, JsValue> {
+    let a: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(a_js)?;
+    let b: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(b_js)?;
+    let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+    let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+
+    let (rows, cols) = (row_rules.len(), col_rules.len());
+    for grid in [&a, &b] {
+        check_dimensions(rows, cols, &row_rules, &col_rules, grid)
+            .map_err(|e| JsValue::from_str(&e))?;
+    }
+    let propagator = Propagator::new(rows, cols, &row_rules, &col_rules);
+
+    let score = |grid: Vec<Vec<CellState>>| ProgressScore {
+        determined: grid
+            .iter()
+            .flatten()
+            .filter(|&&cell| cell != CellState::Empty)
+            .count(),
+        consistent: !find_solutions(&propagator, grid, 1).is_empty(),
+    };
+    let (a, b) = (score(a), score(b));
+
+    let winner = match (a.consistent, b.consistent) {
+        (true, false) => "a",
+        (false, true) => "b",
+        (false, false) => "tie",
+        (true, true) => match a.determined.cmp(&b.determined) {
+            std::cmp::Ordering::Greater => "a",
+            std::cmp::Ordering::Less => "b",
+            std::cmp::Ordering::Equal => "tie",
+        },
+    };
+    let result = ProgressComparison {
+        a,
+        b,
+        winner: winner.to_string(),
+    };
+    Ok(serde_wasm_bindgen::to_value(&result)?)
+}
+
+/// ルール候補の探索で、1本のラインについて保持する情報
+struct CandidateLine {
+    axis: Axis,
+    index: usize,
+    rules: Vec<(Vec<usize>, CompiledRule)>, // ルール候補と、そのコンパイル済みパターン
+}
+
+/// ルール候補の組み合わせを深さ優先で探索する再帰関数
+/// 各ラインで盤面と矛盾する候補を取り除き、全候補で共通して確定するマスを書き込む枝刈りを繰り返してから、
+/// 候補が最も少ないラインで分岐する
+///
+/// # Arguments
+/// * `lines` - 全ラインの候補（行の後に列が並ぶ）
+/// * `remaining` - 各ラインで、まだ残っている候補の番号
+/// * `grid` - 現在の盤面
+///
+/// # Returns
+/// * `Some((choice, solution))` - 解が成立した候補の組み合わせ（各ラインで選んだ候補の番号）と、その解
+/// * `None` - どの組み合わせでも解が成立しない場合
+fn search_rule_candidates(
+    rows: usize,
+    cols: usize,
+    lines: &[CandidateLine],
+    mut remaining: Vec<Vec<usize>>,
+    mut grid: Vec<Vec<CellState>>,
+) -> Option<(Vec<usize>, Vec<Vec<CellState>>)> {
+    // 1. 盤面に変化がなくなるまで、候補の絞り込みと共通マスの書き込みを繰り返す
+    loop {
+        let mut changed = false;
+        for (line, candidates) in lines.iter().zip(remaining.iter_mut()) {
+            let current = get_line(&grid, line.axis, line.index);
+            let mut common: Option<Vec<CellState>> = None;
+            candidates.retain(|&k| match line.rules[k].1.apply(&current) {
+                Ok(result) => {
+                    // 残った候補全てで同じ値になるマスだけを残す
+                    common = Some(match common.take() {
+                        None => result,
+                        Some(prev) => prev
+                            .iter()
+                            .zip(&result)
+                            .map(|(&a, &b)| if a == b { a } else { CellState::Empty })
+                            .collect(),
+                    });
+                    true
+                }
+                Err(_) => false,
+            });
+            // 盤面と矛盾しない候補が一つもなければ、この分岐は行き止まり
+            let common = common?;
+            if common != current {
+                set_line(&mut grid, line.axis, line.index, &common);
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    // 2. 候補が2つ以上残っているラインのうち、最も候補の少ないラインで分岐する
+    let branch = (0..lines.len())
+        .filter(|&i| remaining[i].len() > 1)
+        .min_by_key(|&i| remaining[i].len());
+    match branch {
+        None => {
+            // 全ラインの候補が1つに決まったら、そのルールで解が存在するかを確かめる
+            let choice: Vec<usize> = remaining.iter().map(|c| c[0]).collect();
+            let rules: Vec<Vec<usize>> = lines
+                .iter()
+                .zip(&choice)
+                .map(|(line, &k)| line.rules[k].0.clone())
+                .collect();
+            let propagator = Propagator::new(rows, cols, &rules[..rows], &rules[rows..]);
+            let solution = find_solutions(&propagator, grid, 1).pop()?;
+            Some((choice, solution))
+        }
+        Some(i) => remaining[i].clone().into_iter().find_map(|k| {
+            let mut next = remaining.clone();
+            next[i] = vec![k];
+            search_rule_candidates(rows, cols, lines, next, grid.clone())
+        }),
+    }
+}
+
+/// ルール候補の探索結果をJavaScriptに返すためのデータ構造
+#[derive(Serialize, Deserialize)]
+pub struct RuleCandidatesResult {
+    found: bool,                // 解が成立する組み合わせが見つかったかどうか
+    row_rules: Vec<Vec<usize>>, // 採用した行ルール（見つからなかった場合は空）
+    col_rules: Vec<Vec<usize>>, // 採用した列ルール（見つからなかった場合は空）
+    grid: Vec<Vec<CellState>>,  // 採用したルールでの解（見つからなかった場合は呼び出し時点の盤面）
+    message: String,            // ユーザーに表示するメッセージ
+}
+
+/// 各ラインに複数のルール候補が与えられたとき、解が成立する組み合わせを探す関数
+/// OCRの読み取り誤りなどで候補が複数ある場合に、パズルとして成立するものを自動で選び出す
+///
+/// # Arguments
+/// * `rows` - 盤面の行数
+/// * `cols` - 盤面の列数
+/// * `row_candidates_js` - 各行のルール候補の配列（例: `[[[1, 2], [1, 3]], [[4]], ...]`）
+/// * `col_candidates_js` - 各列のルール候補の配列
+/// * `initial_grid_js` - 現在の盤面
+///
+/// # Returns
+/// * `Ok(JsValue)` - 探索結果（`RuleCandidatesResult`）
+/// * `Err(JsValue)` - 入力の形式が不正な場合や、候補が1つもないラインがある場合のエラー
+#[wasm_bindgen]
+pub fn solve_with_rule_candidates(
+    rows: usize,
+    cols: usize,
+    row_candidates_js: JsValue,
+    col_candidates_js: JsValue,
+    initial_grid_js: JsValue,
+) -> Result<JsValue, JsValue> {
+    let row_candidates: Vec<Vec<Vec<usize>>> = serde_wasm_bindgen::from_value(row_candidates_js)?;
+    let col_candidates: Vec<Vec<Vec<usize>>> = serde_wasm_bindgen::from_value(col_candidates_js)?;
+    let initial_grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(initial_grid_js)?;
+    if row_candidates.len() != rows || col_candidates.len() != cols {
+        return Err(JsValue::from_str(
+            "ルール候補の数が盤面サイズと一致しません",
+        ));
+    }
+
+    let mut lines = Vec::new();
+    for (axis, candidates, size) in [
+        (Axis::Row, &row_candidates, cols),
+        (Axis::Column, &col_candidates, rows),
+    ] {
+        for (index, rules) in candidates.iter().enumerate() {
+            if rules.is_empty() {
+                return Err(JsValue::from_str(&format!(
+                    "{} {}: ルール候補がありません",
+                    axis.label(),
+                    index + 1
+                )));
+            }
+            let rules = rules
+                .iter()
+                .map(|rule| (rule.clone(), compile_rule(size, rule)))
+                .collect();
+            lines.push(CandidateLine { axis, index, rules });
+        }
+    }
+    let remaining = lines
+        .iter()
+        .map(|line| (0..line.rules.len()).collect())
+        .collect();
+
+    let result = match search_rule_candidates(rows, cols, &lines, remaining, initial_grid.clone()) {
+        Some((choice, grid)) => {
+            let mut rules = lines
+                .iter()
+                .zip(&choice)
+                .map(|(line, &k)| line.rules[k].0.clone());
+            RuleCandidatesResult {
+                found: true,
+                row_rules: rules.by_ref().take(rows).collect(),
+                col_rules: rules.collect(),
+                grid,
+                message: "解が成立するルールの組み合わせが見つかりました".to_string(),
+            }
+        }
+        None => RuleCandidatesResult {
+            found: false,
+            row_rules: Vec::new(),
+            col_rules: Vec::new(),
+            grid: initial_grid,
+            message: "どの組み合わせでも解が成立しませんでした".to_string(),
+        },
+    };
+    Ok(serde_wasm_bindgen::to_value(&result)?)
+}
+
+// --- 盤面の出力ユーティリティ ---
+
+/// 盤面をRGBAのピクセル配列（幅×高さ×4バイト）に変換する関数
+/// 1マスを1ピクセルとして扱い、JavaScript側で`ImageData`に包んでそのまま`putImageData`できる並びで返す
+///
+/// # Arguments
+/// * `grid_js` - 盤面の状態（`CellState`の2次元配列）
+/// * `fill_color` - 「塗り」のマスの色（`0xRRGGBBAA`形式）
+/// * `bg_color` - それ以外のマスの色（`0xRRGGBBAA`形式）
+///
+/// # Returns
+/// * `Ok(Vec<u8>)` - 行優先で並んだRGBAのバイト列（JavaScript側では`Uint8Array`になる）
+/// * `Err(JsValue)` - 盤面の形式が不正な場合のエラー
+#[wasm_bindgen]
+pub fn export_rgba(grid_js: JsValue, fill_color: u32, bg_color: u32) -> Result<Vec<u8>, JsValue> {
+    let grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(grid_js)?;
+
+    // 行ごとに長さが違う盤面は画像にできない
+    let width = grid_width(&grid).map_err(|e| JsValue::from_str(&e))?;
+
+    // `0xRRGGBBAA`をビッグエンディアンで分解すると、そのまま[R, G, B, A]の並びになる
+    let fill_rgba = fill_color.to_be_bytes();
+    let bg_rgba = bg_color.to_be_bytes();
+
+    let mut pixels = Vec::with_capacity(grid.len() * width * 4);
+    for row in &grid {
+        for &cell in row {
+            let rgba = if cell == CellState::Filled {
+                &fill_rgba
+            } else {
+                &bg_rgba
+            };
+            pixels.extend_from_slice(rgba);
+        }
+    }
+    Ok(pixels)
+}
+
+/// ルールから求めた各ラインの密度をJavaScriptに返すためのデータ構造
+#[derive(Serialize, Deserialize)]
+pub struct RuleDensity {
+    rows: Vec<f64>, // 各行の密度（0.0〜1.0）
+    cols: Vec<f64>, // 各列の密度（0.0〜1.0）
+}
+
+/// 各行・各列のルールから、そのラインの「塗り」のマスの割合（密度）を求める関数
+/// 密度はブロック長の合計÷ラインの長さで、盤面を解かなくても計算できる
+/// 密度が極端に偏ったパズルは解きにくいことが多いので、作問のバランス調整やヒートマップ表示に使う
+/// ラインの長さが0の場合、密度は0.0とする
+///
+/// # Arguments
+/// * `rows` - 盤面の行数
+/// * `cols` - 盤面の列数
+/// * `row_rules_js` - 行ルールの配列
+/// * `col_rules_js` - 列ルールの配列
+///
+/// # Returns
+/// * `Ok(JsValue)` - 行方向・列方向の密度（`RuleDensity`）
+/// * `Err(JsValue)` - 入力が不正な場合、またはルールの本数が行数・列数と一致しない場合のエラー
+#[wasm_bindgen]
+pub fn rule_density(
+    rows: usize,
+    cols: usize,
+    row_rules_js: JsValue,
+    col_rules_js: JsValue,
+) -> Result<JsValue, JsValue> {
+    let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+    let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+    let blank = vec![vec![CellState::Empty; cols]; rows];
+    check_dimensions(rows, cols, &row_rules, &col_rules, &blank)
+        .map_err(|e| JsValue::from_str(&e))?;
+
+    // 行ルールは列数、列ルールは行数がラインの長さになる
+    let densities = |rules: &[Vec<usize>], size: usize| {
+        rules
+            .iter()
+            .map(|rule| {
+                if size == 0 {
+                    0.0
+                } else {
+                    rule.iter().sum::<usize>() as f64 / size as f64
+                }
+            })
+            .collect()
+    };
+    let density = RuleDensity {
+        rows: densities(&row_rules, cols),
+        cols: densities(&col_rules, rows),
+    };
+    Ok(serde_wasm_bindgen::to_value(&density)?)
+}
+
+/// 盤面を`block_size`×`block_size`マスのブロックごとの塗り密度にダウンサンプリングする関数
+/// 各ブロックの値は、ブロック内の「塗り」のマスの割合（0.0〜1.0）で、グレースケールのプレビューにそのまま使える
+/// 盤面の大きさが`block_size`で割り切れない場合、右端・下端の余りは小さなブロックとして残し、
+/// そのブロックに含まれるマスの数だけで平均をとる（余りを切り捨てたり、空のマスで埋めたりはしない）
+///
+/// # Arguments
+/// * `grid_js` - 盤面の状態（`CellState`の2次元配列）
+/// * `block_size` - 1ブロックの一辺のマス数（1以上）
+///
+/// # Returns
+/// * `Ok(JsValue)` - ブロックごとの塗り密度の2次元配列（`ceil(行数 / block_size)`行 × `ceil(列数 / block_size)`列）
+/// * `Err(JsValue)` - 盤面の形式が不正な場合、または`block_size`が0の場合のエラー
+#[wasm_bindgen]
+pub fn density_map(grid_js: JsValue, block_size: usize) -> Result<JsValue, JsValue> {
+    let grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(grid_js)?;
+    let width = grid_width(&grid).map_err(|e| JsValue::from_str(&e))?;
+    if block_size == 0 {
+        return Err(JsValue::from_str(
+            "ブロックの大きさは1以上を指定してください",
+        ));
+    }
+
+    let density: Vec<Vec<f64>> = grid
+        .chunks(block_size)
+        .map(|block_rows| {
+            (0..width)
+                .step_by(block_size)
+                .map(|left| {
+                    let right = (left + block_size).min(width);
+                    let filled = block_rows
+                        .iter()
+                        .flat_map(|row| &row[left..right])
+                        .filter(|&&cell| cell == CellState::Filled)
+                        .count();
+                    filled as f64 / (block_rows.len() * (right - left)) as f64
+                })
+                .collect()
+        })
+        .collect();
+    Ok(serde_wasm_bindgen::to_value(&density)?)
+}
+
+/// 「塗り」の領域の輪郭になるマス（境界のマス）を抽出する関数
+/// 隣接するマスのうち1つでも「塗り」でないマス（「空」または「×」）があれば、その「塗り」のマスを境界とみなす
+/// 盤面の外側は「塗り」でないものとして扱うので、盤面の端に接する「塗り」のマスも境界になる
+///
+/// # Arguments
+/// * `grid_js` - 盤面の状態（`CellState`の2次元配列）
+/// * `diagonal` - `true`の場合は斜めを含む8近傍、`false`の場合は上下左右の4近傍で隣接を判定する
+///
+/// # Returns
+/// * `Ok(JsValue)` - 境界のマスの座標（行, 列）のリスト（行優先の順）
+/// * `Err(JsValue)` - 盤面の形式が不正な場合のエラー
+#[wasm_bindgen]
+pub fn extract_outline(grid_js: JsValue, diagonal: bool) -> Result<JsValue, JsValue> {
+    let grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(grid_js)?;
+    grid_width(&grid).map_err(|e| JsValue::from_str(&e))?;
+
+    // 先頭の4つが上下左右、残りの4つが斜めの隣接
+    const NEIGHBORS: [(isize, isize); 8] = [
+        (-1, 0),
+        (1, 0),
+        (0, -1),
+        (0, 1),
+        (-1, -1),
+        (-1, 1),
+        (1, -1),
+        (1, 1),
+    ];
+    let offsets = if diagonal {
+        &NEIGHBORS[..]
+    } else {
+        &NEIGHBORS[..4]
+    };
+    let is_filled = |r: usize, c: usize, dr: isize, dc: isize| {
+        let (Some(r), Some(c)) = (r.checked_add_signed(dr), c.checked_add_signed(dc)) else {
+            return false;
+        };
+        grid.get(r).and_then(|row| row.get(c)) == Some(&CellState::Filled)
+    };
+
+    let mut outline: Vec<(usize, usize)> = Vec::new();
+    for (r, row) in grid.iter().enumerate() {
+        for (c, &cell) in row.iter().enumerate() {
+            if cell == CellState::Filled && offsets.iter().any(|&(dr, dc)| !is_filled(r, c, dr, dc))
+            {
+                outline.push((r, c));
+            }
+        }
+    }
+    Ok(serde_wasm_bindgen::to_value(&outline)?)
+}
+
+/// 「塗り」の領域の輪郭を表す閉じた多角形
+#[derive(Serialize, Deserialize)]
+pub struct Contour {
+    points: Vec<(usize, usize)>, // 頂点の座標（x = 列, y = 行）の並び（最後の頂点から最初の頂点に戻って閉じる）
+    hole: bool,                  // 「塗り」の領域に囲まれた穴の輪郭か
+}
+
+/// 盤面の「塗り」の領域の外周を、閉じた多角形の頂点列として抽出する関数
+/// 上下左右に隣接する「塗り」のマスを1つの領域にまとめ、マスの角を頂点とする輪郭を返す
+/// 頂点はまっすぐ続く辺の途中では打たず、曲がり角だけに置く
+/// 外周は時計回り、穴（「塗り」に囲まれた「塗り」でないマス）の輪郭は反時計回りに並ぶので、
+/// 全ての輪郭を1つのSVGの`path`要素にまとめて描けば、穴が抜けた絵になる
+/// 斜めにだけ接する「塗り」のマスは、別々の領域として扱う
+///
+/// # Arguments
+/// * `grid_js` - 盤面の状態（`CellState`の2次元配列）
+///
+/// # Returns
+/// * `Ok(JsValue)` - 輪郭（`Contour`）の配列
+/// * `Err(JsValue)` - 盤面の形式が不正な場合のエラー
+#[wasm_bindgen]
+pub fn trace_contours(grid_js: JsValue) -> Result<JsValue, JsValue> {
+    let grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(grid_js)?;
+    let width = grid_width(&grid).map_err(|e| JsValue::from_str(&e))?;
+    Ok(serde_wasm_bindgen::to_value(&contours_of(&grid, width))?)
+}
+
+/// `trace_contours`の本体で、幅`width`の盤面の輪郭を求める
+fn contours_of(grid: &[Vec<CellState>], width: usize) -> Vec<Contour> {
+    let is_filled = |r: isize, c: isize| {
+        r >= 0
+            && c >= 0
+            && grid.get(r as usize).and_then(|row| row.get(c as usize)) == Some(&CellState::Filled)
+    };
+
+    // 「塗り」のマスと「塗り」でないマスの境目を、「塗り」が右手側になる向きの辺として集める
+    // 頂点`(x, y)`の番号は`y * (width + 1) + x`で、頂点ごとにそこから出る辺を記録する
+    let vertex = |x: usize, y: usize| y * (width + 1) + x;
+    let mut edges: Vec<((usize, usize), (isize, isize))> = Vec::new(); // （始点, 向き）
+    let mut outgoing: Vec<Vec<usize>> = vec![Vec::new(); (grid.len() + 1) * (width + 1)];
+    for (r, row) in grid.iter().enumerate() {
+        for (c, &cell) in row.iter().enumerate() {
+            if cell != CellState::Filled {
+                continue;
+            }
+            let (ri, ci) = (r as isize, c as isize);
+            // 上、右、下、左の辺（マスの周りを時計回りに進む向き）
+            let sides = [
+                ((ri - 1, ci), (c, r), (1, 0)),
+                ((ri, ci + 1), (c + 1, r), (0, 1)),
+                ((ri + 1, ci), (c + 1, r + 1), (-1, 0)),
+                ((ri, ci - 1), (c, r + 1), (0, -1)),
+            ];
+            for ((nr, nc), start, direction) in sides {
+                if !is_filled(nr, nc) {
+                    outgoing[vertex(start.0, start.1)].push(edges.len());
+                    edges.push((start, direction));
+                }
+            }
+        }
+    }
+
+    // 辺をたどって閉じた輪郭にする
+    // 1つの頂点から2本の辺が出る（斜めにだけ接する）場合は、右折を優先して領域を分ける
+    let mut used = vec![false; edges.len()];
+    let mut contours = Vec::new();
+    for first in 0..edges.len() {
+        if used[first] {
+            continue;
+        }
+        let mut points = Vec::new();
+        let mut current = first;
+        loop {
+            used[current] = true;
+            let ((x, y), (dx, dy)) = edges[current];
+            let (end_x, end_y) = (x.wrapping_add_signed(dx), y.wrapping_add_signed(dy));
+            let candidates = &outgoing[vertex(end_x, end_y)];
+            let turn_priority = [(-dy, dx), (dx, dy), (dy, -dx)]; // 右折、直進、左折
+            // 境界の辺は必ず閉じた輪郭になるが、続く辺が見つからない場合は、そこまでの頂点で輪郭を閉じる
+            let Some(next) = turn_priority.iter().find_map(|&turn| {
+                candidates
+                    .iter()
+                    .copied()
+                    .find(|&e| !used[e] && edges[e].1 == turn)
+                    .or_else(|| {
+                        candidates
+                            .iter()
+                            .copied()
+                            .find(|&e| e == first && edges[e].1 == turn)
+                    })
+            }) else {
+                break;
+            };
+            // 向きが変わる頂点だけを残す
+            if edges[next].1 != (dx, dy) {
+                points.push((end_x, end_y));
+            }
+            if next == first {
+                break;
+            }
+            current = next;
+        }
+        // 頂点の並びの符号付き面積（y軸が下向きなので、時計回りなら正）で穴かどうかを判定する
+        let area: isize = points
+            .iter()
+            .zip(points.iter().cycle().skip(1))
+            .map(|(&(x1, y1), &(x2, y2))| x1 as isize * y2 as isize - x2 as isize * y1 as isize)
+            .sum();
+        contours.push(Contour {
+            points,
+            hole: area < 0,
+        });
+    }
+    contours
+}
+
+/// `artwork_complexity`で使う指標の重み（孤立した「塗り」のマスが多いほどノイジー）
+const ARTWORK_WEIGHT_ISOLATION: f64 = 0.35;
+/// `artwork_complexity`で使う指標の重み（「塗り」の領域が細かく分かれているほどノイジー）
+const ARTWORK_WEIGHT_FRAGMENTATION: f64 = 0.25;
+/// `artwork_complexity`で使う指標の重み（輪郭の曲がり角が多いほどノイジー）
+const ARTWORK_WEIGHT_JAGGEDNESS: f64 = 0.3;
+/// `artwork_complexity`で使う指標の重み（塗り密度が低いほど絵として認識しにくい）
+const ARTWORK_WEIGHT_SPARSITY: f64 = 0.1;
+/// スコアがこの値未満なら"clear"
+const ARTWORK_CLEAR_THRESHOLD: f64 = 0.3;
+/// スコアがこの値未満なら"moderate"、以上なら"noisy"
+const ARTWORK_MODERATE_THRESHOLD: f64 = 0.55;
+
+/// 絵の複雑度の評価結果
+#[derive(Serialize, Deserialize)]
+pub struct ArtworkComplexity {
+    score: f64,            // 各指標を重み付きで平均した複雑度（0.0〜1.0、大きいほどノイジー）
+    label: String,         // "clear"、"moderate"、"noisy"（「塗り」のマスがなければ"empty"）
+    fill_density: f64,     // 盤面全体に対する「塗り」のマスの割合
+    components: usize,     // 上下左右につながった「塗り」の領域の数
+    holes: usize,          // 「塗り」の領域に囲まれた穴の数
+    isolated_cells: usize, // 上下左右のどこにも「塗り」が隣接しない「塗り」のマスの数
+    perimeter: usize,      // 「塗り」の領域の輪郭の長さ（マスの辺の数）
+    corners: usize,        // 輪郭の曲がり角の数
+    isolation: f64,        // 孤立した「塗り」のマスの割合
+    fragmentation: f64,    // 領域の分かれ具合（1つの領域なら0.0、全てのマスがばらばらなら1.0）
+    jaggedness: f64,       // 輪郭の長さに対する曲がり角の数の割合（長方形に近いほど0.0に近い）
+}
+
+/// 盤面の「塗り」の領域から、絵としての複雑度（認識しにくさ）を評価する関数
+/// 孤立した点、細かく分かれた領域、ギザギザの輪郭、低い塗り密度を「ノイジー」な特徴として重み付けして1つのスコアにまとめる
+/// 作問時の目安のための評価で、絵の良し悪しを厳密に判定するものではない
+///
+/// # Arguments
+/// * `grid_js` - 盤面の状態（`CellState`の2次元配列、「塗り」のマスを絵とみなす）
+///
+/// # Returns
+/// * `Ok(JsValue)` - スコアと各指標（`ArtworkComplexity`）
+/// * `Err(JsValue)` - 盤面の形式が不正な場合のエラー
+#[wasm_bindgen]
+pub fn artwork_complexity(grid_js: JsValue) -> Result<JsValue, JsValue> {
+    let grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(grid_js)?;
+    let width = grid_width(&grid).map_err(|e| JsValue::from_str(&e))?;
+    let is_filled =
+        |r: usize, c: usize| grid.get(r).and_then(|row| row.get(c)) == Some(&CellState::Filled);
+    // 上下左右の隣接マスの座標（盤面の外側は除く）
+    let neighbors = |r: usize, c: usize| {
+        [(-1, 0), (1, 0), (0, -1), (0, 1)]
+            .into_iter()
+            .filter_map(move |(dr, dc)| {
+                Some((r.checked_add_signed(dr)?, c.checked_add_signed(dc)?))
+            })
+    };
+
+    let (mut filled, mut isolated_cells, mut perimeter, mut components) = (0, 0, 0, 0usize);
+    let mut visited = vec![vec![false; width]; grid.len()];
+    for r in 0..grid.len() {
+        for c in 0..width {
+            if !is_filled(r, c) {
+                continue;
+            }
+            filled += 1;
+            let filled_neighbors = neighbors(r, c)
+                .filter(|&(nr, nc)| is_filled(nr, nc))
+                .count();
+            if filled_neighbors == 0 {
+                isolated_cells += 1;
+            }
+            perimeter += 4 - filled_neighbors;
+
+            // 未訪問の「塗り」のマスから塗りつぶして、新しい領域を数える
+            if visited[r][c] {
+                continue;
+            }
+            components += 1;
+            visited[r][c] = true;
+            let mut stack = vec![(r, c)];
+            while let Some((sr, sc)) = stack.pop() {
+                for (nr, nc) in neighbors(sr, sc) {
+                    if is_filled(nr, nc) && !visited[nr][nc] {
+                        visited[nr][nc] = true;
+                        stack.push((nr, nc));
+                    }
+                }
+            }
+        }
+    }
+    let contours = contours_of(&grid, width);
+    let holes = contours.iter().filter(|contour| contour.hole).count();
+    let corners: usize = contours.iter().map(|contour| contour.points.len()).sum();
+
+    let cells = grid.len() * width;
+    let ratio = |count: usize, total: usize| {
+        if total == 0 {
+            0.0
+        } else {
+            count as f64 / total as f64
+        }
+    };
+    let fill_density = ratio(filled, cells);
+    let isolation = ratio(isolated_cells, filled);
+    let fragmentation = ratio(components.saturating_sub(1), filled.saturating_sub(1));
+    let jaggedness = ratio(corners, perimeter);
+
+    let (score, label) = if filled == 0 {
+        (0.0, "empty")
+    } else {
+        let score = (ARTWORK_WEIGHT_ISOLATION * isolation
+            + ARTWORK_WEIGHT_FRAGMENTATION * fragmentation
+            + ARTWORK_WEIGHT_JAGGEDNESS * jaggedness
+            + ARTWORK_WEIGHT_SPARSITY * (1.0 - fill_density))
+            / (ARTWORK_WEIGHT_ISOLATION
+                + ARTWORK_WEIGHT_FRAGMENTATION
+                + ARTWORK_WEIGHT_JAGGEDNESS
+                + ARTWORK_WEIGHT_SPARSITY);
+        let label = if score < ARTWORK_CLEAR_THRESHOLD {
+            "clear"
+        } else if score < ARTWORK_MODERATE_THRESHOLD {
+            "moderate"
+        } else {
+            "noisy"
+        };
+        (score, label)
+    };
+
+    Ok(serde_wasm_bindgen::to_value(&ArtworkComplexity {
+        score,
+        label: label.to_string(),
+        fill_density,
+        components,
+        holes,
+        isolated_cells,
+        perimeter,
+        corners,
+        isolation,
+        fragmentation,
+        jaggedness,
+    })?)
+}
+
+/// 余白を除去した盤面をJavaScriptに返すためのデータ構造
+#[derive(Serialize, Deserialize)]
+pub struct CropResult {
+    grid: Vec<Vec<CellState>>, // 「塗り」のマスを囲む最小の矩形で切り出した盤面
+    row_rules: Vec<Vec<usize>>, // 切り出した盤面から再計算した行ルール
+    col_rules: Vec<Vec<usize>>, // 切り出した盤面から再計算した列ルール
+    top: usize,                // 切り出した範囲の、元の盤面での最上行
+    left: usize,               // 切り出した範囲の、元の盤面での最左列
+}
+
+/// 盤面の周囲にある「塗り」のない行・列を取り除く関数
+/// 「塗り」のマスのバウンディングボックスだけを切り出し、ルールも切り出した盤面に合わせて再計算する
+/// 「塗り」のマスが1つもない盤面は、0x0の盤面（`top`、`left`は0）として返す
+///
+/// # Arguments
+/// * `grid_js` - 盤面の状態（`CellState`の2次元配列）
+///
+/// # Returns
+/// * `Ok(JsValue)` - 切り出した盤面とルール、元の盤面での位置
+/// * `Err(JsValue)` - 盤面の形式が不正な場合のエラー
+#[wasm_bindgen]
+pub fn crop_grid(grid_js: JsValue) -> Result<JsValue, JsValue> {
+    let grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(grid_js)?;
+    grid_width(&grid).map_err(|e| JsValue::from_str(&e))?;
+
+    // 「塗り」のマスを囲む最小の矩形（上端, 下端, 左端, 右端）を求める
+    let filled_cells = grid.iter().enumerate().flat_map(|(r, row)| {
+        row.iter()
+            .enumerate()
+            .filter(|&(_, &cell)| cell == CellState::Filled)
+            .map(move |(c, _)| (r, c))
+    });
+    let bounds = filled_cells.fold(None, |bounds, (r, c)| match bounds {
+        None => Some((r, r, c, c)),
+        Some((top, bottom, left, right)) => {
+            Some((top.min(r), bottom.max(r), left.min(c), right.max(c)))
+        }
+    });
+
+    let (cropped, top, left) = match bounds {
+        None => (Vec::new(), 0, 0),
+        Some((top, bottom, left, right)) => (
+            grid[top..=bottom]
+                .iter()
+                .map(|row| row[left..=right].to_vec())
+                .collect(),
+            top,
+            left,
+        ),
+    };
+    let (row_rules, col_rules) = rules_from_grid(&cropped);
+
+    let result = CropResult {
+        grid: cropped,
+        row_rules,
+        col_rules,
+        top,
+        left,
+    };
+    Ok(serde_wasm_bindgen::to_value(&result)?)
+}
+
+// --- パズルの書き出し ---
+
+/// ルールや盤面のような数値の2次元配列を、JSONの配列表記（例: `[[1,2],[],[3]]`）に変換する
+fn nested_to_json(values: &[Vec<usize>]) -> String {
+    let lines: Vec<String> = values
+        .iter()
+        .map(|line| {
+            let numbers: Vec<String> = line.iter().map(usize::to_string).collect();
+            format!("[{}]", numbers.join(","))
+        })
+        .collect();
+    format!("[{}]", lines.join(","))
+}
+
+/// パズルを他のノノグラムツールと交換できるJSON文字列に変換する関数
+/// 形式ごとにフィールド名や盤面の表し方が異なるため、その差をここで吸収する
+///
+/// * `"native"` - `{"rows", "cols", "row_rules", "col_rules", "grid"}`（`grid`は`CellState`の数値）
+/// * `"webpbn"` - `{"width", "height", "rows", "columns", "goal"}`（`goal`は`#`が塗り、`.`がそれ以外の行ごとの文字列）
+///
+/// # Arguments
+/// * `rows` - 盤面の行数
+/// * `cols` - 盤面の列数
+/// * `row_rules_js` - 行ルールの配列
+/// * `col_rules_js` - 列ルールの配列
+/// * `grid_js` - 解答の盤面（省略可、`undefined`や`null`なら盤面のフィールドを出力しない）
+/// * `format` - 出力形式（`"native"`または`"webpbn"`）
+///
+/// # Returns
+/// * `Ok(String)` - JSON文字列
+/// * `Err(JsValue)` - 入力の形式が不正な場合や、未対応の形式を指定した場合のエラー
+#[wasm_bindgen]
+pub fn export_puzzle_json(
+    rows: usize,
+    cols: usize,
+    row_rules_js: JsValue,
+    col_rules_js: JsValue,
+    grid_js: JsValue,
+    format: &str,
+) -> Result<String, JsValue> {
+    let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+    let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+    let grid: Option<Vec<Vec<CellState>>> = if grid_js.is_undefined() || grid_js.is_null() {
+        None
+    } else {
+        Some(serde_wasm_bindgen::from_value(grid_js)?)
+    };
+    // 空ルールの表記揺れ（`[0]`など）は、どの形式でも`[]`に揃える
+    let row_rules: Vec<Vec<usize>> = row_rules.iter().map(|rule| normalize_rule(rule)).collect();
+    let col_rules: Vec<Vec<usize>> = col_rules.iter().map(|rule| normalize_rule(rule)).collect();
+    let empty = vec![vec![CellState::Empty; cols]; rows];
+    check_dimensions(
+        rows,
+        cols,
+        &row_rules,
+        &col_rules,
+        grid.as_ref().unwrap_or(&empty),
+    )
+    .map_err(|e| JsValue::from_str(&e))?;
+
+    match format {
+        "native" => {
+            let mut json = format!(
+                "{{\"rows\":{},\"cols\":{},\"row_rules\":{},\"col_rules\":{}",
+                rows,
+                cols,
+                nested_to_json(&row_rules),
+                nested_to_json(&col_rules)
+            );
+            if let Some(grid) = &grid {
+                let values: Vec<Vec<usize>> = grid
+                    .iter()
+                    .map(|row| row.iter().map(|&cell| cell as usize).collect())
+                    .collect();
+                json.push_str(&format!(",\"grid\":{}", nested_to_json(&values)));
+            }
+            json.push('}');
+            Ok(json)
+        }
+        "webpbn" => {
+            let mut json = format!(
+                "{{\"width\":{},\"height\":{},\"rows\":{},\"columns\":{}",
+                cols,
+                rows,
+                nested_to_json(&row_rules),
+                nested_to_json(&col_rules)
+            );
+            if let Some(grid) = &grid {
+                let lines: Vec<String> = grid
+                    .iter()
+                    .map(|row| {
+                        let line: String = row
+                            .iter()
+                            .map(|&cell| if cell == CellState::Filled { '#' } else { '.' })
+                            .collect();
+                        format!("\"{}\"", line)
+                    })
+                    .collect();
+                json.push_str(&format!(",\"goal\":[{}]", lines.join(",")));
+            }
+            json.push('}');
+            Ok(json)
+        }
+        _ => Err(JsValue::from_str(&format!(
+            "未対応の形式です: {}（\"native\"または\"webpbn\"を指定してください）",
+            format
+        ))),
+    }
+}
+
+/// ヒント数字と空の盤面だけを描いた、印刷用のパズル用紙をSVGで出力する関数
+/// 行ヒントは盤面の左に右詰めで、列ヒントは盤面の上に下詰めで並べ（`hint_layout`と同じ配置）、
+/// ヒント領域の大きさは最も長いルールに合わせる
+/// 盤面の罫線は5マスごとに太くして、紙の上で数えやすくする
+///
+/// # Arguments
+/// * `rows` - 盤面の行数
+/// * `cols` - 盤面の列数
+/// * `row_rules_js` - 行ルールの配列
+/// * `col_rules_js` - 列ルールの配列
+/// * `cell_size` - 1マスの一辺の長さ（ピクセル、1以上）
+///
+/// # Returns
+/// * `Ok(String)` - SVGの文字列
+/// * `Err(JsValue)` - ルールの形式が不正な場合、ルールの本数が盤面と合わない場合、または`cell_size`が0の場合のエラー
+#[wasm_bindgen]
+pub fn export_blank_puzzle(
+    rows: usize,
+    cols: usize,
+    row_rules_js: JsValue,
+    col_rules_js: JsValue,
+    cell_size: usize,
+) -> Result<String, JsValue> {
+    let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+    let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+    let blank = vec![vec![CellState::Empty; cols]; rows];
+    check_dimensions(rows, cols, &row_rules, &col_rules, &blank)
+        .map_err(|e| JsValue::from_str(&e))?;
+    if cell_size == 0 {
+        return Err(JsValue::from_str("マスの大きさは1以上を指定してください"));
+    }
+
+    let layout = compute_hint_layout(rows, cols, &row_rules, &col_rules);
+    let width = layout.total_cols * cell_size;
+    let height = layout.total_rows * cell_size;
+    let left = layout.hint_width * cell_size;
+    let top = layout.hint_height * cell_size;
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" viewBox=\"0 0 {width} {height}\">\n"
+    );
+    svg.push_str(&format!(
+        "<rect width=\"{width}\" height=\"{height}\" fill=\"#fff\"/>\n"
+    ));
+
+    // 盤面の罫線（外枠と5マスごとの線は太くする）
+    let stroke = |k: usize, count: usize| {
+        if k.is_multiple_of(5) || k == count {
+            2
+        } else {
+            1
+        }
+    };
+    for r in 0..=rows {
+        let y = top + r * cell_size;
+        svg.push_str(&format!(
+            "<line x1=\"{left}\" y1=\"{y}\" x2=\"{width}\" y2=\"{y}\" stroke=\"#000\" stroke-width=\"{}\"/>\n",
+            stroke(r, rows)
+        ));
+    }
+    for c in 0..=cols {
+        let x = left + c * cell_size;
+        svg.push_str(&format!(
+            "<line x1=\"{x}\" y1=\"{top}\" x2=\"{x}\" y2=\"{height}\" stroke=\"#000\" stroke-width=\"{}\"/>\n",
+            stroke(c, cols)
+        ));
+    }
+
+    // ヒント数字は、それぞれのセルの中央に置く
+    let font_size = (cell_size * 3 / 5).max(1);
+    for hint in layout.row_hints.iter().chain(&layout.col_hints) {
+        let x = hint.col * cell_size + cell_size / 2;
+        let y = hint.row * cell_size + cell_size / 2;
+        svg.push_str(&format!(
+            "<text x=\"{x}\" y=\"{y}\" font-size=\"{font_size}\" font-family=\"sans-serif\" text-anchor=\"middle\" dominant-baseline=\"central\">{}</text>\n",
+            hint.value
+        ));
+    }
+    svg.push_str("</svg>\n");
+    Ok(svg)
+}
+
+// --- 圧縮した出力 ---
+
+/// JSONの文字列リテラルに変換する（`"`と`\`、制御文字をエスケープする）
+fn json_string(s: &str) -> String {
+    let mut json = String::with_capacity(s.len() + 2);
+    json.push('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            ch if (ch as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => json.push(ch),
+        }
+    }
+    json.push('"');
+    json
+}
+
+impl SolveResult {
+    /// JavaScriptに返すときと同じフィールド名のJSON文字列に変換する（盤面は`CellState`の数値）
+    fn to_json(&self) -> String {
+        let grid: Vec<Vec<usize>> = self
+            .grid
+            .iter()
+            .map(|row| row.iter().map(|&cell| cell as usize).collect())
+            .collect();
+        let mut json = format!(
+            "{{\"grid\":{},\"message\":{},\"error\":{}",
+            nested_to_json(&grid),
+            json_string(&self.message),
+            self.error
+        );
+        if let Some(metrics) = &self.metrics {
+            let conflict = metrics.conflict.as_ref().map_or("null".to_string(), |c| {
+                format!(
+                    "{{\"axis\":{},\"index\":{},\"message\":{}}}",
+                    json_string(&c.axis),
+                    c.index,
+                    json_string(&c.message)
+                )
+            });
+            json.push_str(&format!(
+                ",\"metrics\":{{\"iterations\":{},\"lines_processed\":{},\"conflict\":{}}}",
+                metrics.iterations, metrics.lines_processed, conflict
+            ));
+        }
+        json.push('}');
+        json
+    }
+}
+
+/// 解析結果をJSONにし、gzip形式（`flate2`のDEFLATE実装）で圧縮する
+///
+/// # Arguments
+/// * `result` - 圧縮する解析結果
+/// * `level` - 圧縮レベル（0〜9、0は無圧縮）
+///
+/// # Returns
+/// * `Ok(Vec<u8>)` - gzip形式のバイト列
+/// * `Err(String)` - 圧縮レベルが範囲外の場合のエラー
+fn compress_result(result: &SolveResult, level: u32) -> Result<Vec<u8>, String> {
+    if level > 9 {
+        return Err("圧縮レベルは0〜9の範囲で指定してください".to_string());
+    }
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::new(level));
+    std::io::Write::write_all(&mut encoder, result.to_json().as_bytes())
+        .and_then(|_| encoder.finish())
+        .map_err(|e| e.to_string())
+}
+
+/// `solve_puzzle`と同じ解析を行い、結果をJSONにしてgzipで圧縮したバイト列を返す関数
+/// 大きな盤面の結果をネットワーク越しに返すときの転送量を減らすために使う
+/// ノノグラムの盤面は同じ状態が続きやすいので、圧縮率が高い
+/// JavaScript側では`DecompressionStream("gzip")`などで展開し、`JSON.parse`すれば`solve_puzzle`と同じ形の結果になる
+///
+/// # Arguments
+/// * `rows` - 盤面の行数
+/// * `cols` - 盤面の列数
+/// * `row_rules_js` - 行ルールの配列
+/// * `col_rules_js` - 列ルールの配列
+/// * `initial_grid_js` - 現在の盤面
+/// * `level` - 圧縮レベル（0〜9、0は無圧縮、大きいほど圧縮率が高く時間がかかる）
+///
+/// # Returns
+/// * `Ok(Vec<u8>)` - gzip形式のバイト列（JavaScriptでは`Uint8Array`）
+/// * `Err(JsValue)` - 入力が不正な場合、または圧縮レベルが範囲外の場合のエラー
+#[wasm_bindgen]
+pub fn solve_puzzle_compressed(
+    rows: usize,
+    cols: usize,
+    row_rules_js: JsValue,
+    col_rules_js: JsValue,
+    initial_grid_js: JsValue,
+    level: u32,
+) -> Result<Vec<u8>, JsValue> {
+    if level > 9 {
+        return Err(JsValue::from_str(
+            "圧縮レベルは0〜9の範囲で指定してください",
+        ));
+    }
+    let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+    let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+    let grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(initial_grid_js)?;
+    check_dimensions(rows, cols, &row_rules, &col_rules, &grid)
+        .map_err(|e| JsValue::from_str(&e))?;
+
+    let result = solve_puzzle_native(rows, cols, &row_rules, &col_rules, grid);
+    compress_result(&result, level).map_err(|e| JsValue::from_str(&e))
+}
+
+// --- 盤面の相互変換 ---
+
+/// 数値の2次元配列を`CellState`の盤面に変換する
+/// 不正な値があれば、最初に見つかったマスの位置をエラーメッセージに含める
+fn grid_from_u8(grid: &[Vec<u8>]) -> Result<Vec<Vec<CellState>>, String> {
+    grid.iter()
+        .enumerate()
+        .map(|(r, row)| {
+            row.iter()
+                .enumerate()
+                .map(|(c, &value)| {
+                    CellState::try_from(value).map_err(|_| {
+                        format!(
+                            "行{}列{}のセル値（{}）が不正です（0, 1, 2のいずれかを指定してください）",
+                            r + 1,
+                            c + 1,
+                            value
+                        )
+                    })
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// 1マス分の値を、不正でもエラーで打ち切らずに読み込むためのラッパー
+/// 盤面全体を読み込んでから、不正なマスの位置を特定して報告するのに使う
+struct CheckedCell(Result<CellState, String>);
+
+impl<'de> Deserialize<'de> for CheckedCell {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        // `CellState`のVisitorのエラーを、そのマスの結果として持っておく
+        Ok(CheckedCell(
+            CellState::deserialize(deserializer).map_err(|e| e.to_string()),
+        ))
+    }
+}
+
+/// JavaScriptから渡された盤面を`CellState`の盤面に変換する
+/// 不正な値があれば、最初に見つかったマスの位置をエラーメッセージに含める
+fn grid_from_js(grid_js: JsValue) -> Result<Vec<Vec<CellState>>, JsValue> {
+    let cells: Vec<Vec<CheckedCell>> = serde_wasm_bindgen::from_value(grid_js)?;
+    cells
+        .into_iter()
+        .enumerate()
+        .map(|(r, row)| {
+            row.into_iter()
+                .enumerate()
+                .map(|(c, cell)| {
+                    cell.0.map_err(|e| {
+                        JsValue::from_str(&format!(
+                            "行{}列{}のセル値が不正です（{}）",
+                            r + 1,
+                            c + 1,
+                            e
+                        ))
+                    })
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// `0`/`1`/`2`の数値の2次元配列を検証し、`CellState`の盤面として返す関数
+///
+/// # Arguments
+/// * `grid_js` - 数値の2次元配列（0: 空, 1: 塗り, 2: ×）
+///
+/// # Returns
+/// * `Ok(JsValue)` - `CellState`の盤面
+/// * `Err(JsValue)` - 3以上の値など、不正な値が含まれている場合のエラー
+#[wasm_bindgen]
+pub fn from_u8_grid(grid_js: JsValue) -> Result<JsValue, JsValue> {
+    let grid: Vec<Vec<u8>> = serde_wasm_bindgen::from_value(grid_js)?;
+    let cells = grid_from_u8(&grid).map_err(|e| JsValue::from_str(&e))?;
+    Ok(serde_wasm_bindgen::to_value(&cells)?)
+}
+
+/// `CellState`の盤面を、`0`/`1`/`2`の数値の2次元配列に変換する関数
+///
+/// # Arguments
+/// * `grid_js` - 盤面の状態（`CellState`の2次元配列）
+///
+/// # Returns
+/// * `Ok(JsValue)` - 数値の2次元配列
+/// * `Err(JsValue)` - 盤面の形式が不正な場合のエラー
+#[wasm_bindgen]
+pub fn to_u8_grid(grid_js: JsValue) -> Result<JsValue, JsValue> {
+    let grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(grid_js)?;
+    let values: Vec<Vec<u8>> = grid
+        .iter()
+        .map(|row| row.iter().map(|&cell| cell as u8).collect())
+        .collect();
+    Ok(serde_wasm_bindgen::to_value(&values)?)
+}
+
+/// コンパクトな文字列表現で、行と行の間に置く区切り文字
+const COMPACT_ROW_SEPARATOR: char = '|';
+
+/// 盤面全体を1行の文字列（例: `"#..x#|.##..|....."`）に変換する関数
+/// 各マスを1文字（`#`: 塗り, `.`: 空, `x`: ×）で表し、行の間を`|`で区切る
+/// ログへの埋め込みや、テストケースの記述に使う
+///
+/// # Arguments
+/// * `grid_js` - 盤面の状態（`CellState`の2次元配列）
+///
+/// # Returns
+/// * `Ok(String)` - 盤面の文字列表現
+/// * `Err(JsValue)` - 盤面の形式が不正な場合のエラー
+#[wasm_bindgen]
+pub fn grid_to_compact_string(grid_js: JsValue) -> Result<String, JsValue> {
+    let grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(grid_js)?;
+    let rows: Vec<String> = grid
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|&cell| match cell {
+                    CellState::Empty => '.',
+                    CellState::Filled => '#',
+                    CellState::Crossed => 'x',
+                })
+                .collect()
+        })
+        .collect();
+    Ok(rows.join(&COMPACT_ROW_SEPARATOR.to_string()))
+}
+
+/// `grid_to_compact_string`の文字列表現を、盤面に戻す関数
+/// 空文字列は0行の盤面として扱う
+///
+/// # Arguments
+/// * `s` - 盤面の文字列表現（`#`、`.`、`x`と区切りの`|`）
+///
+/// # Returns
+/// * `Ok(JsValue)` - `CellState`の盤面
+/// * `Err(JsValue)` - 不正な文字がある場合（その位置を含む）、または行の長さが揃っていない場合のエラー
+#[wasm_bindgen]
+pub fn compact_string_to_grid(s: &str) -> Result<JsValue, JsValue> {
+    if s.is_empty() {
+        return Ok(serde_wasm_bindgen::to_value(&Vec::<Vec<CellState>>::new())?);
+    }
+    let grid = s
+        .split(COMPACT_ROW_SEPARATOR)
+        .enumerate()
+        .map(|(r, row)| {
+            row.chars()
+                .enumerate()
+                .map(|(c, ch)| match ch {
+                    '.' => Ok(CellState::Empty),
+                    '#' => Ok(CellState::Filled),
+                    'x' => Ok(CellState::Crossed),
+                    _ => Err(format!(
+                        "行{}列{}の文字（{}）が不正です（#、.、xのいずれかを指定してください）",
+                        r + 1,
+                        c + 1,
+                        ch
+                    )),
+                })
+                .collect()
+        })
+        .collect::<Result<Vec<Vec<CellState>>, String>>()
+        .map_err(|e| JsValue::from_str(&e))?;
+    grid_width(&grid).map_err(|e| JsValue::from_str(&e))?;
+    Ok(serde_wasm_bindgen::to_value(&grid)?)
+}
+
+// --- 共有用の文字列 ---
+
+/// 共有用の文字列を作るときに埋め込むフォーマットのバージョン
+/// フォーマットを変えるときはこの値を上げ、古いバージョンのデコーダも残しておく
+const SHARE_FORMAT_VERSION: u32 = 1;
+
+/// 共有用の文字列で盤面のデータに使う文字（URLにそのまま使えるBase64の64文字）
+const SHARE_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// 共有用の文字列から読み込む盤面の、1辺の最大のマス数
+/// 列数が0の盤面はデータが空でも行数分の領域を確保するので、URLから巨大な盤面を作らせないように制限する
+const MAX_SHARED_GRID_SIDE: usize = 1000;
+
+/// 共有用の文字列の先頭にある`v1-`のようなバージョン番号を読み取り、残りの本体と分ける
+fn split_share_version(s: &str) -> Result<(u32, &str), String> {
+    let missing = || {
+        format!(
+            "共有文字列にバージョン番号がありません（\"v{}-\"のような接頭辞が必要です）",
+            SHARE_FORMAT_VERSION
+        )
+    };
+    let (version, body) = s
+        .strip_prefix('v')
+        .and_then(|rest| rest.split_once('-'))
+        .ok_or_else(missing)?;
+    let version = version.parse::<u32>().map_err(|_| missing())?;
+    Ok((version, body))
+}
+
+/// 対応していないバージョンの共有文字列を読み込もうとしたときのエラーメッセージ
+fn unsupported_share_version(version: u32) -> String {
+    format!(
+        "共有文字列のバージョン{}には対応していません（対応しているバージョン: 1〜{}）",
+        version, SHARE_FORMAT_VERSION
+    )
+}
+
+/// 共有用の文字列から読み込んだ行ルールと列ルール
+#[derive(Serialize, Deserialize)]
+pub struct SharedRules {
+    row_rules: Vec<Vec<usize>>, // 行ルールの配列
+    col_rules: Vec<Vec<usize>>, // 列ルールの配列
+}
+
+/// v1フォーマットの、ルールの並びの部分を作る
+/// ルールの数字を`.`で、ルール同士を`_`で区切り、ブロックのないルールは`0`と書く
+fn encode_rules_v1(rules: &[Vec<usize>]) -> String {
+    rules
+        .iter()
+        .map(|rule| {
+            let blocks = normalize_rule(rule);
+            if blocks.is_empty() {
+                "0".to_string()
+            } else {
+                blocks
+                    .iter()
+                    .map(usize::to_string)
+                    .collect::<Vec<_>>()
+                    .join(".")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+/// `encode_rules_v1`の文字列をルールの並びに戻す（空文字列はルールが1本もないものとして扱う）
+fn decode_rules_v1(section: &str, axis: Axis) -> Result<Vec<Vec<usize>>, String> {
+    if section.is_empty() {
+        return Ok(Vec::new());
+    }
+    section
+        .split('_')
+        .enumerate()
+        .map(|(i, rule)| {
+            let blocks = rule
+                .split('.')
+                .map(|number| number.parse::<usize>())
+                .collect::<Result<Vec<usize>, _>>()
+                .map_err(|_| format!("{}ルール{}が不正です: \"{}\"", axis.label(), i + 1, rule))?;
+            Ok(normalize_rule(&blocks))
+        })
+        .collect()
+}
+
+/// 行ルールと列ルールを、URLなどで共有するための1つの文字列にまとめる関数
+/// 先頭にフォーマットのバージョン番号（`v1-`）を付けるので、将来フォーマットを変えても古い文字列を読み込める
+/// v1では、行ルールと列ルールを`~`で区切り、それぞれをルール同士は`_`、ルールの数字は`.`で区切る
+/// 例: 行ルール`[[1], [1, 1]]`、列ルール`[[2], []]` → `"v1-1_1.1~2_0"`
+///
+/// # Arguments
+/// * `row_rules_js` - 行ルールの配列
+/// * `col_rules_js` - 列ルールの配列
+///
+/// # Returns
+/// * `Ok(String)` - 共有用の文字列
+/// * `Err(JsValue)` - 入力の形式が不正な場合のエラー
+#[wasm_bindgen]
+pub fn encode_rules(row_rules_js: JsValue, col_rules_js: JsValue) -> Result<String, JsValue> {
+    let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+    let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+    Ok(encode_shared_rules(&row_rules, &col_rules))
+}
+
+/// `encode_rules`の本体で、現在のバージョンのフォーマットで共有用の文字列を作る
+fn encode_shared_rules(row_rules: &[Vec<usize>], col_rules: &[Vec<usize>]) -> String {
+    format!(
+        "v{}-{}~{}",
+        SHARE_FORMAT_VERSION,
+        encode_rules_v1(row_rules),
+        encode_rules_v1(col_rules)
+    )
+}
+
+/// `encode_rules`で作った共有用の文字列を、行ルールと列ルールに戻す関数
+/// 先頭のバージョン番号を見て、そのバージョンのフォーマットで読み込む
+///
+/// # Arguments
+/// * `s` - 共有用の文字列
+///
+/// # Returns
+/// * `Ok(JsValue)` - 行ルールと列ルール（`SharedRules`）
+/// * `Err(JsValue)` - バージョン番号がない、対応していないバージョンである、または本体が不正な場合のエラー
+#[wasm_bindgen]
+pub fn decode_rules(s: &str) -> Result<JsValue, JsValue> {
+    let rules = decode_shared_rules(s).map_err(|e| JsValue::from_str(&e))?;
+    Ok(serde_wasm_bindgen::to_value(&rules)?)
+}
+
+/// `decode_rules`の本体で、バージョン番号に合ったフォーマットで共有用の文字列を読み込む
+fn decode_shared_rules(s: &str) -> Result<SharedRules, String> {
+    let (version, body) = split_share_version(s)?;
+    match version {
+        1 => {
+            let (rows, cols) = body
+                .split_once('~')
+                .ok_or("行ルールと列ルールの区切り（~）がありません")?;
+            Ok(SharedRules {
+                row_rules: decode_rules_v1(rows, Axis::Row)?,
+                col_rules: decode_rules_v1(cols, Axis::Column)?,
+            })
+        }
+        version => Err(unsupported_share_version(version)),
+    }
+}
+
+/// 盤面を、URLなどで共有するための1つの文字列にまとめる関数
+/// 先頭にフォーマットのバージョン番号（`v1-`）を付けるので、将来フォーマットを変えても古い文字列を読み込める
+/// v1では、`行数.列数.データ`の形で、データは1マスを2ビット（空=0、塗り=1、×=2）として
+/// 行優先に3マスずつ`SHARE_ALPHABET`の1文字にまとめる
+///
+/// # Arguments
+/// * `grid_js` - 盤面の状態（`CellState`の2次元配列）
+///
+/// # Returns
+/// * `Ok(String)` - 共有用の文字列
+/// * `Err(JsValue)` - 盤面の形式が不正な場合や、1辺が`MAX_SHARED_GRID_SIDE`を超えて読み込めない場合のエラー
+#[wasm_bindgen]
+pub fn encode_grid(grid_js: JsValue) -> Result<String, JsValue> {
+    let grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(grid_js)?;
+    encode_shared_grid(&grid).map_err(|e| JsValue::from_str(&e))
+}
+
+/// `encode_grid`の本体で、現在のバージョンのフォーマットで共有用の文字列を作る
+fn encode_shared_grid(grid: &[Vec<CellState>]) -> Result<String, String> {
+    let width = grid_width(grid)?;
+    // `decode_grid`で読み込めない大きさの文字列は作らない
+    if grid.len() > MAX_SHARED_GRID_SIDE || width > MAX_SHARED_GRID_SIDE {
+        return Err(format!(
+            "盤面が大きすぎます（{}行×{}列、1辺は最大{}マスです）",
+            grid.len(),
+            width,
+            MAX_SHARED_GRID_SIDE
+        ));
+    }
+    let data: String = grid
+        .concat()
+        .chunks(3)
+        .map(|cells| {
+            let value = cells
+                .iter()
+                .enumerate()
+                .fold(0, |value, (i, &cell)| value | (cell as usize) << (2 * i));
+            SHARE_ALPHABET[value] as char
+        })
+        .collect();
+    Ok(format!(
+        "v{}-{}.{}.{}",
+        SHARE_FORMAT_VERSION,
+        grid.len(),
+        width,
+        data
+    ))
+}
+
+/// `encode_grid`のv1フォーマットの本体（`行数.列数.データ`）を盤面に戻す
+fn decode_grid_v1(body: &str) -> Result<Vec<Vec<CellState>>, String> {
+    let mut parts = body.splitn(3, '.');
+    let (Some(rows), Some(cols), Some(data)) = (parts.next(), parts.next(), parts.next()) else {
+        return Err("盤面の共有文字列は「行数.列数.データ」の形で指定してください".to_string());
+    };
+    let (Ok(rows), Ok(cols)) = (rows.parse::<usize>(), cols.parse::<usize>()) else {
+        return Err(format!("行数（{}）または列数（{}）が不正です", rows, cols));
+    };
+    if rows > MAX_SHARED_GRID_SIDE || cols > MAX_SHARED_GRID_SIDE {
+        return Err(format!(
+            "盤面が大きすぎます（{}行×{}列、1辺は最大{}マスです）",
+            rows, cols, MAX_SHARED_GRID_SIDE
+        ));
+    }
+    let total = rows * cols;
+    let expected = total.div_ceil(3);
+    if data.len() != expected {
+        return Err(format!(
+            "データの長さ（{}文字）が盤面の大きさに合いません（{}文字必要です）",
+            data.len(),
+            expected
+        ));
+    }
+
+    let mut cells = Vec::with_capacity(total);
+    for (i, ch) in data.bytes().enumerate() {
+        let value = SHARE_ALPHABET
+            .iter()
+            .position(|&symbol| symbol == ch)
+            .ok_or_else(|| format!("データの{}文字目（{}）が不正です", i + 1, ch as char))?;
+        for shift in 0..3 {
+            if cells.len() == total {
+                break;
+            }
+            cells.push(match value >> (2 * shift) & 0b11 {
+                0 => CellState::Empty,
+                1 => CellState::Filled,
+                2 => CellState::Crossed,
+                _ => {
+                    return Err(format!(
+                        "データの{}文字目（{}）が不正です",
+                        i + 1,
+                        ch as char
+                    ));
+                }
+            });
+        }
+    }
+    // 列数が0の場合も、行数分の空の行を作る
+    Ok((0..rows)
+        .map(|r| cells[r * cols..(r + 1) * cols].to_vec())
+        .collect())
+}
+
+/// `encode_grid`で作った共有用の文字列を、盤面に戻す関数
+/// 先頭のバージョン番号を見て、そのバージョンのフォーマットで読み込む
+///
+/// # Arguments
+/// * `s` - 共有用の文字列
+///
+/// # Returns
+/// * `Ok(JsValue)` - `CellState`の盤面
+/// * `Err(JsValue)` - バージョン番号がない、対応していないバージョンである、または本体が不正な場合のエラー
+#[wasm_bindgen]
+pub fn decode_grid(s: &str) -> Result<JsValue, JsValue> {
+    let grid = decode_shared_grid(s).map_err(|e| JsValue::from_str(&e))?;
+    Ok(serde_wasm_bindgen::to_value(&grid)?)
+}
+
+/// `decode_grid`の本体で、バージョン番号に合ったフォーマットで共有用の文字列を読み込む
+fn decode_shared_grid(s: &str) -> Result<Vec<Vec<CellState>>, String> {
+    let (version, body) = split_share_version(s)?;
+    match version {
+        1 => decode_grid_v1(body),
+        version => Err(unsupported_share_version(version)),
+    }
+}
+
+// --- 差分の適用 ---
+
+/// 盤面の1マス分の差分を表す構造体
+#[derive(Serialize, Deserialize)]
+pub struct CellPatch {
+    row: usize,       // 変更するマスの行番号
+    col: usize,       // 変更するマスの列番号
+    state: CellState, // 変更後の状態
+}
+
+/// 盤面に差分パッチを適用し、新しい盤面を返す関数
+/// 渡された盤面自体は変更せず、パッチを先頭から順に適用した盤面を新たに作る
+///
+/// # Arguments
+/// * `grid_js` - 適用前の盤面（`CellState`の2次元配列）
+/// * `patch_js` - `{ row, col, state }`の配列
+///
+/// # Returns
+/// * `Ok(JsValue)` - パッチ適用後の盤面
+/// * `Err(JsValue)` - 盤面やパッチの形式が不正な場合、または盤面の範囲外を指すパッチがある場合のエラー
+#[wasm_bindgen]
+pub fn apply_patch(grid_js: JsValue, patch_js: JsValue) -> Result<JsValue, JsValue> {
+    let mut grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(grid_js)?;
+    // 状態が0〜2以外のパッチは、ここでデシリアライズのエラーになる
+    let patch: Vec<CellPatch> = serde_wasm_bindgen::from_value(patch_js)?;
+
+    for (i, cell) in patch.iter().enumerate() {
+        let Some(target) = grid.get_mut(cell.row).and_then(|row| row.get_mut(cell.col)) else {
+            return Err(JsValue::from_str(&format!(
+                "パッチの{}番目（{}行{}列）は盤面の範囲外です",
+                i + 1,
+                cell.row + 1,
+                cell.col + 1
+            )));
+        };
+        *target = cell.state;
+    }
+    Ok(serde_wasm_bindgen::to_value(&grid)?)
+}
+
+/// 2つの盤面の間を補間するときに、変化するマスを開示する順序
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RevealOrder {
+    /// 左上から行ごとに順番に開示する
+    #[default]
+    RowMajor,
+    /// 変化するマスの重心に近いものから順に開示する（中心から広がるように見える）
+    FromCentroid,
+    /// シード値に基づくランダムな順序で開示する
+    Random,
+}
+
+impl RevealOrder {
+    /// JavaScriptから渡された開示順序を読み込む
+    /// `undefined`や`null`の場合は既定値を使う
+    fn from_js(order_js: JsValue) -> Result<Self, JsValue> {
+        if order_js.is_undefined() || order_js.is_null() {
+            return Ok(RevealOrder::default());
+        }
+        Ok(serde_wasm_bindgen::from_value(order_js)?)
+    }
+
+    /// 変化するマスの一覧（行優先の順）を、開示する順に並べ替える
+    fn arrange(self, cells: &mut [CellPatch], seed: u32) {
+        match self {
+            RevealOrder::RowMajor => {}
+            RevealOrder::FromCentroid => {
+                let count = cells.len().max(1) as f64;
+                let avg_row = cells.iter().map(|cell| cell.row as f64).sum::<f64>() / count;
+                let avg_col = cells.iter().map(|cell| cell.col as f64).sum::<f64>() / count;
+                let distance = |cell: &CellPatch| {
+                    (cell.row as f64 - avg_row).powi(2) + (cell.col as f64 - avg_col).powi(2)
+                };
+                // 安定ソートなので、距離が同じマスは行優先の順のまま残る
+                cells.sort_by(|a, b| distance(a).total_cmp(&distance(b)));
+            }
+            RevealOrder::Random => SeededRng::new(seed as u64).shuffle(cells),
+        }
+    }
+}
+
+/// 2つの盤面の間を補間するアニメーション用のフレーム列を生成する関数
+/// `from`と`to`で状態が異なるマスを`steps`段階に分け、段階ごとに少しずつ`to`の状態に書き換えた盤面を返す
+/// 「解けていく様子」を滑らかに見せる演出に使う
+/// 各段階で開示するマスの数はできるだけ均等にするので、変化するマスが`steps`より少ない場合は同じ盤面が続くことがある
+///
+/// # Arguments
+/// * `from_js` - 補間の始点の盤面
+/// * `to_js` - 補間の終点の盤面（`from_js`と同じ形）
+/// * `steps` - フレーム数（1以上）
+/// * `order_js` - マスを開示する順序（"row_major"、"from_centroid"、"random"、省略時は"row_major"）
+/// * `seed` - "random"で使うシード値（省略時は0）
+///
+/// # Returns
+/// * `Ok(JsValue)` - `steps`個の盤面の配列（始点は含まず、最後の盤面は`to_js`と一致する）
+/// * `Err(JsValue)` - 盤面の形式が不正な場合、2つの盤面の形が異なる場合、または`steps`が0の場合のエラー
+#[wasm_bindgen]
+pub fn interpolate_frames(
+    from_js: JsValue,
+    to_js: JsValue,
+    steps: usize,
+    order_js: JsValue,
+    seed: Option<u32>,
+) -> Result<JsValue, JsValue> {
+    let from: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(from_js)?;
+    let to: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(to_js)?;
+    let order = RevealOrder::from_js(order_js)?;
+    if steps == 0 {
+        return Err(JsValue::from_str("フレーム数は1以上を指定してください"));
+    }
+    if from.len() != to.len() || from.iter().zip(&to).any(|(a, b)| a.len() != b.len()) {
+        return Err(JsValue::from_str("2つの盤面の大きさが一致しません"));
+    }
+
+    let mut changes: Vec<CellPatch> = Vec::new();
+    for (r, (from_row, to_row)) in from.iter().zip(&to).enumerate() {
+        for (c, (&before, &after)) in from_row.iter().zip(to_row).enumerate() {
+            if before != after {
+                changes.push(CellPatch {
+                    row: r,
+                    col: c,
+                    state: after,
+                });
+            }
+        }
+    }
+    order.arrange(&mut changes, seed.unwrap_or(0));
+
+    // `k`番目のフレームまでに、変化するマス全体の`(k + 1) / steps`を開示する
+    let mut frame = from;
+    let mut revealed = 0;
+    let mut frames = Vec::with_capacity(steps);
+    for step in 1..=steps {
+        let target = changes.len() * step / steps;
+        for cell in &changes[revealed..target] {
+            frame[cell.row][cell.col] = cell.state;
+        }
+        revealed = target;
+        frames.push(frame.clone());
+    }
+    Ok(serde_wasm_bindgen::to_value(&frames)?)
+}
+
+// --- ランレングス符号 ---
+
+/// 1行分の盤面を、同じ状態が連続する区間ごとの`(状態, 長さ)`の並びに変換する
+fn encode_rle_line(line: &[CellState]) -> Vec<(CellState, usize)> {
+    let mut runs: Vec<(CellState, usize)> = Vec::new();
+    for &cell in line {
+        match runs.last_mut() {
+            Some((state, len)) if *state == cell => *len += 1,
+            _ => runs.push((cell, 1)),
+        }
+    }
+    runs
+}
+
+/// 盤面を行ごとのランレングス符号に変換する関数
+/// 各行は`[状態, 長さ]`のペアの配列になり、連続した領域の多い盤面ほど転送量を減らせる
+///
+/// # Arguments
+/// * `grid_js` - 盤面の状態（`CellState`の2次元配列）
+///
+/// # Returns
+/// * `Ok(JsValue)` - 行ごとの`[[状態, 長さ], ...]`の配列
+/// * `Err(JsValue)` - 盤面の形式が不正な場合のエラー
+#[wasm_bindgen]
+pub fn encode_rle(grid_js: JsValue) -> Result<JsValue, JsValue> {
+    let grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(grid_js)?;
+    let encoded: Vec<Vec<(CellState, usize)>> =
+        grid.iter().map(|line| encode_rle_line(line)).collect();
+    Ok(serde_wasm_bindgen::to_value(&encoded)?)
+}
+
+/// `encode_rle`で作ったランレングス符号を盤面に戻す関数
+/// 復元した盤面が指定したサイズと一致しない場合はエラーにする
+///
+/// # Arguments
+/// * `rle_js` - 行ごとの`[[状態, 長さ], ...]`の配列
+/// * `rows` - 盤面の行数
+/// * `cols` - 盤面の列数
+///
+/// # Returns
+/// * `Ok(JsValue)` - 復元した盤面
+/// * `Err(JsValue)` - 符号の形式が不正な場合、またはサイズが一致しない場合のエラー
+#[wasm_bindgen]
+pub fn decode_rle(rle_js: JsValue, rows: usize, cols: usize) -> Result<JsValue, JsValue> {
+    let encoded: Vec<Vec<(CellState, usize)>> = serde_wasm_bindgen::from_value(rle_js)?;
+    if encoded.len() != rows {
+        return Err(JsValue::from_str(&format!(
+            "ランレングス符号の行数（{}）が盤面の行数（{}）と一致しません",
+            encoded.len(),
+            rows
+        )));
+    }
+
+    let mut grid = Vec::with_capacity(rows);
+    for (r, runs) in encoded.iter().enumerate() {
+        // 長さの合計を先に検査し、巨大な長さが渡されても確保する前にエラーにする
+        let total = runs
+            .iter()
+            .try_fold(0usize, |sum, &(_, len)| sum.checked_add(len));
+        if total != Some(cols) {
+            return Err(JsValue::from_str(&format!(
+                "行 {}: ランレングス符号の長さの合計が盤面の列数（{}）と一致しません",
+                r + 1,
+                cols
+            )));
+        }
+        let mut line = Vec::with_capacity(cols);
+        for &(state, len) in runs {
+            line.extend(std::iter::repeat_n(state, len));
+        }
+        grid.push(line);
+    }
+    Ok(serde_wasm_bindgen::to_value(&grid)?)
+}
+
+// --- ライン単位の編集 ---
+
+/// 盤面の形と、指定したラインが盤面の範囲内かどうかを検査する
+/// 範囲内であれば、そのラインの長さを返す
+fn checked_line_len(grid: &[Vec<CellState>], axis: Axis, index: usize) -> Result<usize, JsValue> {
+    let width = grid_width(grid).map_err(|e| JsValue::from_str(&e))?;
+    let (count, len) = match axis {
+        Axis::Row => (grid.len(), width),
+        Axis::Column => (width, grid.len()),
+    };
+    if index >= count {
+        return Err(JsValue::from_str(&format!(
+            "{} {}は盤面の範囲外です",
+            axis.label(),
+            index + 1
+        )));
+    }
+    Ok(len)
+}
+
+/// 盤面から指定した向き・番号のラインを取り出す
+fn read_line(grid_js: JsValue, axis: Axis, index: usize) -> Result<JsValue, JsValue> {
+    let grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(grid_js)?;
+    checked_line_len(&grid, axis, index)?;
+    // 列も転置せず、各行から直接集める
+    Ok(serde_wasm_bindgen::to_value(&get_line(&grid, axis, index))?)
+}
+
+/// 盤面の指定した向き・番号のラインを書き換えた、新しい盤面を返す
+fn write_line(
+    grid_js: JsValue,
+    axis: Axis,
+    index: usize,
+    line_js: JsValue,
+) -> Result<JsValue, JsValue> {
+    let mut grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(grid_js)?;
+    let line: Vec<CellState> = serde_wasm_bindgen::from_value(line_js)?;
+    let len = checked_line_len(&grid, axis, index)?;
+    if line.len() != len {
+        return Err(JsValue::from_str(&format!(
+            "{} {}: ラインの長さ（{}）が盤面と一致しません（{}）",
+            axis.label(),
+            index + 1,
+            line.len(),
+            len
+        )));
+    }
+    set_line(&mut grid, axis, index, &line);
+    Ok(serde_wasm_bindgen::to_value(&grid)?)
+}
+
+/// 盤面から1行を取り出す関数
+///
+/// # Arguments
+/// * `grid_js` - 盤面の状態（`CellState`の2次元配列）
+/// * `index` - 取り出す行の番号（0始まり）
+///
+/// # Returns
+/// * `Ok(JsValue)` - 指定した行の`CellState`の配列
+/// * `Err(JsValue)` - 盤面の形式が不正な場合、または範囲外の番号を指定した場合のエラー
+#[wasm_bindgen]
+pub fn get_row(grid_js: JsValue, index: usize) -> Result<JsValue, JsValue> {
+    read_line(grid_js, Axis::Row, index)
+}
+
+/// 盤面から1列を取り出す関数
+///
+/// # Arguments
+/// * `grid_js` - 盤面の状態（`CellState`の2次元配列）
+/// * `index` - 取り出す列の番号（0始まり）
+///
+/// # Returns
+/// * `Ok(JsValue)` - 指定した列の`CellState`の配列（上から下の順）
+/// * `Err(JsValue)` - 盤面の形式が不正な場合、または範囲外の番号を指定した場合のエラー
+#[wasm_bindgen]
+pub fn get_column(grid_js: JsValue, index: usize) -> Result<JsValue, JsValue> {
+    read_line(grid_js, Axis::Column, index)
+}
+
+/// 盤面の1行を書き換える関数
+/// 渡された盤面自体は変更せず、書き換えた盤面を新たに返す
+///
+/// # Arguments
+/// * `grid_js` - 盤面の状態（`CellState`の2次元配列）
+/// * `index` - 書き換える行の番号（0始まり）
+/// * `line_js` - 新しい行の`CellState`の配列
+///
+/// # Returns
+/// * `Ok(JsValue)` - 書き換え後の盤面
+/// * `Err(JsValue)` - 範囲外の番号を指定した場合や、ラインの長さが盤面と合わない場合のエラー
+#[wasm_bindgen]
+pub fn set_row(grid_js: JsValue, index: usize, line_js: JsValue) -> Result<JsValue, JsValue> {
+    write_line(grid_js, Axis::Row, index, line_js)
+}
+
+/// 盤面の1列を書き換える関数
+/// 渡された盤面自体は変更せず、書き換えた盤面を新たに返す
+///
+/// # Arguments
+/// * `grid_js` - 盤面の状態（`CellState`の2次元配列）
+/// * `index` - 書き換える列の番号（0始まり）
+/// * `line_js` - 新しい列の`CellState`の配列（上から下の順）
+///
+/// # Returns
+/// * `Ok(JsValue)` - 書き換え後の盤面
+/// * `Err(JsValue)` - 範囲外の番号を指定した場合や、ラインの長さが盤面と合わない場合のエラー
+#[wasm_bindgen]
+pub fn set_column(grid_js: JsValue, index: usize, line_js: JsValue) -> Result<JsValue, JsValue> {
+    write_line(grid_js, Axis::Column, index, line_js)
+}
+
+/// ラインの挿入・削除の結果をJavaScriptに返すためのデータ構造
+#[derive(Serialize, Deserialize)]
+pub struct ResizeResult {
+    grid: Vec<Vec<CellState>>, // ラインを挿入・削除した盤面
+    rules: Vec<Vec<usize>>, // 挿入・削除した向きのルール（行の操作なら行ルール、列の操作なら列ルール）
+}
+
+/// 盤面とその向きのルールに、ラインを1本挿入する、または1本削除する
+/// 挿入では`index`の位置に全て「空」のラインと空のルールを入れ、削除では`index`のラインとルールを取り除く
+/// 交差する向きのルールは変更しないので、必要に応じて呼び出し側で作り直すこと
+fn resize_line(
+    grid_js: JsValue,
+    rules_js: JsValue,
+    axis: Axis,
+    index: usize,
+    insert: bool,
+) -> Result<JsValue, JsValue> {
+    let mut grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(grid_js)?;
+    let mut rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(rules_js)?;
+    resize_grid(&mut grid, &mut rules, axis, index, insert).map_err(|e| JsValue::from_str(&e))?;
+    Ok(serde_wasm_bindgen::to_value(&ResizeResult { grid, rules })?)
+}
+
+/// `resize_line`の本体で、Rustのデータ構造のまま盤面とルールを書き換える
+///
+/// # Returns
+/// * `Ok(())` - 盤面とルールを書き換えた場合
+/// * `Err(String)` - ルールの本数が盤面と合わない場合や、範囲外の番号を指定した場合、行が1本もない盤面に列を挿入しようとした場合のエラー
+fn resize_grid(
+    grid: &mut Vec<Vec<CellState>>,
+    rules: &mut Vec<Vec<usize>>,
+    axis: Axis,
+    index: usize,
+    insert: bool,
+) -> Result<(), String> {
+    let width = grid_width(grid)?;
+    let count = match axis {
+        Axis::Row => grid.len(),
+        Axis::Column => width,
+    };
+    if rules.len() != count {
+        return Err(format!(
+            "ルールの本数（{}）が{}の数（{}）と一致しません",
+            rules.len(),
+            axis.label(),
+            count
+        ));
+    }
+    // 挿入は末尾の後ろ（`count`）も指定できる
+    let limit = if insert { count + 1 } else { count };
+    if index >= limit {
+        return Err(format!("{} {}は盤面の範囲外です", axis.label(), index + 1));
+    }
+    // 行が1本もない盤面では、列を挿入してもマスが増えず、ルールだけが増えて盤面と食い違ってしまう
+    if axis == Axis::Column && insert && grid.is_empty() {
+        return Err(
+            "行が1本もない盤面には列を挿入できません（先に行を挿入してください）".to_string(),
+        );
+    }
+
+    match (axis, insert) {
+        (Axis::Row, true) => grid.insert(index, vec![CellState::Empty; width]),
+        (Axis::Row, false) => {
+            grid.remove(index);
+        }
+        (Axis::Column, true) => grid
+            .iter_mut()
+            .for_each(|row| row.insert(index, CellState::Empty)),
+        (Axis::Column, false) => grid.iter_mut().for_each(|row| {
+            row.remove(index);
+        }),
+    }
+    if insert {
+        rules.insert(index, Vec::new());
+    } else {
+        rules.remove(index);
+    }
+    Ok(())
+}
+
+/// 盤面に1行を挿入する関数
+/// 挿入した行は全て「空」のマスで、行ルールにも同じ位置に空のルールを挿入する
+///
+/// # Arguments
+/// * `grid_js` - 盤面の状態（`CellState`の2次元配列）
+/// * `row_rules_js` - 行ルールの配列
+/// * `index` - 挿入する位置（0始まり、行数を指定すると末尾に追加する）
+///
+/// # Returns
+/// * `Ok(JsValue)` - 挿入後の盤面と行ルール（`ResizeResult`）
+/// * `Err(JsValue)` - 盤面とルールの形が合わない場合、または範囲外の位置を指定した場合のエラー
+#[wasm_bindgen]
+pub fn insert_row(
+    grid_js: JsValue,
+    row_rules_js: JsValue,
+    index: usize,
+) -> Result<JsValue, JsValue> {
+    resize_line(grid_js, row_rules_js, Axis::Row, index, true)
+}
+
+/// 盤面から1行を削除する関数
+/// その行の行ルールも合わせて削除する
+///
+/// # Arguments
+/// * `grid_js` - 盤面の状態（`CellState`の2次元配列）
+/// * `row_rules_js` - 行ルールの配列
+/// * `index` - 削除する行の番号（0始まり）
+///
+/// # Returns
+/// * `Ok(JsValue)` - 削除後の盤面と行ルール（`ResizeResult`）
+/// * `Err(JsValue)` - 盤面とルールの形が合わない場合、または範囲外の番号を指定した場合のエラー
+#[wasm_bindgen]
+pub fn delete_row(
+    grid_js: JsValue,
+    row_rules_js: JsValue,
+    index: usize,
+) -> Result<JsValue, JsValue> {
+    resize_line(grid_js, row_rules_js, Axis::Row, index, false)
+}
+
+/// 盤面に1列を挿入する関数
+/// 挿入した列は全て「空」のマスで、列ルールにも同じ位置に空のルールを挿入する
+///
+/// # Arguments
+/// * `grid_js` - 盤面の状態（`CellState`の2次元配列）
+/// * `col_rules_js` - 列ルールの配列
+/// * `index` - 挿入する位置（0始まり、列数を指定すると右端に追加する）
+///
+/// # Returns
+/// * `Ok(JsValue)` - 挿入後の盤面と列ルール（`ResizeResult`）
+/// * `Err(JsValue)` - 盤面とルールの形が合わない場合、範囲外の位置を指定した場合、または行が1本もない盤面の場合のエラー
+#[wasm_bindgen]
+pub fn insert_column(
+    grid_js: JsValue,
+    col_rules_js: JsValue,
+    index: usize,
+) -> Result<JsValue, JsValue> {
+    resize_line(grid_js, col_rules_js, Axis::Column, index, true)
+}
+
+/// 盤面から1列を削除する関数
+/// その列の列ルールも合わせて削除する
+///
+/// # Arguments
+/// * `grid_js` - 盤面の状態（`CellState`の2次元配列）
+/// * `col_rules_js` - 列ルールの配列
+/// * `index` - 削除する列の番号（0始まり）
+///
+/// # Returns
+/// * `Ok(JsValue)` - 削除後の盤面と列ルール（`ResizeResult`）
+/// * `Err(JsValue)` - 盤面とルールの形が合わない場合、または範囲外の番号を指定した場合のエラー
+#[wasm_bindgen]
+pub fn delete_column(
+    grid_js: JsValue,
+    col_rules_js: JsValue,
+    index: usize,
+) -> Result<JsValue, JsValue> {
+    resize_line(grid_js, col_rules_js, Axis::Column, index, false)
+}
+
+// --- 表示用のレイアウト計算 ---
+
+/// ヒント数字1つ分の表示位置を表すデータ構造
+/// 座標は、ヒント領域と盤面を合わせた全体を1つの格子とみなしたときのセル位置
+#[derive(Serialize, Deserialize)]
+pub struct HintPosition {
+    row: usize,   // 表示するセルの行
+    col: usize,   // 表示するセルの列
+    value: usize, // 表示する数字
+}
+
+/// ヒント数字の配置を計算した結果をJavaScriptに返すためのデータ構造
+#[derive(Serialize, Deserialize)]
+pub struct HintLayout {
+    hint_width: usize,            // 行ヒント領域の幅（最も長い行ルールの数字の個数）
+    hint_height: usize,           // 列ヒント領域の高さ（最も長い列ルールの数字の個数）
+    total_rows: usize,            // ヒント領域を含めた全体の行数
+    total_cols: usize,            // ヒント領域を含めた全体の列数
+    row_hints: Vec<HintPosition>, // 行ヒント（盤面の左外側、右詰め）
+    col_hints: Vec<HintPosition>, // 列ヒント（盤面の上外側、下詰め）
+}
+
+/// 表示するヒント数字の並びを返すヘルパー関数
+/// 空ルールや`[0]`は、紙のパズルと同じく「0」を1つだけ表示する
+fn hint_numbers(rule: Option<&Vec<usize>>) -> Vec<usize> {
+    let numbers: Vec<usize> = rule
+        .map(|r| r.iter().copied().filter(|&n| n > 0).collect())
+        .unwrap_or_default();
+    if numbers.is_empty() { vec![0] } else { numbers }
+}
+
+/// 行・列ルールから、ヒント数字を描画すべきセル座標を計算する関数
+/// 盤面のマス`(r, c)`は、全体の格子では`(hint_height + r, hint_width + c)`の位置になる
+///
+/// # Arguments
+/// * `rows` - 盤面の行数
+/// * `cols` - 盤面の列数
+/// * `row_rules_js` - 行ルールの配列
+/// * `col_rules_js` - 列ルールの配列
+///
+/// # Returns
+/// * `Ok(JsValue)` - ヒント領域のサイズと各数字の座標（`HintLayout`）
+/// * `Err(JsValue)` - ルールの形式が不正な場合のエラー
+#[wasm_bindgen]
+pub fn hint_layout(
+    rows: usize,
+    cols: usize,
+    row_rules_js: JsValue,
+    col_rules_js: JsValue,
+) -> Result<JsValue, JsValue> {
+    let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+    let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+    let layout = compute_hint_layout(rows, cols, &row_rules, &col_rules);
+    Ok(serde_wasm_bindgen::to_value(&layout)?)
+}
+
+/// `hint_layout`の計算部分
+/// 印刷用の用紙の出力（`export_blank_puzzle`）でも同じ配置を使う
+fn compute_hint_layout(
+    rows: usize,
+    cols: usize,
+    row_rules: &[Vec<usize>],
+    col_rules: &[Vec<usize>],
+) -> HintLayout {
+    let row_numbers: Vec<Vec<usize>> = (0..rows).map(|r| hint_numbers(row_rules.get(r))).collect();
+    let col_numbers: Vec<Vec<usize>> = (0..cols).map(|c| hint_numbers(col_rules.get(c))).collect();
+
+    // 最も長いルールに合わせて、ヒント領域の大きさを決める
+    let hint_width = row_numbers.iter().map(Vec::len).max().unwrap_or(0);
+    let hint_height = col_numbers.iter().map(Vec::len).max().unwrap_or(0);
+
+    let mut row_hints = Vec::new();
+    for (r, numbers) in row_numbers.iter().enumerate() {
+        // 行ヒントは盤面に接する側（右）に詰めて並べる
+        let offset = hint_width - numbers.len();
+        for (k, &value) in numbers.iter().enumerate() {
+            row_hints.push(HintPosition {
+                row: hint_height + r,
+                col: offset + k,
+                value,
+            });
+        }
+    }
+
+    let mut col_hints = Vec::new();
+    for (c, numbers) in col_numbers.iter().enumerate() {
+        // 列ヒントは盤面に接する側（下）に詰めて並べる
+        let offset = hint_height - numbers.len();
+        for (k, &value) in numbers.iter().enumerate() {
+            col_hints.push(HintPosition {
+                row: offset + k,
+                col: hint_width + c,
+                value,
+            });
+        }
+    }
+
+    HintLayout {
+        hint_width,
+        hint_height,
+        total_rows: hint_height + rows,
+        total_cols: hint_width + cols,
+        row_hints,
+        col_hints,
+    }
+}
+
+// --- 盤面の集計 ---
+
+/// 盤面に残っている未確定（「空」）のマスの数を数える関数
+/// 0が返れば全てのマスが確定している（完成している）ことを意味する
+#[wasm_bindgen]
+pub fn remaining_cells(grid_js: JsValue) -> Result<usize, JsValue> {
+    let grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(grid_js)?;
+    Ok(grid
+        .iter()
+        .flatten()
+        .filter(|&&cell| cell == CellState::Empty)
+        .count())
+}
+
+/// 完成までに、あと何マス「塗り」にする必要があるかを推定する関数
+/// 行ルールのブロック長の総和から、現在「塗り」になっているマスの数を引いて求める
+/// 進捗バーなどで「空」のマス数より正確な残り作業量を示すのに使う
+#[wasm_bindgen]
+pub fn remaining_filled_cells(grid_js: JsValue, row_rules_js: JsValue) -> Result<usize, JsValue> {
+    let grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(grid_js)?;
+    let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+
+    let required: usize = row_rules.iter().flatten().sum();
+    let filled = grid
+        .iter()
+        .flatten()
+        .filter(|&&cell| cell == CellState::Filled)
+        .count();
+    // ルールより多く塗られている場合（誤りを含む盤面）は0とする
+    Ok(required.saturating_sub(filled))
+}
+
+/// 「塗り」のマスの重心をJavaScriptに返すためのデータ構造
+#[derive(Serialize, Deserialize)]
+pub struct Centroid {
+    avg_row: f64, // 「塗り」のマスの行番号の平均（0始まり）
+    avg_col: f64, // 「塗り」のマスの列番号の平均（0始まり）
+}
+
+/// 「塗り」のマスの重心（行番号・列番号の平均）を求める関数
+/// 絵の構図のバランスの評価や、完成時のアニメーションの起点の計算に使う
+/// 盤面を1回走査するだけで求められる
+///
+/// # Returns
+/// * `Ok(JsValue)` - 重心の座標（`Centroid`）、「塗り」のマスが1つもない場合は`undefined`
+/// * `Err(JsValue)` - 盤面の形式が不正な場合のエラー
+#[wasm_bindgen]
+pub fn filled_centroid(grid_js: JsValue) -> Result<JsValue, JsValue> {
+    let grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(grid_js)?;
+
+    let (mut count, mut row_sum, mut col_sum) = (0usize, 0usize, 0usize);
+    for (r, row) in grid.iter().enumerate() {
+        for (c, &cell) in row.iter().enumerate() {
+            if cell == CellState::Filled {
+                count += 1;
+                row_sum += r;
+                col_sum += c;
+            }
+        }
+    }
+    if count == 0 {
+        return Ok(JsValue::UNDEFINED);
+    }
+    let centroid = Centroid {
+        avg_row: row_sum as f64 / count as f64,
+        avg_col: col_sum as f64 / count as f64,
+    };
+    Ok(serde_wasm_bindgen::to_value(&centroid)?)
+}
+
+// --- パターンの検索 ---
+
+/// 部分パターンが盤面のどこにマッチしたかを表すデータ構造
+#[derive(Serialize, Deserialize)]
+pub struct PatternMatch {
+    row: usize,       // マッチした範囲の最上行
+    col: usize,       // マッチした範囲の最左列
+    transposed: bool, // パターンを転置（左上と右下を結ぶ対角線で反転）してマッチしたか
+    mirror_h: bool,   // パターンを左右反転してマッチしたか（転置の後に適用）
+    mirror_v: bool,   // パターンを上下反転してマッチしたか（転置の後に適用）
+}
+
+/// 検索に使う部分パターン（`None`のマスはワイルドカード）
+type Pattern = Vec<Vec<Option<CellState>>>;
+
+/// 部分パターンの1マスが、盤面の1マスにマッチするかを判定する
+/// パターンの`null`は任意のマスに、「塗り」は「塗り」のマスに、「空」と「×」は「塗り」でないマスにマッチする
+fn pattern_cell_matches(pattern: Option<CellState>, cell: CellState) -> bool {
+    match pattern {
+        None => true,
+        Some(CellState::Filled) => cell == CellState::Filled,
+        Some(_) => cell != CellState::Filled,
+    }
+}
+
+/// 部分パターンが盤面のどこに含まれるかを、全てのマッチ位置について求める関数
+/// 絵の中の特定のモチーフの検出や、テンプレートマッチングに使う
+///
+/// # Arguments
+/// * `grid_js` - 盤面の状態（`CellState`の2次元配列）
+/// * `pattern_js` - 部分パターン（`CellState`または`null`の2次元配列、`null`はワイルドカード）
+/// * `with_transforms` - `true`の場合、パターンを回転・反転した8通りの形でも検索する
+///   対称なパターンで同じ形になる変換は、重複して報告しない
+///
+/// # Returns
+/// * `Ok(JsValue)` - マッチした位置と変換のリスト（`PatternMatch`、変換ごとに行優先の順）
+/// * `Err(JsValue)` - 盤面やパターンの形式が不正な場合のエラー
+#[wasm_bindgen]
+pub fn find_pattern(
+    grid_js: JsValue,
+    pattern_js: JsValue,
+    with_transforms: bool,
+) -> Result<JsValue, JsValue> {
+    let grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(grid_js)?;
+    let pattern: Pattern = serde_wasm_bindgen::from_value(pattern_js)?;
+    let width = grid_width(&grid).map_err(|e| JsValue::from_str(&e))?;
+    let pattern_width = match pattern.first() {
+        Some(first) if pattern.iter().all(|row| row.len() == first.len()) => first.len(),
+        _ => {
+            return Err(JsValue::from_str(
+                "パターンは1行以上で、全ての行が同じ長さである必要があります",
+            ));
+        }
+    };
+    if pattern_width == 0 {
+        return Err(JsValue::from_str("パターンの幅が0です"));
+    }
+
+    // 検索するパターンの形を、変換の組み合わせとともに列挙する（重複する形は除く）
+    let mut variants: Vec<(Pattern, bool, bool, bool)> = Vec::new();
+    let transforms: &[bool] = if with_transforms {
+        &[false, true]
+    } else {
+        &[false]
+    };
+    for &transposed in transforms {
+        let base: Pattern = if transposed {
+            (0..pattern_width)
+                .map(|c| pattern.iter().map(|row| row[c]).collect())
+                .collect()
+        } else {
+            pattern.clone()
+        };
+        for &mirror_h in transforms {
+            for &mirror_v in transforms {
+                let mut shape = base.clone();
+                if mirror_h {
+                    shape.iter_mut().for_each(|row| row.reverse());
+                }
+                if mirror_v {
+                    shape.reverse();
+                }
+                if variants.iter().all(|(other, ..)| *other != shape) {
+                    variants.push((shape, transposed, mirror_h, mirror_v));
+                }
+            }
+        }
+    }
+
+    let mut matches = Vec::new();
+    for (shape, transposed, mirror_h, mirror_v) in &variants {
+        let (height, shape_width) = (shape.len(), shape[0].len());
+        if height > grid.len() || shape_width > width {
+            continue;
+        }
+        for row in 0..=grid.len() - height {
+            for col in 0..=width - shape_width {
+                let found = shape.iter().enumerate().all(|(dr, pattern_row)| {
+                    pattern_row
+                        .iter()
+                        .enumerate()
+                        .all(|(dc, &p)| pattern_cell_matches(p, grid[row + dr][col + dc]))
+                });
+                if found {
+                    matches.push(PatternMatch {
+                        row,
+                        col,
+                        transposed: *transposed,
+                        mirror_h: *mirror_h,
+                        mirror_v: *mirror_v,
+                    });
+                }
+            }
+        }
+    }
+    Ok(serde_wasm_bindgen::to_value(&matches)?)
+}
+
+// --- パズルの生成 ---
+
+/// シード値から再現可能な乱数列を作る、簡易な疑似乱数生成器（SplitMix64）
+/// 外部クレートに頼らず、同じシードなら必ず同じ結果になることを保証する
+struct SeededRng {
+    state: u64,
+}
+
+impl SeededRng {
+    fn new(seed: u64) -> Self {
+        SeededRng { state: seed }
+    }
+
+    /// 次の64ビットの乱数を返す
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// 0以上`bound`未満の整数を返す
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next_u64() % bound as u64) as usize
+    }
+
+    /// 0.0以上1.0未満の実数を返す（上位53ビットを使う）
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// スライスの要素をその場で並べ替える（Fisher-Yatesのシャッフル）
+    fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = self.below(i + 1);
+            items.swap(i, j);
+        }
+    }
+}
+
+/// 各マスの状態を確率的に決めた、ランダムな盤面を生成する関数
+/// ルールとの整合性は考えないので、解ける盤面を作る関数とは別に、整合性チェックやエラー処理のテスト、
+/// デモやファジングの入力として使う
+/// 同じ`seed`と確率からは、必ず同じ盤面が得られる
+/// `fill_prob`と`cross_prob`の合計が1を超える場合は、合計が1になるように比率を保って縮める（「空」のマスは出なくなる）
+///
+/// # Arguments
+/// * `rows` - 盤面の行数
+/// * `cols` - 盤面の列数
+/// * `seed` - 乱数のシード値
+/// * `fill_prob` - 各マスが「塗り」になる確率（0.0以上）
+/// * `cross_prob` - 各マスが「×」になる確率（0.0以上、残りの確率で「空」になる）
+///
+/// # Returns
+/// * `Ok(JsValue)` - `CellState`の盤面
+/// * `Err(JsValue)` - 確率が負の値や数値でない値の場合のエラー
+#[wasm_bindgen]
+pub fn random_grid(
+    rows: usize,
+    cols: usize,
+    seed: u32,
+    fill_prob: f64,
+    cross_prob: f64,
+) -> Result<JsValue, JsValue> {
+    // NaNはどの比較も偽になるので、ここで不正な値として扱われる
+    let valid = fill_prob >= 0.0 && cross_prob >= 0.0 && (fill_prob + cross_prob).is_finite();
+    if !valid {
+        return Err(JsValue::from_str(
+            "確率には0.0以上の有限の数値を指定してください",
+        ));
+    }
+    // 合計が1を超える場合は、比率を保ったまま合計を1に揃える
+    let total = fill_prob + cross_prob;
+    let (fill_prob, cross_prob) = if total > 1.0 {
+        (fill_prob / total, cross_prob / total)
+    } else {
+        (fill_prob, cross_prob)
+    };
+
+    let grid = seeded_grid(
+        &mut SeededRng::new(seed as u64),
+        rows,
+        cols,
+        fill_prob,
+        cross_prob,
+    );
+    Ok(serde_wasm_bindgen::to_value(&grid)?)
+}
+
+/// `rng`の乱数で各マスの状態を決めた盤面を作る（`random_grid`や`estimate_solvability`の本体）
+/// 各マスは`fill_prob`の確率で「塗り」、`cross_prob`の確率で「×」、残りの確率で「空」になる
+/// `cross_prob`を`1.0 - fill_prob`にすると、「空」のない完成盤面になる
+fn seeded_grid(
+    rng: &mut SeededRng,
+    rows: usize,
+    cols: usize,
+    fill_prob: f64,
+    cross_prob: f64,
+) -> Vec<Vec<CellState>> {
+    (0..rows)
+        .map(|_| {
+            (0..cols)
+                .map(|_| {
+                    let value = rng.next_f64();
+                    if value < fill_prob {
+                        CellState::Filled
+                    } else if value < fill_prob + cross_prob {
+                        CellState::Crossed
+                    } else {
+                        CellState::Empty
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// 行・列を入れ替えて作った新しいパズルをJavaScriptに返すためのデータ構造
+#[derive(Serialize, Deserialize)]
+pub struct ScrambleResult {
+    grid: Vec<Vec<CellState>>,  // 行・列を入れ替えた完成盤面
+    row_rules: Vec<Vec<usize>>, // 新しい盤面から再計算した行ルール
+    col_rules: Vec<Vec<usize>>, // 新しい盤面から再計算した列ルール
+    row_order: Vec<usize>,      // 新しい盤面の各行が、元の盤面の何行目だったか
+    col_order: Vec<usize>,      // 新しい盤面の各列が、元の盤面の何列目だったか
+    unique: Option<bool>,       // 一意解かどうか（チェックしなかった場合は`None`）
+}
+
+/// 完成盤面の行と列の順序を入れ替え、ルールを再計算した新しいパズルを作る関数
+/// 同じ`seed`からは必ず同じ並べ替えが得られるので、生成したパズルを再現できる
+///
+/// # Arguments
+/// * `grid_js` - 元になる完成盤面
+/// * `seed` - 並べ替えに使う乱数のシード値
+/// * `check_unique` - `true`の場合、生成したパズルが一意解かどうかも調べる
+///
+/// # Returns
+/// * `Ok(JsValue)` - 新しい盤面とルール（`ScrambleResult`）
+/// * `Err(JsValue)` - 盤面の形式が不正な場合のエラー
+#[wasm_bindgen]
+pub fn scramble_puzzle(
+    grid_js: JsValue,
+    seed: u32,
+    check_unique: bool,
+) -> Result<JsValue, JsValue> {
+    let grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(grid_js)?;
+    let rows = grid.len();
+    let cols = grid_width(&grid).map_err(|e| JsValue::from_str(&e))?;
+
+    let mut rng = SeededRng::new(seed as u64);
+    let mut row_order: Vec<usize> = (0..rows).collect();
+    let mut col_order: Vec<usize> = (0..cols).collect();
+    rng.shuffle(&mut row_order);
+    rng.shuffle(&mut col_order);
+
+    let scrambled: Vec<Vec<CellState>> = row_order
+        .iter()
+        .map(|&r| col_order.iter().map(|&c| grid[r][c]).collect())
+        .collect();
+    let (row_rules, col_rules) = rules_from_grid(&scrambled);
+
+    // 一意性のチェックは、空の盤面から解が2つ見つかるかどうかで判定する
+    let unique = check_unique.then(|| {
+        let propagator = Propagator::new(rows, cols, &row_rules, &col_rules);
+        let empty = vec![vec![CellState::Empty; cols]; rows];
+        find_solutions(&propagator, empty, 2).len() == 1
+    });
+
+    let result = ScrambleResult {
+        grid: scrambled,
+        row_rules,
+        col_rules,
+        row_order,
+        col_order,
+        unique,
+    };
+    Ok(serde_wasm_bindgen::to_value(&result)?)
+}
+
+/// `puzzle_from_bitmap`に渡せる画像の形式
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Bitmap {
+    /// `true`のピクセルを「塗り」とする白黒画像
+    Mono(Vec<Vec<bool>>),
+    /// `ImageData`と同じ並びのRGBA画像
+    Rgba {
+        width: usize,
+        height: usize,
+        data: Vec<u8>,
+    },
+}
+
+impl Bitmap {
+    /// 画像を盤面に変換する
+    /// 明るさが`threshold`未満のピクセルを「塗り」、それ以外を「×」にする
+    fn to_grid(&self, threshold: u8) -> Result<Vec<Vec<CellState>>, String> {
+        let cell = |filled: bool| {
+            if filled {
+                CellState::Filled
+            } else {
+                CellState::Crossed
+            }
+        };
+        match self {
+            Bitmap::Mono(pixels) => Ok(pixels
+                .iter()
+                .map(|row| row.iter().map(|&p| cell(p)).collect())
+                .collect()),
+            Bitmap::Rgba {
+                width,
+                height,
+                data,
+            } => {
+                if width.checked_mul(*height).and_then(|n| n.checked_mul(4)) != Some(data.len()) {
+                    return Err(format!(
+                        "画像のデータ長（{}）が{}x{}のRGBAと一致しません",
+                        data.len(),
+                        width,
+                        height
+                    ));
+                }
+                if *width == 0 {
+                    return Ok(vec![Vec::new(); *height]);
+                }
+                Ok(data
+                    .chunks(width * 4)
+                    .map(|row| {
+                        row.chunks(4)
+                            .map(|px| cell(rgba_brightness(px) < threshold as u32))
+                            .collect()
+                    })
+                    .collect())
+            }
+        }
+    }
+}
+
+/// RGBAのピクセルの明るさ（0〜255）を求める
+/// 透明な部分は白い背景に重ねたものとして扱う
+fn rgba_brightness(px: &[u8]) -> u32 {
+    let [r, g, b, a] = [px[0], px[1], px[2], px[3]].map(u32::from);
+    let luma = (r * 299 + g * 587 + b * 114) / 1000;
+    (luma * a + 255 * (255 - a)) / 255
+}
+
+/// 画像から作ったパズルをJavaScriptに返すためのデータ構造
+#[derive(Serialize, Deserialize)]
+pub struct BitmapPuzzleResult {
+    grid: Vec<Vec<CellState>>,  // 画像から作った完成盤面
+    row_rules: Vec<Vec<usize>>, // 盤面から計算した行ルール
+    col_rules: Vec<Vec<usize>>, // 盤面から計算した列ルール
+    unique: bool,               // ルールから解が一意に決まるかどうか
+    warning: Option<String>,    // 一意でない場合の警告（一意なら`None`）
+}
+
+/// 白黒またはRGBAの画像から、ノノグラムのパズルを作る関数
+///
+/// # Arguments
+/// * `bitmap_js` - `boolean`の2次元配列、または`{ width, height, data }`（`ImageData`と同じ並びのRGBA画像）
+///   `data`は通常の配列で渡す（`ImageData`からは`Array.from(imageData.data)`で変換できる）
+/// * `threshold` - RGBA画像で「塗り」とみなす明るさの上限（この値未満のピクセルが「塗り」になる）
+///
+/// # Returns
+/// * `Ok(JsValue)` - 盤面とルール、一意性のチェック結果
+/// * `Err(JsValue)` - 画像の形式が不正な場合のエラー
+#[wasm_bindgen]
+pub fn puzzle_from_bitmap(bitmap_js: JsValue, threshold: u8) -> Result<JsValue, JsValue> {
+    let bitmap: Bitmap = serde_wasm_bindgen::from_value(bitmap_js)?;
+    let grid = bitmap
+        .to_grid(threshold)
+        .map_err(|e| JsValue::from_str(&e))?;
+    let rows = grid.len();
+    let cols = grid_width(&grid).map_err(|e| JsValue::from_str(&e))?;
+    let (row_rules, col_rules) = rules_from_grid(&grid);
+
+    // 一意性のチェックは、空の盤面から解が2つ見つかるかどうかで判定する
+    let propagator = Propagator::new(rows, cols, &row_rules, &col_rules);
+    let empty = vec![vec![CellState::Empty; cols]; rows];
+    let unique = find_solutions(&propagator, empty, 2).len() == 1;
+    let warning = (!unique)
+        .then(|| "このルールには複数の解があるため、画像どおりに解けるとは限りません".to_string());
+
+    let result = BitmapPuzzleResult {
+        grid,
+        row_rules,
+        col_rules,
+        unique,
+        warning,
+    };
+    Ok(serde_wasm_bindgen::to_value(&result)?)
+}
+
+/// `estimate_solvability`の結果
+#[derive(Serialize, Deserialize)]
+pub struct SolvabilityEstimate {
+    samples: usize,           // 試行した回数
+    unique: usize,            // 一意解になった回数
+    logic_solvable: usize,    // 仮定を置かずに（伝播だけで）解き切れた回数
+    unique_rate: f64,         // 一意解になった割合の推定値
+    unique_std_error: f64,    // `unique_rate`の標準誤差
+    logic_solvable_rate: f64, // 伝播だけで解き切れた割合の推定値
+}
+
+/// 指定したサイズのランダムなパズルが一意解になる確率を、モンテカルロ法で推定する関数
+/// 各マスを1/2の確率で塗ったランダムな完成盤面からルールを作り、解が1つに決まるかを`count_solutions`と同じ探索で調べる
+/// 完成盤面からルールを作るので、どの試行にも必ず解がある（解を持つ確率は常に1になる）
+/// 試行ごとに完全な探索を行うため、大きな盤面では時間がかかる
+///
+/// # Arguments
+/// * `rows` - 盤面の行数
+/// * `cols` - 盤面の列数
+/// * `samples` - 試行回数
+/// * `seed` - 乱数のシード値（同じ値なら同じ結果になる）
+///
+/// # Returns
+/// * `Ok(JsValue)` - 推定結果（`SolvabilityEstimate`）
+/// * `Err(JsValue)` - 盤面サイズや試行回数が0の場合のエラー
+#[wasm_bindgen]
+pub fn estimate_solvability(
+    rows: usize,
+    cols: usize,
+    samples: usize,
+    seed: u32,
+) -> Result<JsValue, JsValue> {
+    if rows == 0 || cols == 0 {
+        return Err(JsValue::from_str("盤面サイズは1以上である必要があります"));
+    }
+    if samples == 0 {
+        return Err(JsValue::from_str("試行回数は1以上である必要があります"));
+    }
+
+    let mut rng = SeededRng::new(seed as u64);
+    let empty = vec![vec![CellState::Empty; cols]; rows];
+    let (mut unique, mut logic_solvable) = (0, 0);
+    for _ in 0..samples {
+        let grid = seeded_grid(&mut rng, rows, cols, 0.5, 0.5);
+        let (row_rules, col_rules) = rules_from_grid(&grid);
+        let propagator = Propagator::new(rows, cols, &row_rules, &col_rules);
+
+        // 伝播だけで全マスが決まれば、一意解であることも同時に分かる
+        let mut logical = empty.clone();
+        let solved = propagator.propagate_to_fixpoint(&mut logical).is_ok()
+            && logical
+                .iter()
+                .flatten()
+                .all(|&cell| cell != CellState::Empty);
+        if solved {
+            logic_solvable += 1;
+            unique += 1;
+        } else if find_solutions(&propagator, logical, 2).len() == 1 {
+            unique += 1;
+        }
+    }
+
+    let rate = |count: usize| count as f64 / samples as f64;
+    let unique_rate = rate(unique);
+    let estimate = SolvabilityEstimate {
+        samples,
+        unique,
+        logic_solvable,
+        unique_rate,
+        unique_std_error: (unique_rate * (1.0 - unique_rate) / samples as f64).sqrt(),
+        logic_solvable_rate: rate(logic_solvable),
+    };
+    Ok(serde_wasm_bindgen::to_value(&estimate)?)
+}
+
+// --- パズルの識別 ---
+
+/// バイト列の64ビットFNV-1aハッシュを計算する
+/// 実行環境やRustのバージョンによらず同じ値になるため、保存して比較するキーに使える
+fn fnv1a64(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xCBF2_9CE4_8422_2325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
+
+/// パズルのサイズとルールを、ハッシュの入力にする文字列に変換する
+/// 例: 2x3で行ルール`[[1], []]`、列ルール`[[1], [], []]`なら`"2x3|1;|1;;"`
+fn puzzle_key(
+    rows: usize,
+    cols: usize,
+    row_rules: &[Vec<usize>],
+    col_rules: &[Vec<usize>],
+) -> String {
+    let join = |rules: &[Vec<usize>]| {
+        rules
+            .iter()
+            .map(|rule| {
+                rule.iter()
+                    .map(|n| n.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            })
+            .collect::<Vec<_>>()
+            .join(";")
+    };
+    format!("{}x{}|{}|{}", rows, cols, join(row_rules), join(col_rules))
+}
+
+/// パズルを回転・鏡映・転置した8通りの形について、それぞれの`puzzle_key`を求める
+/// 左右反転では各行ルールの向きと列の並びが、上下反転では各列ルールの向きと行の並びが逆になる
+fn symmetric_keys(
+    rows: usize,
+    cols: usize,
+    row_rules: &[Vec<usize>],
+    col_rules: &[Vec<usize>],
+) -> Vec<String> {
+    let reversed_each = |rules: &[Vec<usize>]| -> Vec<Vec<usize>> {
+        rules
+            .iter()
+            .map(|rule| rule.iter().rev().copied().collect())
+            .collect()
+    };
+    let reversed_order =
+        |rules: &[Vec<usize>]| -> Vec<Vec<usize>> { rules.iter().rev().cloned().collect() };
+
+    let mut keys = Vec::with_capacity(8);
+    for transposed in [false, true] {
+        let (r, c, rr, cr) = if transposed {
+            (cols, rows, col_rules.to_vec(), row_rules.to_vec())
+        } else {
+            (rows, cols, row_rules.to_vec(), col_rules.to_vec())
+        };
+        for mirror_h in [false, true] {
+            for mirror_v in [false, true] {
+                let mut rr = rr.clone();
+                let mut cr = cr.clone();
+                if mirror_h {
+                    rr = reversed_each(&rr);
+                    cr = reversed_order(&cr);
+                }
+                if mirror_v {
+                    rr = reversed_order(&rr);
+                    cr = reversed_each(&cr);
+                }
+                keys.push(puzzle_key(r, c, &rr, &cr));
+            }
+        }
+    }
+    keys
+}
+
+/// 行・列のルールから、パズルを識別するフィンガープリントを計算する関数
+/// 重複パズルの検出やキャッシュのキーに使う
+///
+/// # Arguments
+/// * `rows` - 盤面の行数
+/// * `cols` - 盤面の列数
+/// * `row_rules_js` - 行ルールの配列
+/// * `col_rules_js` - 列ルールの配列
+/// * `canonical` - `true`なら回転・鏡映・転置で重なるパズルを同じフィンガープリントにする
+///
+/// # Returns
+/// * `Ok(String)` - 16桁の16進数文字列
+/// * `Err(JsValue)` - ルールの形式が不正な場合のエラー
+#[wasm_bindgen]
+pub fn puzzle_fingerprint(
+    rows: usize,
+    cols: usize,
+    row_rules_js: JsValue,
+    col_rules_js: JsValue,
+    canonical: bool,
+) -> Result<String, JsValue> {
+    let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+    let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+    // `[]`と`[0]`のような表記揺れで、同じパズルが別のフィンガープリントにならないようにする
+    let row_rules: Vec<Vec<usize>> = row_rules.iter().map(|rule| normalize_rule(rule)).collect();
+    let col_rules: Vec<Vec<usize>> = col_rules.iter().map(|rule| normalize_rule(rule)).collect();
+
+    let key = if canonical {
+        // 8通りの形のうち、文字列として最小のものを正規形とする
+        symmetric_keys(rows, cols, &row_rules, &col_rules)
+            .into_iter()
+            .min()
+            .unwrap_or_default()
+    } else {
+        puzzle_key(rows, cols, &row_rules, &col_rules)
+    };
+    Ok(format!("{:016x}", fnv1a64(key.as_bytes())))
+}
+
+// --- 難易度の推定 ---
+
+/// `quick_difficulty_tag`で使う特徴量の重み（塗り率が低いほど難しい）
+const QUICK_WEIGHT_SPARSITY: f64 = 0.4;
+/// `quick_difficulty_tag`で使う特徴量の重み（最長ブロックが短いほど難しい）
+const QUICK_WEIGHT_SHORT_BLOCKS: f64 = 0.35;
+/// `quick_difficulty_tag`で使う特徴量の重み（1ラインあたりのブロック数が多いほど難しい）
+const QUICK_WEIGHT_FRAGMENTATION: f64 = 0.25;
+/// スコアがこの値未満なら"easy"
+const QUICK_EASY_THRESHOLD: f64 = 0.45;
+/// スコアがこの値未満なら"medium"、以上なら"hard"
+const QUICK_MEDIUM_THRESHOLD: f64 = 0.6;
+
+/// 実際に解かずに、ルールの特徴量だけから難易度タグ（"easy"/"medium"/"hard"）を推定する関数
+/// 大量のパズルを一次分類するためのスクリーニング用で、厳密な難易度評価ではない
+///
+/// # Arguments
+/// * `rows` - 盤面の行数
+/// * `cols` - 盤面の列数
+/// * `row_rules_js` - 行ルールの配列
+/// * `col_rules_js` - 列ルールの配列
+///
+/// # Returns
+/// * `Ok(String)` - 推定した難易度タグ
+/// * `Err(JsValue)` - ルールの形式が不正な場合や、盤面サイズが0の場合のエラー
+#[wasm_bindgen]
+pub fn quick_difficulty_tag(
+    rows: usize,
+    cols: usize,
+    row_rules_js: JsValue,
+    col_rules_js: JsValue,
+) -> Result<String, JsValue> {
+    let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+    let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+    if rows == 0 || cols == 0 {
+        return Err(JsValue::from_str("盤面サイズは1以上である必要があります"));
+    }
+
+    // 特徴量1: 盤面全体に対する塗りマスの割合（行ルールの総和から求める）
+    let filled: usize = row_rules.iter().flatten().sum();
+    let fill_ratio = (filled as f64 / (rows * cols) as f64).min(1.0);
+
+    // 特徴量2: 最長ブロックの長さの、長い方の辺に対する割合
+    let longest = row_rules
+        .iter()
+        .chain(&col_rules)
+        .flatten()
+        .copied()
+        .max()
+        .unwrap_or(0);
+    let longest_ratio = (longest as f64 / rows.max(cols) as f64).min(1.0);
+
+    // 特徴量3: 1ラインあたりの平均ブロック数を、置ける最大ブロック数（ライン長の半分）で割ったもの
+    let lines = (rows + cols) as f64;
+    let blocks = row_rules
+        .iter()
+        .chain(&col_rules)
+        .map(|rule| rule.iter().filter(|&&n| n > 0).count())
+        .sum::<usize>() as f64;
+    let max_blocks = ((rows + cols) as f64 / 2.0 / 2.0).max(1.0);
+    let fragmentation = (blocks / lines / max_blocks).min(1.0);
+
+    let score = (QUICK_WEIGHT_SPARSITY * (1.0 - fill_ratio)
+        + QUICK_WEIGHT_SHORT_BLOCKS * (1.0 - longest_ratio)
+        + QUICK_WEIGHT_FRAGMENTATION * fragmentation)
+        / (QUICK_WEIGHT_SPARSITY + QUICK_WEIGHT_SHORT_BLOCKS + QUICK_WEIGHT_FRAGMENTATION);
+
+    let tag = if score < QUICK_EASY_THRESHOLD {
+        "easy"
+    } else if score < QUICK_MEDIUM_THRESHOLD {
+        "medium"
+    } else {
+        "hard"
+    };
+    Ok(tag.to_string())
+}
+
+// --- 特徴量の抽出 ---
+
+/// ライン1本のルールから求めた特徴量
+#[derive(Serialize, Deserialize)]
+pub struct LineFeatures {
+    axis: String,       // ラインの向き（"row" または "column"）
+    index: usize,       // ラインの番号（0始まり）
+    block_count: usize, // ブロックの数
+    filled: usize,      // 塗りマスの総数
+    max_block: usize,   // 最長ブロックの長さ
+    mean_block: f64,    // ブロックの平均の長さ（ブロックがなければ0）
+    slack: usize,       // ブロックを左に詰めて置いたときの右側の余り（隙間の自由度）
+}
+
+/// パズル全体の特徴量をJavaScriptに返すためのデータ構造
+#[derive(Serialize, Deserialize)]
+pub struct PuzzleFeatures {
+    rows: usize,
+    cols: usize,
+    total_blocks: usize,      // 全ラインのブロック数の合計
+    total_filled: usize,      // 塗りマスの総数（行ルールの総和）
+    fill_ratio: f64,          // 盤面全体に対する塗りマスの割合
+    max_block: usize,         // 全ラインを通じた最長ブロックの長さ
+    mean_block: f64,          // 全ラインを通じたブロックの平均の長さ
+    mean_slack: f64,          // ラインごとの隙間の自由度の平均
+    min_slack: usize,         // ラインごとの隙間の自由度の最小値（0のラインは一意に決まる）
+    lines: Vec<LineFeatures>, // ラインごとの特徴量（行→列の順）
+}
+
+/// ライン1本のルールから特徴量を求める
+fn line_features(axis: Axis, index: usize, size: usize, rule: &[usize]) -> LineFeatures {
+    let blocks: Vec<usize> = rule.iter().copied().filter(|&n| n > 0).collect();
+    let filled: usize = blocks.iter().sum();
+    // ブロックの間には最低1マスの隙間が必要
+    let min_len = filled + blocks.len().saturating_sub(1);
+    LineFeatures {
+        axis: axis.key().to_string(),
+        index,
+        block_count: blocks.len(),
+        filled,
+        max_block: blocks.iter().copied().max().unwrap_or(0),
+        mean_block: if blocks.is_empty() {
+            0.0
+        } else {
+            filled as f64 / blocks.len() as f64
+        },
+        slack: size.saturating_sub(min_len),
+    }
+}
+
+/// 実際に解かずに、ルールだけから求められる統計的な特徴量を抽出する関数
+/// 難易度推定モデルの入力やパズルの分類に使う
+///
+/// # Arguments
+/// * `rows` - 盤面の行数
+/// * `cols` - 盤面の列数
+/// * `row_rules_js` - 行ルールの配列
+/// * `col_rules_js` - 列ルールの配列
+///
+/// # Returns
+/// * `Ok(JsValue)` - `PuzzleFeatures`のオブジェクト
+/// * `Err(JsValue)` - ルールの形式が不正な場合のエラー
+#[wasm_bindgen]
+pub fn puzzle_features(
+    rows: usize,
+    cols: usize,
+    row_rules_js: JsValue,
+    col_rules_js: JsValue,
+) -> Result<JsValue, JsValue> {
+    let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+    let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+
+    // 行は列数、列は行数の長さのラインになる
+    let lines: Vec<LineFeatures> = row_rules
+        .iter()
+        .enumerate()
+        .map(|(r, rule)| line_features(Axis::Row, r, cols, rule))
+        .chain(
+            col_rules
+                .iter()
+                .enumerate()
+                .map(|(c, rule)| line_features(Axis::Column, c, rows, rule)),
+        )
+        .collect();
+
+    let total_blocks: usize = lines.iter().map(|line| line.block_count).sum();
+    let total_filled: usize = row_rules.iter().flatten().sum();
+    let block_cells: usize = lines.iter().map(|line| line.filled).sum();
+    let features = PuzzleFeatures {
+        rows,
+        cols,
+        total_blocks,
+        total_filled,
+        fill_ratio: if rows * cols == 0 {
+            0.0
+        } else {
+            total_filled as f64 / (rows * cols) as f64
+        },
+        max_block: lines.iter().map(|line| line.max_block).max().unwrap_or(0),
+        mean_block: if total_blocks == 0 {
+            0.0
+        } else {
+            block_cells as f64 / total_blocks as f64
+        },
+        mean_slack: if lines.is_empty() {
+            0.0
+        } else {
+            lines.iter().map(|line| line.slack).sum::<usize>() as f64 / lines.len() as f64
+        },
+        min_slack: lines.iter().map(|line| line.slack).min().unwrap_or(0),
+        lines,
+    };
+    Ok(serde_wasm_bindgen::to_value(&features)?)
+}
+
+/// `possibility_count`が128ビットに収まらなかったときに返す番兵値（「非常に多い」を表す）
+const POSSIBILITY_COUNT_OVERFLOW: u128 = u128::MAX;
+
+/// 二項係数`C(n, k)`を計算する
+/// 128ビットに収まらない場合は`None`を返す
+fn binomial(n: u128, k: u128) -> Option<u128> {
+    if k > n {
+        return Some(0);
+    }
+    let k = k.min(n - k);
+    // C(n, i) * (n - i) / (i + 1) = C(n, i + 1) を、掛ける前に最大公約数で約分してから計算する
+    // C(n, i) / g と (i + 1) / g は互いに素なので、(n - i) は (i + 1) / g で割り切れ、
+    // 掛け算の結果はC(n, i + 1)そのものになる（途中の値が結果より大きくならないので、溢れるのは結果が収まらないときだけ）
+    (0..k).try_fold(1u128, |acc, i| {
+        let g = gcd(acc, i + 1);
+        (acc / g).checked_mul((n - i) / ((i + 1) / g))
+    })
+}
+
+/// 最大公約数をユークリッドの互除法で求める
+fn gcd(mut a: u128, mut b: u128) -> u128 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// ライン1本の配置パターンの数を、パターンを列挙せずに求める
+/// ブロック数を`b`、ブロックを詰めて置いたときの余りを`f`とすると、パターン数は`C(f + b, b)`になる
+/// 128ビットに収まらない場合は`POSSIBILITY_COUNT_OVERFLOW`を返す
+fn possibility_count(size: usize, rule: &[usize]) -> u128 {
+    let Ok(rule) = ValidatedRule::new(rule, size) else {
+        return 0;
+    };
+    let free = (size - rule.min_len()) as u128;
+    let blocks = rule.blocks.len() as u128;
+    binomial(free + blocks, blocks).unwrap_or(POSSIBILITY_COUNT_OVERFLOW)
+}
+
+/// ラインごとの配置パターン数をJavaScriptに返すためのデータ構造
+#[derive(Serialize, Deserialize)]
+pub struct PossibilityCounts {
+    rows: Vec<Option<f64>>, // 各行のパターン数（`null`は128ビットに収まらないほど多いことを表す）
+    cols: Vec<Option<f64>>, // 各列のパターン数（同上）
+}
+
+/// 各ラインの配置パターン数を、パターンを実際に生成せずに計算する関数
+/// 大きな盤面で`solve_puzzle`にかかるコストを事前に見積もるのに使う
+/// JavaScriptの数値は2^53を超えると近似値になるが、桁の大きさを比べる用途には十分な精度がある
+///
+/// # Arguments
+/// * `rows` - 盤面の行数
+/// * `cols` - 盤面の列数
+/// * `row_rules_js` - 行ルールの配列
+/// * `col_rules_js` - 列ルールの配列
+///
+/// # Returns
+/// * `Ok(JsValue)` - 行・列ごとのパターン数
+/// * `Err(JsValue)` - ルールの形式が不正な場合のエラー
+#[wasm_bindgen]
+pub fn possibility_counts(
+    rows: usize,
+    cols: usize,
+    row_rules_js: JsValue,
+    col_rules_js: JsValue,
+) -> Result<JsValue, JsValue> {
+    let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+    let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+
+    let to_js = |count: u128| (count != POSSIBILITY_COUNT_OVERFLOW).then_some(count as f64);
+    let counts = PossibilityCounts {
+        rows: row_rules
+            .iter()
+            .map(|rule| to_js(possibility_count(cols, rule)))
+            .collect(),
+        cols: col_rules
+            .iter()
+            .map(|rule| to_js(possibility_count(rows, rule)))
+            .collect(),
+    };
+    Ok(serde_wasm_bindgen::to_value(&counts)?)
+}
+
+// --- デバッグ出力 ---
+
+/// 配置パターンの確認用の出力をJavaScriptに返すためのデータ構造
+#[derive(Serialize, Deserialize)]
+pub struct PossibilityDump {
+    patterns: Vec<String>, // 先頭から`limit`個のパターン（`#`が塗り、`.`が空）
+    total: usize,          // `generate_possibilities`が生成したパターンの総数
+    expected_total: Option<f64>, // 組み合わせの公式から求めたパターン数（`possibility_count`、128ビットに収まらない場合は`null`）
+}
+
+/// `generate_possibilities`が生成する配置パターンを、目視で確認できる文字列にして返す関数
+/// アルゴリズムを変更した際に、パターンの並びや総数が正しいかを確かめるための検証用ツール
+/// `total`と`expected_total`が食い違っていれば、生成か数え上げのどちらかに誤りがある
+/// 内部では全パターンを生成するため、パターン数が膨大になるルールには使わないこと
+///
+/// # Arguments
+/// * `size` - ラインの長さ
+/// * `rule_js` - ルール（数値の配列）
+/// * `limit` - 出力するパターンの最大数
+///
+/// # Returns
+/// * `Ok(JsValue)` - 先頭のパターンと総数（`PossibilityDump`）
+/// * `Err(JsValue)` - ルールの形式が不正な場合のエラー
+#[wasm_bindgen]
+pub fn debug_possibilities(
+    size: usize,
+    rule_js: JsValue,
+    limit: usize,
+) -> Result<JsValue, JsValue> {
+    let rule: Vec<usize> = serde_wasm_bindgen::from_value(rule_js)?;
+    let possibilities = generate_possibilities(size, &rule);
+    let expected = possibility_count(size, &rule);
+
+    let dump = PossibilityDump {
+        patterns: possibilities
+            .iter()
+            .take(limit)
+            .map(|pattern| {
+                pattern
+                    .iter()
+                    .map(|&cell| if cell == 1 { '#' } else { '.' })
+                    .collect()
+            })
+            .collect(),
+        total: possibilities.len(),
+        expected_total: (expected != POSSIBILITY_COUNT_OVERFLOW).then_some(expected as f64),
+    };
+    Ok(serde_wasm_bindgen::to_value(&dump)?)
+}
+
+// --- 解析の検証 ---
+
+/// 完成盤面から作ったルールを空の盤面に適用したとき、確定したマスが全て元の盤面と一致するかを調べる
+/// 元の盤面が必ず解の1つになるので、矛盾が報告されたり、元の盤面と違う状態に確定したりすればソルバーのバグである
+/// 「塗り」以外のマスは、全て「空白」として扱う
+/// 任意の盤面について成り立つ性質なので、proptestなどのプロパティベーステストで生成した盤面をそのまま渡して検証に使える
+pub(crate) fn roundtrip_holds(solution: &[Vec<CellState>]) -> bool {
+    let rows = solution.len();
+    let cols = solution.first().map_or(0, Vec::len);
+    let (row_rules, col_rules) = rules_from_grid(solution);
+
+    let mut grid = vec![vec![CellState::Empty; cols]; rows];
+    if Propagator::new(rows, cols, &row_rules, &col_rules)
+        .propagate(&mut grid)
+        .is_err()
+    {
+        return false;
+    }
+    grid.iter().zip(solution).all(|(row, expected_row)| {
+        row.iter()
+            .zip(expected_row)
+            .all(|(&cell, &expected)| match cell {
+                CellState::Empty => true,
+                CellState::Filled => expected == CellState::Filled,
+                CellState::Crossed => expected != CellState::Filled,
+            })
+    })
+}
+
+/// 完成盤面を使って、ソルバーの解析結果の妥当性を検証する関数
+/// 完成盤面からルールを作り、空の盤面に`solve_puzzle`と同じ解析を行って、確定したマスが元の盤面と矛盾しないことを確かめる
+/// ランダムな盤面を大量に生成して呼び出せば、ファジングやリグレッションの検出に使える
+///
+/// # Arguments
+/// * `grid_js` - 完成盤面（「塗り」以外のマスは空白として扱う）
+///
+/// # Returns
+/// * `Ok(bool)` - 確定したマスが全て元の盤面と一致すれば`true`、一致しなければ（ソルバーのバグであれば）`false`
+/// * `Err(JsValue)` - 盤面の形式が不正な場合のエラー
+#[wasm_bindgen]
+pub fn test_roundtrip(grid_js: JsValue) -> Result<bool, JsValue> {
+    let grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(grid_js)?;
+    grid_width(&grid).map_err(|e| JsValue::from_str(&e))?;
+    Ok(roundtrip_holds(&grid))
+}
+
+/// `verify_logical_uniqueness`の結果
+#[derive(Serialize, Deserialize)]
+pub struct LogicalUniqueness {
+    logically_unique: bool, // 仮定を置かずに解析だけで元の盤面が復元できたか（推測不要で一意に解けるか）
+    message: String,        // ユーザーに表示する判定結果
+    row_rules: Vec<Vec<usize>>, // 盤面から抽出した行ルール
+    col_rules: Vec<Vec<usize>>, // 盤面から抽出した列ルール
+    grid: Vec<Vec<CellState>>, // 空の盤面から解析した結果
+    mismatches: Vec<(usize, usize)>, // 解析結果が元の盤面と一致しないマスの座標（行, 列）
+}
+
+/// 完成盤面からルールを抽出し、空の盤面とそのルールで解析して元の盤面に戻るかを検証する関数
+/// 戻れば推測の要らない良いパズル、戻らなければ推測が必要か複数の解があるパズルと判定する
+/// 作問したパズルの品質確認に使う（ソルバー自体の検証には`test_roundtrip`を使う）
+///
+/// # Arguments
+/// * `grid_js` - 完成盤面（「塗り」以外のマスは「×」として扱う）
+///
+/// # Returns
+/// * `Ok(JsValue)` - 判定結果と、一致しなかったマスの位置（`LogicalUniqueness`）
+/// * `Err(JsValue)` - 盤面の形式が不正な場合のエラー
+#[wasm_bindgen]
+pub fn verify_logical_uniqueness(grid_js: JsValue) -> Result<JsValue, JsValue> {
+    let solution: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(grid_js)?;
+    let cols = grid_width(&solution).map_err(|e| JsValue::from_str(&e))?;
+    let rows = solution.len();
+    let (row_rules, col_rules) = rules_from_grid(&solution);
+
+    // 完成盤面が解の1つなので、解析が矛盾することはない（矛盾すればソルバーのバグとして全マスを不一致にする）
+    let mut grid = vec![vec![CellState::Empty; cols]; rows];
+    let propagated = Propagator::new(rows, cols, &row_rules, &col_rules)
+        .propagate_to_fixpoint(&mut grid)
+        .is_ok();
+    let mut mismatches = Vec::new();
+    for (r, (row, expected_row)) in grid.iter().zip(&solution).enumerate() {
+        for (c, (&cell, &expected)) in row.iter().zip(expected_row).enumerate() {
+            let matches = match cell {
+                CellState::Empty => false,
+                CellState::Filled => expected == CellState::Filled,
+                CellState::Crossed => expected != CellState::Filled,
+            };
+            if !propagated || !matches {
+                mismatches.push((r, c));
+            }
+        }
+    }
+
+    let logically_unique = mismatches.is_empty();
+    let message = if logically_unique {
+        "推測なしで一意に解ける良いパズルです".to_string()
+    } else {
+        format!(
+            "{}マスが解析だけでは決まりません（推測が必要か、複数の解があります）",
+            mismatches.len()
+        )
+    };
+    Ok(serde_wasm_bindgen::to_value(&LogicalUniqueness {
+        logically_unique,
+        message,
+        row_rules,
+        col_rules,
+        grid,
+        mismatches,
+    })?)
+}
+
+// --- ルールの入力処理 ---
+
+/// `"1 2 1"`のような文字列を、ルールの数値列に変換する関数
+/// 区切りには空白とカンマを使え、空文字列や`"0"`だけの入力は空ルールとして扱う
+fn parse_rule_text(text: &str) -> Result<Vec<usize>, String> {
+    let rule = text
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|token| !token.is_empty())
+        .map(|token| {
+            token
+                .parse::<usize>()
+                .map_err(|_| format!("ルールに数字以外が含まれています: \"{}\"", token))
+        })
+        .collect::<Result<Vec<usize>, String>>()?;
+    // "0"のみのルールは、ブロックのない空ルールと同じ意味
+    if rule == [0] {
+        return Ok(Vec::new());
+    }
+    Ok(rule)
+}
+
+/// テキストボックスに入力された1ライン分のルール（例: `"1 2 1"`）をパースする関数
+#[wasm_bindgen]
+pub fn parse_rule(s: &str) -> Result<Vec<usize>, JsValue> {
+    parse_rule_text(s).map_err(|e| JsValue::from_str(&e))
+}
+
+/// テキストエリアに改行区切りで入力された全ラインのルールを、まとめてパースする関数
+/// 空行は空ルールとして扱い、エラーの場合は何行目に問題があるかをメッセージに含める
+#[wasm_bindgen]
+pub fn parse_rules(s: &str) -> Result<JsValue, JsValue> {
+    let rules = s
+        .trim()
+        .lines()
+        .enumerate()
+        .map(|(i, line)| parse_rule_text(line).map_err(|e| format!("{}行目: {}", i + 1, e)))
+        .collect::<Result<Vec<Vec<usize>>, String>>()
+        .map_err(|e| JsValue::from_str(&e))?;
+    Ok(serde_wasm_bindgen::to_value(&rules)?)
+}
+
+/// CSV（またはスプレッドシートからコピーしたタブ区切りのテキスト）の全ラインのルールを、まとめてパースする関数
+/// CSVの1行が1ラインのルールで、各セルが1つのブロックの長さになる
+/// 空のセルは無視し、セルが全て空の行（または空行）は空ルールとして扱う
+/// `"0"`だけの行も、`parse_rule`と同様に空ルールとして扱う
+///
+/// # Arguments
+/// * `input` - CSVのテキスト（区切りはカンマまたはタブ、セルを囲む`"`は取り除く）
+/// * `axis` - 読み込むルールの向き（エラーメッセージに使う）
+///
+/// # Returns
+/// * `Ok(JsValue)` - 各ラインのルールの配列
+/// * `Err(JsValue)` - 数字として読めないセルがある場合の、その位置（CSVの行・列）を含むエラー
+#[wasm_bindgen]
+pub fn parse_rules_csv(input: &str, axis: Axis) -> Result<JsValue, JsValue> {
+    // 末尾の改行だけを取り除く（先頭の空行は空ルールとして残す）
+    let rules = input
+        .trim_end_matches(['\r', '\n'])
+        .lines()
+        .enumerate()
+        .map(|(i, line)| {
+            let rule = line
+                .split([',', '\t'])
+                .enumerate()
+                .map(|(j, cell)| (j, cell.trim().trim_matches('"').trim()))
+                .filter(|(_, cell)| !cell.is_empty())
+                .map(|(j, cell)| {
+                    cell.parse::<usize>().map_err(|_| {
+                        format!(
+                            "{}ルールのCSV {}行目{}列目: 数字ではありません: \"{}\"",
+                            axis.label(),
+                            i + 1,
+                            j + 1,
+                            cell
+                        )
+                    })
+                })
+                .collect::<Result<Vec<usize>, String>>()?;
+            // "0"のみのルールは、ブロックのない空ルールと同じ意味
+            Ok(if rule == [0] { Vec::new() } else { rule })
+        })
+        .collect::<Result<Vec<Vec<usize>>, String>>()
+        .map_err(|e| JsValue::from_str(&e))?;
+    Ok(serde_wasm_bindgen::to_value(&rules)?)
+}
+
+/// 行ルールの候補を探すときに調べる、配置パターンの最大数
+const MAX_SUGGESTION_PATTERNS: usize = 100_000;
+
+/// 行ルールの入力補完の結果をJavaScriptに返すためのデータ構造
+#[derive(Serialize, Deserialize)]
+pub struct RuleSuggestion {
+    candidates: Vec<Vec<usize>>, // 列ルールと整合する行ルールの候補（重複なし）
+    forced: Vec<CellState>,      // 列ルールだけから確定する、その行のマスの状態
+    warnings: Vec<String>,       // 列ルールの矛盾など、候補の計算で気づいた問題
+}
+
+/// 作問エディタで行ルールを入力するときに、入力済みの列ルールと整合する行ルールの候補を提案する関数
+/// 各列のルールを空の列に適用して、指定した行で「塗り」や「×」に確定するマスを求め、
+/// 確定していないマスの塗り方を変えながら、その行のルールを候補として集める
+/// 列ごとに独立して調べるだけなので、候補が全て実際に解けるパズルになるとは限らない
+///
+/// # Arguments
+/// * `rows` - 盤面の行数
+/// * `cols` - 盤面の列数
+/// * `col_rules_js` - 列ルールの配列
+/// * `row_index` - 候補を求める行の番号（0始まり）
+/// * `limit` - 返す候補の最大数
+///
+/// # Returns
+/// * `Ok(JsValue)` - 候補と警告（`RuleSuggestion`）
+/// * `Err(JsValue)` - 入力が不正な場合、または行番号が範囲外の場合のエラー
+#[wasm_bindgen]
+pub fn suggest_rule(
+    rows: usize,
+    cols: usize,
+    col_rules_js: JsValue,
+    row_index: usize,
+    limit: usize,
+) -> Result<JsValue, JsValue> {
+    let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+    if col_rules.len() != cols {
+        return Err(JsValue::from_str(&format!(
+            "列ルールの数（{}）が列数（{}）と一致しません",
+            col_rules.len(),
+            cols
+        )));
+    }
+    if row_index >= rows {
+        return Err(JsValue::from_str(&format!(
+            "行番号{}は盤面の範囲外です（行数: {}）",
+            row_index + 1,
+            rows
+        )));
+    }
+
+    // 列ごとに、空の列にルールを適用して、指定した行のマスが確定するかを調べる
+    // 矛盾する列は警告に残し、そのマスは確定していないものとして扱う
+    let mut warnings = Vec::new();
+    let empty_column = vec![CellState::Empty; rows];
+    let forced: Vec<CellState> = col_rules
+        .iter()
+        .enumerate()
+        .map(
+            |(c, rule)| match compile_rule(rows, rule).apply(&empty_column) {
+                Ok(line) => line[row_index],
+                Err(e) => {
+                    warnings.push(format!("{} {}: {}", Axis::Column.label(), c + 1, e));
+                    CellState::Empty
+                }
+            },
+        )
+        .collect();
+
+    // 確定していないマスを「塗り」→「×」の順に試し、行全体の配置からルールを求める
+    fn collect(
+        line: &mut [CellState],
+        forced: &[CellState],
+        position: usize,
+        limit: usize,
+        visited: &mut usize,
+        candidates: &mut Vec<Vec<usize>>,
+    ) {
+        if candidates.len() >= limit || *visited >= MAX_SUGGESTION_PATTERNS {
+            return;
+        }
+        if position == line.len() {
+            *visited += 1;
+            let rule = line_to_rule(line);
+            if !candidates.contains(&rule) {
+                candidates.push(rule);
+            }
+            return;
+        }
+        if forced[position] != CellState::Empty {
+            collect(line, forced, position + 1, limit, visited, candidates);
+            return;
+        }
+        for state in [CellState::Filled, CellState::Crossed] {
+            line[position] = state;
+            collect(line, forced, position + 1, limit, visited, candidates);
+        }
+    }
+    let mut candidates = Vec::new();
+    collect(
+        &mut forced.clone(),
+        &forced,
+        0,
+        limit,
+        &mut 0,
+        &mut candidates,
+    );
+
+    let suggestion = RuleSuggestion {
+        candidates,
+        forced,
+        warnings,
+    };
+    Ok(serde_wasm_bindgen::to_value(&suggestion)?)
+}
+
+/// ルールを収めるのに必要な最小のラインの長さを求める関数
+/// `ブロック長の合計 + (ブロック数 - 1)`で、これより短いラインにはルールが収まらない
+///
+/// # Arguments
+/// * `rule_js` - ルール（ブロック長の配列、0は無視する）
+///
+/// # Returns
+/// * `Ok(usize)` - 最小のラインの長さ（ブロックのないルールは0）
+/// * `Err(JsValue)` - 入力の形式が不正な場合のエラー
+#[wasm_bindgen]
+pub fn min_line_size(rule_js: JsValue) -> Result<usize, JsValue> {
+    let rule: Vec<usize> = serde_wasm_bindgen::from_value(rule_js)?;
+    Ok(rule_min_len(&rule))
+}
+
+/// ルールから提案する盤面の大きさをJavaScriptに返すためのデータ構造
+#[derive(Serialize, Deserialize)]
+pub struct BoardSizeSuggestion {
+    rows: usize,           // 提案する行数
+    cols: usize,           // 提案する列数
+    fits: bool, // ルールの本数どおりの盤面（行ルールの数×列ルールの数）に全てのルールが収まるか
+    too_long: Vec<String>, // ルールの本数どおりの盤面に収まらないラインの説明
+}
+
+/// 全ての行ルール・列ルールを収めるのに必要な最小の盤面の大きさを提案する関数
+/// 行数は列ルールの本数と最も長い列ルールの最小の長さの大きい方、列数は行ルールについて同様に求める
+/// ルールの本数どおりの盤面に収まらないルールがあれば、その一覧も返すので、ルールから盤面を起こすときの検証に使える
+///
+/// # Arguments
+/// * `row_rules_js` - 行ルールの配列
+/// * `col_rules_js` - 列ルールの配列
+///
+/// # Returns
+/// * `Ok(JsValue)` - 提案する盤面の大きさ（`BoardSizeSuggestion`）
+/// * `Err(JsValue)` - 入力の形式が不正な場合のエラー
+#[wasm_bindgen]
+pub fn suggest_board_size(
+    row_rules_js: JsValue,
+    col_rules_js: JsValue,
+) -> Result<JsValue, JsValue> {
+    let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+    let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+
+    // 行ルールは列数、列ルールは行数の長さのラインに収まる必要がある
+    let mut too_long = Vec::new();
+    let mut required = |axis: Axis, rules: &[Vec<usize>], size: usize| {
+        let mut longest = 0;
+        for (index, rule) in rules.iter().enumerate() {
+            let min_len = rule_min_len(rule);
+            if min_len > size {
+                too_long.push(format!(
+                    "{} {}: 最低{}マス必要ですが、長さは{}です",
+                    axis.label(),
+                    index + 1,
+                    min_len,
+                    size
+                ));
+            }
+            longest = longest.max(min_len);
+        }
+        longest
+    };
+    let min_cols = required(Axis::Row, &row_rules, col_rules.len());
+    let min_rows = required(Axis::Column, &col_rules, row_rules.len());
+
+    let suggestion = BoardSizeSuggestion {
+        rows: row_rules.len().max(min_rows),
+        cols: col_rules.len().max(min_cols),
+        fits: too_long.is_empty(),
+        too_long,
+    };
+    Ok(serde_wasm_bindgen::to_value(&suggestion)?)
+}
+/// `lint_rules`で検出するルールの入力ミスの種類
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleLintIssue {
+    /// ブロック長がラインの長さを超えている
+    BlockTooLong,
+    /// 空ルールを表す`[0]`以外の位置に0がある
+    MisplacedZero,
+    /// 各ブロックはラインに収まるが、ブロックと最低限の隙間の合計がラインの長さを超えている
+    RuleTooLong,
+    /// ルールの本数が盤面の行数・列数より少なく、このラインのルールがない
+    MissingRule,
+    /// ルールの本数が盤面の行数・列数より多く、このルールに対応するラインがない
+    ExtraRule,
+}
+
+/// `lint_rules`で検出した入力ミス1つ分
+#[derive(Serialize, Deserialize)]
+pub struct RuleLint {
+    axis: String,               // ラインの向き（"row" または "column"）
+    line_index: usize,          // ラインの番号（0始まり）
+    block_index: Option<usize>, // 問題のあるブロックの番号（0始まり、ルール全体の問題なら`null`）
+    issue: RuleLintIssue,       // 問題の種類
+    message: String,            // ユーザーに表示する説明
+}
+
+/// 解析を行う前に、ルールの明白な入力ミスをラインとブロックの位置付きで列挙する関数
+/// ブロック長だけを見る静的なチェックなので、作問エディタで入力のたびに呼び出すリアルタイムの検証に使える
+/// 行ルールと列ルールの塗りマスの総数の食い違いのような、ラインをまたぐ問題は調べない
+///
+/// # Arguments
+/// * `rows` - 盤面の行数
+/// * `cols` - 盤面の列数
+/// * `row_rules_js` - 行ルールの配列
+/// * `col_rules_js` - 列ルールの配列
+///
+/// # Returns
+/// * `Ok(JsValue)` - 検出した問題（`RuleLint`）の配列（行、列の順。問題がなければ空の配列）
+/// * `Err(JsValue)` - 入力の形式が不正な場合のエラー
+#[wasm_bindgen]
+pub fn lint_rules(
+    rows: usize,
+    cols: usize,
+    row_rules_js: JsValue,
+    col_rules_js: JsValue,
+) -> Result<JsValue, JsValue> {
+    let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+    let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+
+    let mut lints = Vec::new();
+    for (axis, rules, count, size) in [
+        (Axis::Row, &row_rules, rows, cols),
+        (Axis::Column, &col_rules, cols, rows),
+    ] {
+        let mut lint = |line_index, block_index, issue, message: String| {
+            lints.push(RuleLint {
+                axis: axis.key().to_string(),
+                line_index,
+                block_index,
+                issue,
+                message: format!("{} {}: {}", axis.label(), line_index + 1, message),
+            })
+        };
+        for (line_index, rule) in rules.iter().enumerate() {
+            if line_index >= count {
+                lint(
+                    line_index,
+                    None,
+                    RuleLintIssue::ExtraRule,
+                    format!("盤面の{}数（{}）を超えるルールです", axis.label(), count),
+                );
+                continue;
+            }
+            // `[0]`だけのルールは空ルールの書き方として認める
+            if rule.as_slice() != [0] {
+                for (block_index, &block) in rule.iter().enumerate() {
+                    if block == 0 {
+                        lint(
+                            line_index,
+                            Some(block_index),
+                            RuleLintIssue::MisplacedZero,
+                            format!(
+                                "{}番目のブロックが0です（0は空ルールの`0`としてだけ使えます）",
+                                block_index + 1
+                            ),
+                        );
+                    }
+                }
+            }
+            let mut too_long = false;
+            for (block_index, &block) in rule.iter().enumerate() {
+                if block > size {
+                    too_long = true;
+                    lint(
+                        line_index,
+                        Some(block_index),
+                        RuleLintIssue::BlockTooLong,
+                        format!(
+                            "{}番目のブロック（{}）がラインの長さ（{}）を超えています",
+                            block_index + 1,
+                            block,
+                            size
+                        ),
+                    );
+                }
+            }
+            // 個々のブロックが長すぎる場合は、ルール全体の長さの問題としては重ねて報告しない
+            let min_len = rule_min_len(rule);
+            if !too_long && min_len > size {
+                lint(
+                    line_index,
+                    None,
+                    RuleLintIssue::RuleTooLong,
+                    format!(
+                        "ブロックと隙間に最低{}マス必要ですが、ラインの長さは{}です",
+                        min_len, size
+                    ),
+                );
+            }
+        }
+        for line_index in rules.len()..count {
+            lint(
+                line_index,
+                None,
+                RuleLintIssue::MissingRule,
+                "ルールが入力されていません".to_string(),
+            );
+        }
+    }
+    Ok(serde_wasm_bindgen::to_value(&lints)?)
+}
+
+// --- 履歴付きの盤面 ---
+
+/// アンドゥ・リドゥの履歴に保存する盤面
+/// 四分木にした方が小さくなる疎な盤面は四分木で、そうでない密な盤面はそのままの形で保持する
+enum GridSnapshot {
+    Dense(Vec<Vec<CellState>>), // 通常の2次元配列
+    Sparse(QuadGrid),           // 盤面全体を覆う四分木
+}
+
+impl GridSnapshot {
+    /// 盤面を保存する
+    fn capture(grid: &[Vec<CellState>]) -> Self {
+        match QuadGrid::sparse(grid) {
+            Some(quad) => GridSnapshot::Sparse(quad),
+            None => GridSnapshot::Dense(grid.to_vec()),
+        }
+    }
+
+    /// 保存した盤面を、通常の2次元配列に戻す
+    fn restore(self) -> Vec<Vec<CellState>> {
+        match self {
+            GridSnapshot::Dense(grid) => grid,
+            GridSnapshot::Sparse(quad) => quad.to_grid(),
+        }
+    }
+}
+
+/// 盤面の状態と変更履歴をWASM側でまとめて管理する構造体
+/// マスの変更や1ステップ分の解析は全てアンドゥスタックに記録され、`undo`/`redo`で行き来できる
+#[wasm_bindgen]
+pub struct Board {
+    rows: usize,
+    cols: usize,
+    propagator: Propagator,             // 解析に使うコンパイル済みルール
+    grid: Vec<Vec<CellState>>,          // 現在の盤面
+    undo_stack: VecDeque<GridSnapshot>, // 変更前の盤面の履歴（新しいものが末尾）
+    redo_stack: Vec<GridSnapshot>,      // アンドゥで取り消した盤面の履歴
+    history_limit: usize,               // アンドゥスタックの上限（0なら無制限）
+}
+
+#[wasm_bindgen]
+impl Board {
+    /// 空の盤面とルールから、履歴付きの盤面を作る
+    ///
+    /// # Arguments
+    /// * `rows` - 盤面の行数
+    /// * `cols` - 盤面の列数
+    /// * `row_rules_js` - 行ルールの配列
+    /// * `col_rules_js` - 列ルールの配列
+    /// * `history_limit` - アンドゥできる操作の最大数（0なら無制限）
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        rows: usize,
+        cols: usize,
+        row_rules_js: JsValue,
+        col_rules_js: JsValue,
+        history_limit: usize,
+    ) -> Result<Board, JsValue> {
+        let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+        let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+        Board::from_rules(rows, cols, &row_rules, &col_rules, history_limit)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// 現在の盤面を返す
+    pub fn grid(&self) -> Result<JsValue, JsValue> {
+        Ok(serde_wasm_bindgen::to_value(&self.grid)?)
+    }
+
+    /// 指定したマスの状態を変更する
+    /// 変更はアンドゥスタックに記録される
+    pub fn set_cell(&mut self, row: usize, col: usize, state: CellState) -> Result<(), JsValue> {
+        if row >= self.rows || col >= self.cols {
+            return Err(JsValue::from_str("指定したマスは盤面の範囲外です"));
+        }
+        if self.grid[row][col] == state {
+            return Ok(());
+        }
+        self.record_history();
+        self.grid[row][col] = state;
+        Ok(())
+    }
+
+    /// 直前の操作を1つ取り消す
+    /// 取り消せる操作がなければ`false`を返す
+    pub fn undo(&mut self) -> bool {
+        let Some(previous) = self.undo_stack.pop_back() else {
+            return false;
+        };
+        let current = std::mem::replace(&mut self.grid, previous.restore());
+        self.redo_stack.push(GridSnapshot::capture(&current));
+        true
+    }
+
+    /// 取り消した操作を1つやり直す
+    /// やり直せる操作がなければ`false`を返す
+    pub fn redo(&mut self) -> bool {
+        let Some(next) = self.redo_stack.pop() else {
+            return false;
+        };
+        let current = std::mem::replace(&mut self.grid, next.restore());
+        self.undo_stack.push_back(GridSnapshot::capture(&current));
+        true
+    }
+
+    /// アンドゥできる操作が残っているかどうか
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    /// やり直せる操作が残っているかどうか
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    /// 全ての行、続いて全ての列を1回ずつ解析し、盤面を1ステップ分進める
+    /// 盤面に変化があった場合は、その変化もアンドゥスタックに記録される
+    ///
+    /// # Returns
+    /// * `Ok(JsValue)` - `solve_puzzle`と同じ形式の解析結果
+    pub fn solve_step(&mut self) -> Result<JsValue, JsValue> {
+        let mut next_grid = self.grid.clone();
+        let mut settled = SettledLines::new(self.rows, self.cols);
+        let result = match self
+            .propagator
+            .pass(&mut next_grid, 1, &mut settled, &mut |_| {})
+        {
+            Ok(true) => {
+                self.record_history();
+                self.grid = next_grid;
+                SolveResult {
+                    grid: self.grid.clone(),
+                    message: "確定できるマスを更新しました".to_string(),
+                    error: false,
+                    metrics: None,
+                }
+            }
+            Ok(false) => SolveResult {
+                grid: self.grid.clone(),
+                message: "これ以上自動で確定できるマスはありません".to_string(),
+                error: false,
+                metrics: None,
+            },
+            // 矛盾した場合は盤面を変更せずにエラーを返す
+            Err(e) => SolveResult {
+                grid: self.grid.clone(),
+                message: e.to_string(),
+                error: true,
+                metrics: None,
+            },
+        };
+        Ok(serde_wasm_bindgen::to_value(&result)?)
+    }
+}
+
+impl Board {
+    /// `Board::new`の本体で、デシリアライズ済みのルールから履歴付きの盤面を作る
+    fn from_rules(
+        rows: usize,
+        cols: usize,
+        row_rules: &[Vec<usize>],
+        col_rules: &[Vec<usize>],
+        history_limit: usize,
+    ) -> Result<Board, String> {
+        let grid = vec![vec![CellState::Empty; cols]; rows];
+        check_dimensions(rows, cols, row_rules, col_rules, &grid)?;
+        Ok(Board {
+            rows,
+            cols,
+            propagator: Propagator::new(rows, cols, row_rules, col_rules),
+            grid,
+            undo_stack: VecDeque::new(),
+            redo_stack: Vec::new(),
+            history_limit,
+        })
+    }
+
+    /// 現在の盤面をアンドゥスタックに積み、やり直し用の履歴を破棄する
+    /// 上限を超えた場合は、最も古い履歴から捨てる
+    fn record_history(&mut self) {
+        self.undo_stack.push_back(GridSnapshot::capture(&self.grid));
+        if self.history_limit > 0 && self.undo_stack.len() > self.history_limit {
+            self.undo_stack.pop_front();
+        }
+        self.redo_stack.clear();
+    }
+}
+
+// --- 1マスずつの解析 ---
+
+/// 解析で確定したマスを1つずつ取り出せるようにする構造体
+/// 内部で1イテレーション分の解析結果をキューに溜めておき、キューが空になったら次のイテレーションを回す
+#[wasm_bindgen]
+pub struct StepSolver {
+    propagator: Propagator,                     // 解析に使うコンパイル済みルール
+    grid: Vec<Vec<CellState>>,                  // 解析が進んだ盤面
+    settled: SettledLines,                      // 全マスが確定したライン
+    iteration: usize,                           // 実行済みのイテレーション数
+    queue: VecDeque<(usize, usize, CellState)>, // まだ取り出されていない確定マス（行, 列, 状態）
+    finished: bool,                             // これ以上確定できるマスがないかどうか
+}
+
+#[wasm_bindgen]
+impl StepSolver {
+    /// 盤面とルールから、1マスずつ解析を進める構造体を作る
+    ///
+    /// # Arguments
+    /// * `rows` - 盤面の行数
+    /// * `cols` - 盤面の列数
+    /// * `row_rules_js` - 行ルールの配列
+    /// * `col_rules_js` - 列ルールの配列
+    /// * `grid_js` - 解析を始める盤面の状態
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        rows: usize,
+        cols: usize,
+        row_rules_js: JsValue,
+        col_rules_js: JsValue,
+        grid_js: JsValue,
+    ) -> Result<StepSolver, JsValue> {
+        let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+        let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+        let grid: Vec<Vec<CellState>> = serde_wasm_bindgen::from_value(grid_js)?;
+        StepSolver::from_rules(rows, cols, &row_rules, &col_rules, grid)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// 次に確定するマスを1つ返す
+    ///
+    /// # Returns
+    /// * `Ok(JsValue)` - `{ row, col, state }`（全て確定済みで返すマスがなければ`undefined`）
+    /// * `Err(JsValue)` - 解析中にルールとの矛盾が見つかった場合のエラー
+    pub fn next_cell(&mut self) -> Result<JsValue, JsValue> {
+        match self
+            .next_patch()
+            .map_err(|e| JsValue::from_str(&e.to_string()))?
+        {
+            Some(patch) => Ok(serde_wasm_bindgen::to_value(&patch)?),
+            None => Ok(JsValue::UNDEFINED),
+        }
+    }
+
+    /// 解析が進んだ現在の盤面を返す
+    /// 1イテレーション分まとめて更新されるため、まだ`next_cell`で取り出していないマスも含まれる
+    pub fn grid(&self) -> Result<JsValue, JsValue> {
+        Ok(serde_wasm_bindgen::to_value(&self.grid)?)
+    }
+}
+
+impl StepSolver {
+    /// `StepSolver::new`の本体で、デシリアライズ済みのルールと盤面から構造体を作る
+    fn from_rules(
+        rows: usize,
+        cols: usize,
+        row_rules: &[Vec<usize>],
+        col_rules: &[Vec<usize>],
+        grid: Vec<Vec<CellState>>,
+    ) -> Result<StepSolver, String> {
+        check_dimensions(rows, cols, row_rules, col_rules, &grid)?;
+        Ok(StepSolver {
+            propagator: Propagator::new(rows, cols, row_rules, col_rules),
+            grid,
+            settled: SettledLines::new(rows, cols),
+            iteration: 0,
+            queue: VecDeque::new(),
+            finished: false,
+        })
+    }
+
+    /// `next_cell`の本体で、次に確定するマスを返す（返すマスがなければ`None`）
+    /// 矛盾が見つかった場合はエラーを返し、以降は（そのイテレーションで積んだマスも含めて）`None`を返し続ける
+    fn next_patch(&mut self) -> Result<Option<CellPatch>, PropagationError> {
+        while self.queue.is_empty() && !self.finished {
+            self.advance()?;
+        }
+        Ok(self
+            .queue
+            .pop_front()
+            .map(|(row, col, state)| CellPatch { row, col, state }))
+    }
+
+    /// 1イテレーション分の解析を行い、新たに確定したマスを確定した順にキューへ積む
+    fn advance(&mut self) -> Result<(), PropagationError> {
+        self.iteration += 1;
+        let queue = &mut self.queue;
+        let result = self.propagator.pass(
+            &mut self.grid,
+            self.iteration,
+            &mut self.settled,
+            &mut |event| {
+                let Some(after) = event.after else {
+                    return;
+                };
+                for i in event.changed_cells() {
+                    let (row, col) = match event.axis {
+                        Axis::Row => (event.index, i),
+                        Axis::Column => (i, event.index),
+                    };
+                    queue.push_back((row, col, after[i]));
+                }
+            },
+        );
+        let changed = match result {
+            Ok(changed) => changed,
+            // 矛盾したイテレーションで積んだマスは捨て、以降は何も返さない
+            Err(e) => {
+                self.finished = true;
+                self.queue.clear();
+                return Err(e);
+            }
+        };
+        if !changed {
+            self.finished = true;
+        }
+        Ok(())
+    }
+}
+
+// --- 盤面の組み立て ---
+
+/// マスを1つずつ設定して盤面を組み立てるビルダー
+/// JavaScript側では`new GridBuilder(5, 5).set(0, 1, CellState.Filled).set(...).build()`のようにチェーンで書ける
+/// `set`はビルダーを消費して新しいビルダーを返すため、途中の状態が共有されることはない
+#[wasm_bindgen]
+pub struct GridBuilder {
+    rows: usize,
+    cols: usize,
+    grid: Vec<Vec<CellState>>, // 組み立て中の盤面
+}
+
+#[wasm_bindgen]
+impl GridBuilder {
+    /// 全てのマスが「空」の盤面から組み立てを始める
+    #[wasm_bindgen(constructor)]
+    pub fn new(rows: usize, cols: usize) -> GridBuilder {
+        GridBuilder {
+            rows,
+            cols,
+            grid: vec![vec![CellState::Empty; cols]; rows],
+        }
+    }
+
+    /// 指定したマスの状態を設定したビルダーを返す
+    /// 盤面の範囲外を指定した場合はエラーになる
+    pub fn set(mut self, row: usize, col: usize, state: CellState) -> Result<GridBuilder, JsValue> {
+        if row >= self.rows || col >= self.cols {
+            return Err(JsValue::from_str(&format!(
+                "{}行{}列は{}x{}の盤面の範囲外です",
+                row + 1,
+                col + 1,
+                self.rows,
+                self.cols
+            )));
+        }
+        self.grid[row][col] = state;
+        Ok(self)
+    }
+
+    /// 組み立てた盤面を、`solve_puzzle`にそのまま渡せる形で返す
+    pub fn build(&self) -> Result<JsValue, JsValue> {
+        Ok(serde_wasm_bindgen::to_value(&self.grid)?)
+    }
+}
+
+// --- 解析結果のキャッシュ ---
+
+/// `Solver`のキャッシュの利用状況
+#[derive(Serialize, Deserialize)]
+pub struct CacheMetrics {
+    hits: usize,     // キャッシュした結果を返した回数
+    misses: usize,   // 解析を行った回数
+    hit_rate: f64,   // 呼び出し全体に占めるキャッシュヒットの割合（呼び出しがなければ0）
+    entries: usize,  // 現在キャッシュしている結果の数
+    capacity: usize, // キャッシュできる結果の数の上限
+}
+
+/// `solve_puzzle`の結果を、入力ごとにキャッシュしながら返す構造体
+/// UIから同じパズル・同じ盤面で繰り返し呼ばれても、2回目以降は解析をせずにキャッシュした結果を返す
+/// キャッシュが上限に達したら、最も長く使われていない結果から捨てる（LRU）
+#[wasm_bindgen]
+pub struct Solver {
+    capacity: usize, // キャッシュできる結果の数の上限（0ならキャッシュしない）
+    entries: VecDeque<(String, SolveResult)>, // 入力のキーと解析結果（末尾ほど最近使われたもの）
+    hits: usize,     // キャッシュした結果を返した回数
+    misses: usize,   // 解析を行った回数
+}
+
+#[wasm_bindgen]
+impl Solver {
+    /// 空のキャッシュを持つ`Solver`を作る
+    ///
+    /// # Arguments
+    /// * `capacity` - キャッシュできる結果の数の上限（0ならキャッシュせず、毎回解析する）
+    #[wasm_bindgen(constructor)]
+    pub fn new(capacity: usize) -> Solver {
+        Solver {
+            capacity,
+            entries: VecDeque::new(),
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// `solve_puzzle`と同じ解析を行う
+    /// 前に同じ入力で呼ばれていれば、解析をせずにキャッシュした結果を返す
+    ///
+    /// # Arguments
+    /// * `rows` - 盤面の行数
+    /// * `cols` - 盤面の列数
+    /// * `row_rules_js` - 行ルールの配列
+    /// * `col_rules_js` - 列ルールの配列
+    /// * `initial_grid_js` - 解析を始める盤面
+    ///
+    /// # Returns
+    /// * `Ok(JsValue)` - `solve_puzzle`と同じ解析結果
+    /// * `Err(JsValue)` - 入力の形式が不正な場合のエラー
+    pub fn solve(
+        &mut self,
+        rows: usize,
+        cols: usize,
+        row_rules_js: JsValue,
+        col_rules_js: JsValue,
+        initial_grid_js: JsValue,
+    ) -> Result<JsValue, JsValue> {
+        let row_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(row_rules_js)?;
+        let col_rules: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(col_rules_js)?;
+        let grid = grid_from_js(initial_grid_js)?;
+        check_dimensions(rows, cols, &row_rules, &col_rules, &grid)
+            .map_err(|e| JsValue::from_str(&e))?;
+        let result = self.solve_native(rows, cols, &row_rules, &col_rules, grid);
+        Ok(serde_wasm_bindgen::to_value(&*result)?)
+    }
+
+    /// キャッシュの利用状況（ヒット数・ミス数・ヒット率など）を返す
+    pub fn metrics(&self) -> Result<JsValue, JsValue> {
+        Ok(serde_wasm_bindgen::to_value(&self.cache_metrics())?)
+    }
+
+    /// キャッシュした結果と利用状況の統計を全て消す
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.hits = 0;
+        self.misses = 0;
+    }
+}
+
+impl Solver {
+    /// `solve`の本体で、入力の検証が済んだパズルを、キャッシュを使いながら解析する
+    /// キャッシュした結果はコピーせずに借用して返す（キャッシュしない設定のときだけ、解析結果をそのまま返す）
+    fn solve_native(
+        &mut self,
+        rows: usize,
+        cols: usize,
+        row_rules: &[Vec<usize>],
+        col_rules: &[Vec<usize>],
+        grid: Vec<Vec<CellState>>,
+    ) -> Cow<'_, SolveResult> {
+        let key = Solver::cache_key(rows, cols, row_rules, col_rules, &grid);
+        let cached = self
+            .entries
+            .iter()
+            .position(|(k, _)| *k == key)
+            .and_then(|position| self.entries.remove(position));
+        let entry = match cached {
+            Some(entry) => {
+                self.hits += 1;
+                entry
+            }
+            None => {
+                self.misses += 1;
+                let result = solve_puzzle_native(rows, cols, row_rules, col_rules, grid);
+                if self.capacity == 0 {
+                    return Cow::Owned(result);
+                }
+                if self.entries.len() >= self.capacity {
+                    self.entries.pop_front();
+                }
+                (key, result)
+            }
+        };
+        // 使われた結果を末尾に置き、捨てられにくくする
+        self.entries.push_back(entry);
+        Cow::Borrowed(&self.entries[self.entries.len() - 1].1)
+    }
+
+    /// `metrics`の本体で、キャッシュの利用状況を集計する
+    fn cache_metrics(&self) -> CacheMetrics {
+        let calls = self.hits + self.misses;
+        CacheMetrics {
+            hits: self.hits,
+            misses: self.misses,
+            hit_rate: if calls == 0 {
+                0.0
+            } else {
+                self.hits as f64 / calls as f64
+            },
+            entries: self.entries.len(),
+            capacity: self.capacity,
+        }
+    }
+
+    /// 入力をキャッシュのキーにする文字列を作る
+    /// `puzzle_fingerprint`と同じルールの文字列に盤面を続けたもので、ハッシュ値と違って衝突しない
+    /// `[]`と`[0]`のような表記揺れは正規化せず、別の入力として扱う
+    fn cache_key(
+        rows: usize,
+        cols: usize,
+        row_rules: &[Vec<usize>],
+        col_rules: &[Vec<usize>],
+        grid: &[Vec<CellState>],
+    ) -> String {
+        let mut key = puzzle_key(rows, cols, row_rules, col_rules);
+        key.push('|');
+        key.extend(
+            grid.iter()
+                .flatten()
+                .map(|&cell| char::from(b'0' + cell as u8)),
+        );
+        key
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// シード値から、各マスを1/2の確率で塗った完成盤面（塗り以外は「×」）を作る
+    fn random_picture(rows: usize, cols: usize, seed: u64) -> Vec<Vec<CellState>> {
+        seeded_grid(&mut SeededRng::new(seed), rows, cols, 0.5, 0.5)
+    }
+
+    /// 確定したマスが全て元の絵と一致するか（「空」のマスは問わない）
+    fn agrees_with(grid: &[Vec<CellState>], picture: &[Vec<CellState>]) -> bool {
+        grid.len() == picture.len()
+            && grid.iter().zip(picture).all(|(row, expected)| {
+                row.len() == expected.len()
+                    && row
+                        .iter()
+                        .zip(expected)
+                        .all(|(&cell, &want)| cell == CellState::Empty || cell == want)
+            })
+    }
+
+    /// 絵からルールを作り、空の盤面から`solve_puzzle_native`で解いた結果を返す
+    fn solve_picture(picture: &[Vec<CellState>], cols: usize) -> SolveResult {
+        let rows = picture.len();
+        let (row_rules, col_rules) = if cols == 0 {
+            (vec![Vec::new(); rows], Vec::new())
+        } else {
+            rules_from_grid(picture)
+        };
+        let empty = vec![vec![CellState::Empty; cols]; rows];
+        solve_puzzle_native(rows, cols, &row_rules, &col_rules, empty)
+    }
+
+    #[test]
+    fn solves_wide_and_tall_puzzles() {
+        for (rows, cols) in [(10, 30), (30, 10)] {
+            for seed in 0..5 {
+                let picture = random_picture(rows, cols, seed);
+                let result = solve_picture(&picture, cols);
+                assert!(
+                    !result.is_error(),
+                    "{}x{}: {}",
+                    rows,
+                    cols,
+                    result.message()
+                );
+                assert!(
+                    agrees_with(result.grid(), &picture),
+                    "{}x{} seed {}",
+                    rows,
+                    cols,
+                    seed
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn solves_single_line_puzzles_completely() {
+        // 1行または1列の盤面は、交差するラインが1マスずつなので必ず解き切れる
+        for n in [1, 2, 7, 20] {
+            for (rows, cols) in [(1, n), (n, 1)] {
+                let picture = random_picture(rows, cols, n as u64);
+                let result = solve_picture(&picture, cols);
+                assert!(
+                    !result.is_error(),
+                    "{}x{}: {}",
+                    rows,
+                    cols,
+                    result.message()
+                );
+                assert_eq!(result.grid(), picture.as_slice(), "{}x{}", rows, cols);
+            }
+        }
+    }
+
+    #[test]
+    fn keeps_rows_of_zero_width_grid() {
+        let picture = vec![Vec::new(); 3];
+        let result = solve_picture(&picture, 0);
+        assert!(!result.is_error(), "{}", result.message());
+        assert_eq!(result.grid(), picture.as_slice());
+    }
+
+    #[test]
+    fn rejects_mismatched_dimensions() {
+        let rules = vec![vec![1]; 2];
+        let grid = vec![vec![CellState::Empty; 2]; 2];
+        assert!(solve_puzzle_native(3, 2, &rules, &rules, grid.clone()).is_error());
+        assert!(solve_puzzle_native(2, 3, &rules, &rules, grid.clone()).is_error());
+        let ragged = vec![vec![CellState::Empty; 2], vec![CellState::Empty; 1]];
+        assert!(solve_puzzle_native(2, 2, &rules, &rules, ragged).is_error());
+    }
+
+    #[test]
+    fn diagonals_cover_every_cell_once() {
+        for (rows, cols) in [(1, 1), (2, 5), (5, 2), (4, 4), (0, 3), (3, 0)] {
+            let count = diagonal_count(rows, cols);
+            for anti in [false, true] {
+                let mut cells: Vec<(usize, usize)> = (0..count)
+                    .flat_map(|index| diagonal_cells(rows, cols, index, anti))
+                    .collect();
+                // どの対角線も1マス以上を含み、全てのマスがちょうど1本の対角線に属する
+                assert!(
+                    (0..count).all(|index| !diagonal_cells(rows, cols, index, anti).is_empty())
+                );
+                cells.sort_unstable();
+                let all: Vec<(usize, usize)> = (0..rows)
+                    .flat_map(|r| (0..cols).map(move |c| (r, c)))
+                    .collect();
+                assert_eq!(cells, all, "{}x{} anti={}", rows, cols, anti);
+            }
+        }
+    }
+
+    /// ルールの配置パターンを1つ選んで一部のマスだけを書き込み、3回に1回は1マスを反転させたラインを作る
+    /// 反転させたラインはルールと矛盾することがあるので、矛盾する場合としない場合の両方が得られる
+    fn random_line(compiled: &CompiledRule, rng: &mut SeededRng) -> Vec<CellState> {
+        let count = compiled.patterns.len() / compiled.words;
+        let pattern = &compiled.patterns[rng.below(count) * compiled.words..][..compiled.words];
+        let mut line: Vec<CellState> = (0..compiled.size)
+            .map(|i| match (rng.below(2), pattern[i / 64] >> (i % 64) & 1) {
+                (0, _) => CellState::Empty,
+                (_, 1) => CellState::Filled,
+                _ => CellState::Crossed,
+            })
+            .collect();
+        if compiled.size > 0 && rng.below(3) == 0 {
+            let i = rng.below(compiled.size);
+            line[i] = match line[i] {
+                CellState::Filled => CellState::Crossed,
+                _ => CellState::Filled,
+            };
+        }
+        line
+    }
+
+    #[test]
+    fn line_result_does_not_depend_on_pattern_order() {
+        let mut rng = SeededRng::new(145);
+        for (size, rule) in [
+            (5, vec![]),
+            (10, vec![3, 2]),
+            (15, vec![1, 1, 1, 2]),
+            (70, vec![30, 30]),
+        ] {
+            let compiled = compile_rule(size, &rule);
+            let mut chunks: Vec<&[u64]> = compiled.patterns.chunks(compiled.words).collect();
+            let (mut conflicts, mut solved) = (0, 0);
+            for _ in 0..20 {
+                rng.shuffle(&mut chunks);
+                let shuffled = CompiledRule {
+                    patterns: chunks.concat().into(),
+                    ..compiled.clone()
+                };
+                for _ in 0..10 {
+                    let line = random_line(&compiled, &mut rng);
+                    let expected = compiled.apply(&line);
+                    match &expected {
+                        Ok(_) => solved += 1,
+                        Err(_) => conflicts += 1,
+                    }
+                    assert_eq!(shuffled.apply(&line), expected, "{:?} {:?}", rule, line);
+                }
+            }
+            // 矛盾する場合としない場合の両方を確かめられていること
+            assert!(conflicts > 0 && solved > 0, "{:?}", rule);
+        }
+    }
+
+    #[test]
+    fn treats_zero_blocks_as_empty_rule() {
+        let blank_rules = [vec![], vec![0], vec![0, 0]];
+        for rule in &blank_rules {
+            assert_eq!(normalize_rule(rule), Vec::<usize>::new());
+            let compiled = compile_rule(4, rule);
+            assert_eq!(&*compiled.patterns, &[0]);
+            assert_eq!(
+                compiled.apply(&[CellState::Empty; 4]),
+                Ok(vec![CellState::Crossed; 4])
+            );
+            assert!(
+                compiled
+                    .apply(&[
+                        CellState::Filled,
+                        CellState::Empty,
+                        CellState::Empty,
+                        CellState::Empty
+                    ])
+                    .is_err()
+            );
+        }
+
+        // 空ルールの書き方を混ぜたパズルを解くと、空ルールのラインは全て「×」になる
+        let row_rules = vec![vec![], vec![2], vec![0, 0]];
+        let col_rules = vec![vec![0], vec![1], vec![1]];
+        let empty = vec![vec![CellState::Empty; 3]; 3];
+        let result = solve_puzzle_native(3, 3, &row_rules, &col_rules, empty);
+        assert!(!result.is_error(), "{}", result.message());
+        let (x, f) = (CellState::Crossed, CellState::Filled);
+        assert_eq!(
+            result.grid(),
+            &[vec![x, x, x], vec![x, f, f], vec![x, x, x]]
+        );
+    }
+
+    /// 行ルール、列ルール、初期盤面の組
+    type RandomPuzzle = (Vec<Vec<usize>>, Vec<Vec<usize>>, Vec<Vec<CellState>>);
+
+    /// 絵からルールを作り、絵のマスを`1 / reveal`の確率で書き込んだ初期盤面と合わせて返す
+    fn random_puzzle(rows: usize, cols: usize, seed: u64, reveal: usize) -> RandomPuzzle {
+        let picture = random_picture(rows, cols, seed);
+        let (row_rules, col_rules) = rules_from_grid(&picture);
+        let mut rng = SeededRng::new(seed ^ 0xABCD);
+        let grid = picture
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|&cell| {
+                        if rng.below(reveal) == 0 {
+                            cell
+                        } else {
+                            CellState::Empty
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        (row_rules, col_rules, grid)
+    }
+
+    #[test]
+    fn worklist_reaches_the_same_fixpoint() {
+        for seed in 0..20 {
+            let (rows, cols) = (8 + seed as usize % 5, 12 - seed as usize % 5);
+            let (row_rules, col_rules, grid) = random_puzzle(rows, cols, seed, 6);
+            let propagator = Propagator::new(rows, cols, &row_rules, &col_rules);
+            let mut expected = grid.clone();
+            assert!(propagator.propagate_to_fixpoint(&mut expected).is_ok());
+            let mut actual = grid;
+            assert!(
+                propagator
+                    .propagate_worklist(&mut actual, &mut |_| {})
+                    .is_ok()
+            );
+            assert_eq!(actual, expected, "seed {}", seed);
+        }
+    }
+
+    /// 変化の記録を使わず、全ての行と列を毎回解析し直して、変化がなくなった盤面を返す（矛盾があれば`None`）
+    fn full_repropagation(
+        propagator: &Propagator,
+        mut grid: Vec<Vec<CellState>>,
+    ) -> Option<Vec<Vec<CellState>>> {
+        loop {
+            let before = grid.clone();
+            for (r, rule) in propagator.row_rules.iter().enumerate() {
+                grid[r] = rule.apply(&grid[r]).ok()?;
+            }
+            for (c, rule) in propagator.col_rules.iter().enumerate() {
+                let line = rule.apply(&get_line(&grid, Axis::Column, c)).ok()?;
+                set_line(&mut grid, Axis::Column, c, &line);
+            }
+            if grid == before {
+                return Some(grid);
+            }
+        }
+    }
+
+    #[test]
+    fn zoned_propagation_matches_global_propagation() {
+        let mut zoned_runs = 0;
+        for seed in 0..30 {
+            let (rows, cols) = (6 + seed as usize % 7, 12 - seed as usize % 5);
+            // 多く書き込んだ盤面ほど、最初のパスの後に複数のゾーンへ分かれやすい
+            let (row_rules, col_rules, mut grid) =
+                random_puzzle(rows, cols, seed, 2 + seed as usize % 4);
+            if seed % 4 == 0 {
+                let cell = &mut grid[seed as usize % rows][seed as usize % cols];
+                *cell = match *cell {
+                    CellState::Filled => CellState::Crossed,
+                    _ => CellState::Filled,
+                };
+            }
+            let propagator = Propagator::new(rows, cols, &row_rules, &col_rules);
+            let mut expected = grid.clone();
+            let global = propagator.propagate_to_fixpoint(&mut expected);
+            let mut actual = grid;
+            let zoned = propagator.propagate_zoned(&mut actual);
+            assert_eq!(zoned.is_ok(), global.is_ok(), "seed {}", seed);
+            if let Ok(zones) = zoned {
+                assert_eq!(actual, expected, "seed {}", seed);
+                if zones > 1 {
+                    zoned_runs += 1;
+                }
+            }
+        }
+        // ゾーンが1つだけの盤面ばかりでは、分割して解く経路を確かめたことにならない
+        assert!(zoned_runs > 0);
+    }
+
+    #[test]
+    fn stale_tracking_matches_full_repropagation() {
+        for seed in 0..30 {
+            let (rows, cols) = (6 + seed as usize % 7, 12 - seed as usize % 5);
+            let (row_rules, col_rules, mut grid) = random_puzzle(rows, cols, seed, 5);
+            // 3回に1回は、絵と食い違うマスを書き込んで矛盾する盤面にする
+            if seed % 3 == 0 {
+                let cell = &mut grid[seed as usize % rows][seed as usize % cols];
+                *cell = match *cell {
+                    CellState::Filled => CellState::Crossed,
+                    _ => CellState::Filled,
+                };
+            }
+            for order in [LineOrder::Sequential, LineOrder::MostConstrainedFirst] {
+                let propagator =
+                    Propagator::new(rows, cols, &row_rules, &col_rules).with_order(order);
+                let expected = full_repropagation(&propagator, grid.clone());
+                let mut actual = grid.clone();
+                let result = propagator.propagate_to_fixpoint(&mut actual);
+                assert_eq!(result.is_ok(), expected.is_some(), "seed {}", seed);
+                if let Some(expected) = expected {
+                    assert_eq!(actual, expected, "seed {} {:?}", seed, order);
+                }
+            }
+        }
+
+        // 転置対称な解析では、列への書き込みで変化した行も解析し直す対象になる
+        for seed in 0..20 {
+            let size = 5 + seed as usize % 11;
+            let picture = symmetric_picture(size, seed);
+            let (row_rules, col_rules) = rules_from_grid(&picture);
+            let grid = vec![vec![CellState::Empty; size]; size];
+            let propagator =
+                Propagator::new(size, size, &row_rules, &col_rules).with_symmetry(true);
+            let expected = full_repropagation(&propagator, grid.clone());
+            let mut actual = grid;
+            assert!(propagator.propagate_to_fixpoint(&mut actual).is_ok());
+            assert_eq!(Some(actual), expected, "seed {}", seed);
+        }
+    }
+
+    /// 対角線について対称な、`size`行`size`列の完成盤面を作る
+    /// この絵から作ったパズルは、行ルールと列ルールが一致する
+    fn symmetric_picture(size: usize, seed: u64) -> Vec<Vec<CellState>> {
+        let picture = random_picture(size, size, seed);
+        (0..size)
+            .map(|r| (0..size).map(|c| picture[r.min(c)][r.max(c)]).collect())
+            .collect()
+    }
+
+    #[test]
+    fn symmetric_sharing_halves_line_solves() {
+        let (mut separate, mut shared) = (0, 0);
+        for seed in 0..40 {
+            let size = 5 + seed as usize % 16;
+            let picture = symmetric_picture(size, seed);
+            let (row_rules, col_rules) = rules_from_grid(&picture);
+            let grid = vec![vec![CellState::Empty; size]; size];
+
+            let mut expected = grid.clone();
+            let result = Propagator::new(size, size, &row_rules, &col_rules)
+                .propagate_with(&mut expected, &mut |_| separate += 1);
+            assert!(result.is_ok());
+            // 対称な解析では行・列の両方について通知されるが、実際に解析するのは行だけ
+            let mut actual = grid;
+            let result = Propagator::new(size, size, &row_rules, &col_rules)
+                .with_symmetry(true)
+                .propagate_with(&mut actual, &mut |event| {
+                    if event.axis == Axis::Row {
+                        shared += 1;
+                    }
+                });
+            assert!(result.is_ok());
+            assert_eq!(actual, expected, "seed {}", seed);
+        }
+        // 計測では、40問の合計で2552本から1264本に減った
+        assert!(shared * 5 <= separate * 3, "{} / {}", shared, separate);
+    }
+
+    #[test]
+    fn symmetry_takes_priority_over_worklist() {
+        let picture = symmetric_picture(7, 149);
+        let (row_rules, col_rules) = rules_from_grid(&picture);
+        let grid = vec![vec![CellState::Empty; 7]; 7];
+        assert!(is_transpose_symmetric(&row_rules, &col_rules, &grid));
+
+        let propagator = Propagator::new(7, 7, &row_rules, &col_rules)
+            .with_order(LineOrder::Worklist)
+            .with_symmetry(true);
+        let mut events: Vec<(Axis, usize)> = Vec::new();
+        let mut actual = grid.clone();
+        let result = propagator.propagate_with(&mut actual, &mut |event| {
+            events.push((event.axis, event.index))
+        });
+        assert!(result.is_ok());
+        // 対称な解析では、行の解析のすぐ後に同じ番号の列の通知が続く
+        assert!(
+            events
+                .chunks(2)
+                .all(|pair| pair == [(Axis::Row, pair[0].1), (Axis::Column, pair[0].1)])
+        );
+
+        let mut expected = grid;
+        let result =
+            Propagator::new(7, 7, &row_rules, &col_rules).propagate_to_fixpoint(&mut expected);
+        assert!(result.is_ok());
+        assert_eq!(actual, expected);
+    }
+
+    /// 探索方針に従って、解を見つかった順に最大`limit`個まで集める
+    fn enumerate_solutions(
+        propagator: &Propagator,
+        grid: Vec<Vec<CellState>>,
+        policy: SearchPolicy,
+        limit: usize,
+    ) -> Vec<Vec<Vec<CellState>>> {
+        let mut search =
+            SolutionSearch::new(propagator, grid).with_guess_order(policy.guess_order());
+        let mut solutions = Vec::new();
+        while solutions.len() < limit {
+            match search.next_solution(&|| false) {
+                SearchOutcome::Solved(grid) => solutions.push(grid),
+                _ => break,
+            }
+        }
+        solutions
+    }
+
+    #[test]
+    fn lexicographic_aliases_enumerate_in_canonical_order() {
+        use serde::de::IntoDeserializer;
+        let parse = |name: &str| {
+            SearchPolicy::deserialize(name.into_deserializer())
+                .map_err(|e: serde::de::value::Error| e.to_string())
+        };
+        assert_eq!(parse("lexicographic_max"), Ok(SearchPolicy::FilledFirst));
+        assert_eq!(parse("lexicographic_min"), Ok(SearchPolicy::CrossedFirst));
+
+        // 盤面を行優先のビット列（「塗り」が1）とみなしたときの並び
+        let bits = |grid: &Vec<Vec<CellState>>| -> Vec<bool> {
+            grid.iter()
+                .flatten()
+                .map(|&cell| cell == CellState::Filled)
+                .collect()
+        };
+        let mut ambiguous = 0;
+        for seed in 0..20 {
+            let picture = random_picture(5, 5, seed);
+            let (row_rules, col_rules) = rules_from_grid(&picture);
+            let propagator = Propagator::new(5, 5, &row_rules, &col_rules);
+            let empty = vec![vec![CellState::Empty; 5]; 5];
+            for (alias, canonical, descending) in [
+                ("lexicographic_max", SearchPolicy::FilledFirst, true),
+                ("lexicographic_min", SearchPolicy::CrossedFirst, false),
+            ] {
+                let policy = parse(alias).unwrap();
+                let solutions = enumerate_solutions(&propagator, empty.clone(), policy, 50);
+                assert_eq!(
+                    solutions,
+                    enumerate_solutions(&propagator, empty.clone(), canonical, 50)
+                );
+                assert!(solutions.contains(&picture), "seed {}", seed);
+                // 見つかる順に、辞書順で単調に並んでいなければならない
+                assert!(
+                    solutions.windows(2).all(|pair| if descending {
+                        bits(&pair[0]) > bits(&pair[1])
+                    } else {
+                        bits(&pair[0]) < bits(&pair[1])
+                    }),
+                    "seed {} {}",
+                    seed,
+                    alias
+                );
+                if solutions.len() > 1 {
+                    ambiguous += 1;
+                }
+            }
+        }
+        assert!(ambiguous > 0);
+    }
+
+    #[test]
+    fn roundtrip_holds_for_random_grids() {
+        let mut rng = SeededRng::new(153);
+        for _ in 0..200 {
+            let (rows, cols) = (1 + rng.below(15), 1 + rng.below(15));
+            // 塗りの密度も変え、「塗り」以外のマスには「×」と「空」を半々に混ぜる
+            let fill = (1 + rng.below(9)) as f64 / 10.0;
+            let solution = seeded_grid(&mut rng, rows, cols, fill, (1.0 - fill) / 2.0);
+            assert!(roundtrip_holds(&solution), "{:?}", solution);
+        }
+    }
+
+    /// 盤面に変化がなくなるまで解析し、結果の盤面とイテレーション数を返す（矛盾した場合は`None`）
+    fn propagate_counting_iterations(
+        propagator: &Propagator,
+        mut grid: Vec<Vec<CellState>>,
+    ) -> Option<(Vec<Vec<CellState>>, usize)> {
+        let mut iterations = 0;
+        propagator
+            .propagate_with(&mut grid, &mut |event| {
+                iterations = iterations.max(event.iteration)
+            })
+            .ok()?;
+        Some((grid, iterations))
+    }
+
+    #[test]
+    fn most_constrained_first_matches_sequential_in_fewer_iterations() {
+        // 書き込むマスの割合ごとに、通常順と制約の強い順のイテレーション数の合計を比べる
+        for reveal in [2, 4, 8, 1000] {
+            let (mut sequential, mut most_constrained) = (0, 0);
+            for seed in 0..40 {
+                let (rows, cols) = (10 + seed as usize % 11, 20 - seed as usize % 11);
+                let (row_rules, col_rules, grid) = random_puzzle(rows, cols, seed, reveal);
+                let sequential_order = Propagator::new(rows, cols, &row_rules, &col_rules);
+                let constrained_order = Propagator::new(rows, cols, &row_rules, &col_rules)
+                    .with_order(LineOrder::MostConstrainedFirst);
+                let expected = propagate_counting_iterations(&sequential_order, grid.clone());
+                let actual = propagate_counting_iterations(&constrained_order, grid);
+                let ((expected, expected_iterations), (actual, actual_iterations)) =
+                    (expected.unwrap(), actual.unwrap());
+                assert_eq!(actual, expected, "reveal {} seed {}", reveal, seed);
+                sequential += expected_iterations;
+                most_constrained += actual_iterations;
+            }
+            assert!(
+                most_constrained < sequential,
+                "reveal {}: {} >= {}",
+                reveal,
+                most_constrained,
+                sequential
+            );
+        }
+    }
+
+    /// スレッドごとにメモリ割り当ての回数を数えるアロケータ（テストは並列に走るため、スレッド単位で数える）
+    struct CountingAllocator;
+
+    thread_local! {
+        static ALLOCATIONS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+    }
+
+    unsafe impl std::alloc::GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: std::alloc::Layout) -> *mut u8 {
+            // スレッドの終了処理中は数えられないので、数えずに割り当てる
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            unsafe { std::alloc::System.alloc(layout) }
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: std::alloc::Layout) {
+            unsafe { std::alloc::System.dealloc(ptr, layout) }
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    /// `f`の実行中に、このスレッドで行われたメモリ割り当ての回数を返す
+    fn count_allocations(f: impl FnOnce()) -> usize {
+        let before = ALLOCATIONS.with(|count| count.get());
+        f();
+        ALLOCATIONS.with(|count| count.get()) - before
+    }
+
+    #[test]
+    fn line_scratch_reuse_keeps_results_and_saves_allocations() {
+        let mut rng = SeededRng::new(167);
+        // 64マスを超えるラインも含め、ビットマスクの語数が異なるルールを混ぜる
+        let rules: Vec<CompiledRule> = [
+            (5, vec![2, 1]),
+            (15, vec![3, 1, 4]),
+            (30, vec![5, 5, 5, 5]),
+            (70, vec![30, 30]),
+            (130, vec![60, 60]),
+        ]
+        .iter()
+        .map(|(size, rule)| compile_rule(*size, rule))
+        .collect();
+        let lines: Vec<(usize, Vec<CellState>)> = (0..200)
+            .map(|_| {
+                let index = rng.below(rules.len());
+                (index, random_line(&rules[index], &mut rng))
+            })
+            .collect();
+
+        let mut expected = Vec::new();
+        let fresh = count_allocations(|| {
+            for (index, line) in &lines {
+                expected.push(rules[*index].apply(line));
+            }
+        });
+        let mut scratch = LineScratch::default();
+        let mut actual = Vec::new();
+        let reused = count_allocations(|| {
+            for (index, line) in &lines {
+                actual.push(rules[*index].apply_with(line, &mut scratch));
+            }
+        });
+        assert_eq!(actual, expected);
+        // 一時バッファを使い回せば、割り当ては結果のラインとエラーメッセージ、バッファの拡張だけになる
+        // 計測では、200本のラインで1007回から211回に減った
+        assert!(reused * 3 < fresh, "{} / {}", reused, fresh);
+    }
+
+    #[test]
+    fn blank_line_shortcut_matches_pattern_scan() {
+        for (size, rule) in [
+            (0, vec![]),
+            (5, vec![]),
+            (5, vec![0]),
+            (3, vec![5]),
+            (10, vec![3, 2]),
+            (10, vec![4, 5]),
+            (70, vec![30, 30]),
+            (130, vec![60, 60]),
+        ] {
+            let compiled = compile_rule(size, &rule);
+            let blank = vec![CellState::Empty; size];
+            assert_eq!(
+                compiled.apply(&blank),
+                compiled.apply_patterns(&blank, &mut LineScratch::default()),
+                "{} {:?}",
+                size,
+                rule
+            );
+        }
+    }
+
+    #[test]
+    fn quad_grid_reads_and_writes_lines_like_the_dense_grid() {
+        let mut rng = SeededRng::new(1880);
+        // 「空」以外のマスの割合を0から100%まで変え、「塗り」と「×」を半々にする
+        let random_grid = |rng: &mut SeededRng, rows, cols| {
+            let density = rng.below(11) as f64 / 10.0;
+            seeded_grid(rng, rows, cols, density / 2.0, density / 2.0)
+        };
+        for _ in 0..40 {
+            let (rows, cols) = (1 + rng.below(20), 1 + rng.below(20));
+            let mut grid = random_grid(&mut rng, rows, cols);
+            let mut quad = QuadGrid::new(&grid, cols);
+            assert_eq!(quad.to_grid(), grid);
+            for _ in 0..10 {
+                for (axis, count) in [(Axis::Row, rows), (Axis::Column, cols)] {
+                    for index in 0..count {
+                        let line = get_line(&grid, axis, index);
+                        assert_eq!(quad.line(axis, index), line);
+                        assert_eq!(
+                            quad.is_blank_line(axis, index),
+                            line.iter().all(|&cell| cell == CellState::Empty)
+                        );
+                    }
+                }
+                // ラインを書き換えても、同じ盤面から作り直した木と同じ形になる
+                let (axis, count) = if rng.below(2) == 0 {
+                    (Axis::Row, rows)
+                } else {
+                    (Axis::Column, cols)
+                };
+                let index = rng.below(count);
+                let length = get_line(&grid, axis, index).len();
+                let line = random_grid(&mut rng, 1, length).remove(0);
+                set_line(&mut grid, axis, index, &line);
+                quad.set_line(axis, index, &line);
+                assert_eq!(quad, QuadGrid::new(&grid, cols));
+            }
+        }
+    }
+
+    /// 伝播中の`LineEvent`を、比較できる形で記録したもの
+    type RecordedEvent = (usize, Axis, usize, Vec<CellState>, Option<Vec<CellState>>);
+
+    fn record_event(events: &mut Vec<RecordedEvent>, event: &LineEvent) {
+        events.push((
+            event.iteration,
+            event.axis,
+            event.index,
+            event.before.to_vec(),
+            event.after.map(<[CellState]>::to_vec),
+        ));
+    }
+
+    #[test]
+    fn sparse_pass_matches_dense_pass() {
+        for seed in 0..12 {
+            let (rows, cols) = (12 + seed as usize % 3 * 4, 20 - seed as usize % 4 * 2);
+            // 塗りの少ない絵ほど、空の盤面から確定できないマスが多く残り、四分木が小さくなる
+            let picture = seeded_grid(&mut SeededRng::new(seed), rows, cols, 0.125, 0.875);
+            let (mut row_rules, col_rules) = rules_from_grid(&picture);
+            // 一部のシードでは、解析の途中で矛盾するルールにする
+            if seed % 3 == 0 {
+                row_rules[seed as usize % rows] = vec![cols];
+            }
+            let propagator = Propagator::new(rows, cols, &row_rules, &col_rules);
+            let empty = vec![vec![CellState::Empty; cols]; rows];
+            assert!(QuadGrid::sparse(&empty).is_some());
+
+            let mut sparse_events = Vec::new();
+            let mut sparse = empty.clone();
+            let sparse_result = propagator.propagate_with(&mut sparse, &mut |event| {
+                record_event(&mut sparse_events, event)
+            });
+
+            let mut dense_events = Vec::new();
+            let mut dense = empty;
+            let mut settled = SettledLines::new(rows, cols);
+            let mut dense_result = Ok(0);
+            for iteration in 1.. {
+                match propagator.pass(&mut dense, iteration, &mut settled, &mut |event| {
+                    record_event(&mut dense_events, event)
+                }) {
+                    Ok(true) => continue,
+                    Ok(false) => dense_result = Ok(iteration),
+                    Err(error) => dense_result = Err(error),
+                }
+                break;
+            }
+
+            assert_eq!(sparse_result.is_ok(), dense_result.is_ok(), "seed {}", seed);
+            assert_eq!(sparse_result.ok(), dense_result.ok(), "seed {}", seed);
+            assert_eq!(sparse_events, dense_events, "seed {}", seed);
+            assert_eq!(sparse, dense, "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn transpose_handles_sparse_and_dense_grids() {
+        let mut rng = SeededRng::new(188);
+        for _ in 0..50 {
+            let (rows, cols) = (1 + rng.below(20), 1 + rng.below(20));
+            // 「空」以外のマスの割合を0から100%まで変える
+            let density = rng.below(11) as f64 / 10.0;
+            let grid = seeded_grid(&mut rng, rows, cols, density / 2.0, density / 2.0);
+            let transposed = transpose(grid.clone());
+            assert_eq!(transposed.len(), cols);
+            assert!(
+                (0..rows).all(|r| (0..cols).all(|c| transposed[c][r] == grid[r][c])),
+                "{:?}",
+                grid
+            );
+        }
+    }
+
+    #[test]
+    fn solves_sparse_large_puzzle() {
+        // ほとんどが「×」で、数マスだけ「塗り」の大盤面
+        let (rows, cols) = (200, 150);
+        let mut picture = vec![vec![CellState::Crossed; cols]; rows];
+        for (r, c) in [(0, 0), (3, 140), (77, 12), (150, 149), (199, 70)] {
+            picture[r][c] = CellState::Filled;
+        }
+        let result = solve_picture(&picture, cols);
+        assert!(!result.is_error(), "{}", result.message());
+        assert!(agrees_with(result.grid(), &picture));
+    }
+
+    #[test]
+    fn cache_key_separates_rules_and_grids() {
+        let rules = vec![vec![1], vec![]];
+        let grid = vec![vec![CellState::Empty; 2]; 2];
+        let key = Solver::cache_key(2, 2, &rules, &rules, &grid);
+        assert_eq!(key, Solver::cache_key(2, 2, &rules, &rules, &grid));
+
+        let mut changed = grid.clone();
+        changed[1][0] = CellState::Crossed;
+        assert_ne!(key, Solver::cache_key(2, 2, &rules, &rules, &changed));
+        // 表記揺れは正規化しないので、`[]`と`[0]`は別のキーになる
+        let zero = vec![vec![1], vec![0]];
+        assert_ne!(key, Solver::cache_key(2, 2, &zero, &rules, &grid));
+        assert_ne!(key, Solver::cache_key(2, 2, &rules, &zero, &grid));
+    }
+
+    #[test]
+    fn resize_keeps_rules_and_cells_in_step() {
+        let mut grid = vec![vec![CellState::Filled; 3]; 2];
+        let mut rules = vec![vec![2], vec![2], vec![2]];
+        assert!(resize_grid(&mut grid, &mut rules, Axis::Column, 3, true).is_ok());
+        assert_eq!(
+            grid,
+            vec![
+                vec![
+                    CellState::Filled,
+                    CellState::Filled,
+                    CellState::Filled,
+                    CellState::Empty
+                ];
+                2
+            ]
+        );
+        assert_eq!(rules, vec![vec![2], vec![2], vec![2], vec![]]);
+        assert!(resize_grid(&mut grid, &mut rules, Axis::Column, 0, false).is_ok());
+        assert_eq!(rules.len(), 3);
+        assert!(grid.iter().all(|row| row.len() == 3));
+        assert!(resize_grid(&mut grid, &mut rules, Axis::Column, 3, false).is_err());
+        assert!(resize_grid(&mut grid, &mut rules, Axis::Column, 5, true).is_err());
+
+        // 行が1本もない盤面には、列を挿入できない（行の挿入はできる）
+        let mut empty: Vec<Vec<CellState>> = Vec::new();
+        let mut col_rules: Vec<Vec<usize>> = Vec::new();
+        assert!(resize_grid(&mut empty, &mut col_rules, Axis::Column, 0, true).is_err());
+        assert!(empty.is_empty() && col_rules.is_empty());
+        let mut row_rules: Vec<Vec<usize>> = Vec::new();
+        assert!(resize_grid(&mut empty, &mut row_rules, Axis::Row, 0, true).is_ok());
+        assert_eq!(empty, vec![Vec::<CellState>::new()]);
+        assert_eq!(row_rules, vec![Vec::<usize>::new()]);
+        assert!(resize_grid(&mut empty, &mut col_rules, Axis::Column, 0, true).is_ok());
+        assert_eq!(empty, vec![vec![CellState::Empty]]);
+    }
+
+    #[cfg(feature = "profiling")]
+    #[test]
+    fn profiling_measures_phases_natively() {
+        let picture = random_picture(15, 15, 159);
+        let (row_rules, col_rules) = rules_from_grid(&picture);
+        let propagator = Propagator::new(15, 15, &row_rules, &col_rules);
+        let started = now_ms();
+        let result = solve_with(&propagator, vec![vec![CellState::Empty; 15]; 15]);
+        let elapsed = now_ms() - started;
+        assert!(!result.is_error(), "{}", result.message());
+
+        let timings = ProfiledSolveResult::new(result, 1.5, &propagator).timings;
+        assert_eq!(timings.deserialize_ms, 1.5);
+        assert_eq!(timings.serialize_ms, 0.0);
+        let phases = [timings.row_ms, timings.column_ms, timings.transpose_ms];
+        assert!(phases.iter().all(|&ms| ms >= 0.0), "{:?}", phases);
+        // 各フェーズの合計は、解析全体の所要時間を超えない
+        assert!(
+            phases.iter().sum::<f64>() <= elapsed,
+            "{:?} {}",
+            phases,
+            elapsed
+        );
+        assert!(timings.row_ms + timings.column_ms > 0.0);
+    }
+
+    #[test]
+    fn compressed_result_decodes_to_json() {
+        use std::io::Read;
+        let picture = random_picture(20, 25, 183);
+        let result = solve_picture(&picture, 25);
+        let json = result.to_json();
+        for level in [0, 1, 9] {
+            let bytes = compress_result(&result, level).unwrap();
+            assert_eq!(&bytes[..2], &[0x1F, 0x8B], "level {}", level);
+            let mut decoded = String::new();
+            flate2::read::GzDecoder::new(bytes.as_slice())
+                .read_to_string(&mut decoded)
+                .unwrap();
+            assert_eq!(decoded, json, "level {}", level);
+        }
+        assert!(compress_result(&result, 10).is_err());
+    }
+
+    #[test]
+    fn timeout_search_solves_or_reports_the_best_partial_grid() {
+        let picture = random_picture(8, 8, 137);
+        let (row_rules, col_rules) = rules_from_grid(&picture);
+        let propagator = Propagator::new(8, 8, &row_rules, &col_rules);
+        let empty = vec![vec![CellState::Empty; 8]; 8];
+
+        let solved = search_with_timeout(&propagator, empty.clone(), 60_000);
+        assert!(!solved.is_error(), "{}", solved.message());
+        assert_eq!(rules_from_grid(solved.grid()), (row_rules, col_rules));
+
+        // 期限が既に過ぎていれば、探索を始める前に打ち切って入力の盤面を返す
+        let timed_out = search_with_timeout(&propagator, empty.clone(), 0);
+        assert!(timed_out.is_error());
+        assert!(timed_out.message().starts_with("時間切れ"));
+        assert_eq!(timed_out.grid(), empty.as_slice());
+    }
+
+    #[test]
+    fn search_remembers_the_most_determined_branch() {
+        // 各行・各列に1マスずつ塗る6x6のパズルは、論理だけでは1マスも確定しない
+        let rules = vec![vec![1]; 6];
+        let propagator = Propagator::new(6, 6, &rules, &rules);
+        let empty = |grid: &Vec<Vec<CellState>>| {
+            grid.iter()
+                .flatten()
+                .filter(|&&cell| cell == CellState::Empty)
+                .count()
+        };
+        let mut search = SolutionSearch::new(&propagator, vec![vec![CellState::Empty; 6]; 6]);
+        assert!(search.best_partial().is_none());
+        let steps = std::cell::Cell::new(0);
+        let outcome = search.next_solution(&|| {
+            steps.set(steps.get() + 1);
+            steps.get() > 6
+        });
+        assert!(matches!(outcome, SearchOutcome::Cancelled));
+        let first = empty(&search.stack[0].grid);
+        let best = empty(search.best_partial().unwrap());
+        assert_eq!(first, 36);
+        assert!(best < first, "{} {}", best, first);
+        assert!(search.stack.iter().all(|frame| empty(&frame.grid) >= best));
+    }
+
+    #[test]
+    fn progress_point_keeps_the_total_filled_key() {
+        use serde::de::value::{Error, MapDeserializer};
+        // JavaScriptとの間の形は`{iter, total_filled, delta}`のまま変えない
+        let fields = |key: &'static str| {
+            MapDeserializer::<_, Error>::new([("iter", 2usize), (key, 7), ("delta", 3)].into_iter())
+        };
+        let point = ProgressPoint::deserialize(fields("total_filled")).unwrap();
+        assert_eq!((point.iter, point.total_determined, point.delta), (2, 7, 3));
+        assert!(ProgressPoint::deserialize(fields("total_determined")).is_err());
+    }
+
+    #[test]
+    fn rejects_oversized_shared_grids() {
+        assert!(decode_grid_v1("18446744073709551615.0.").is_err());
+        assert!(decode_grid_v1("100000000.0.").is_err());
+        assert!(decode_grid_v1("0.100000000.").is_err());
+        assert_eq!(decode_grid_v1("3.0."), Ok(vec![Vec::new(); 3]));
+        let side = MAX_SHARED_GRID_SIDE;
+        let data = "A".repeat((side * side).div_ceil(3));
+        let grid = decode_grid_v1(&format!("{}.{}.{}", side, side, data)).unwrap();
+        assert_eq!((grid.len(), grid[0].len()), (side, side));
+    }
+
+    #[test]
+    fn rejects_regions_outside_the_grid() {
+        let region = |top, left, height, width| Region {
+            top,
+            left,
+            height,
+            width,
+        };
+        assert!(region(0, 0, 3, 4).fits(3, 4));
+        assert!(region(1, 2, 2, 2).fits(3, 4));
+        assert!(!region(1, 0, 3, 1).fits(3, 4));
+        assert!(!region(usize::MAX, 0, 2, 1).fits(3, 4));
+        assert!(!region(0, 1, 1, usize::MAX).fits(3, 4));
+    }
+
+    #[test]
+    fn rejects_tampered_search_snapshot() {
+        let snapshot = |stack: Vec<SearchFrame>, pending| SearchSnapshot {
+            rows: 2,
+            cols: 2,
+            row_rules: vec![vec![1]; 2],
+            col_rules: vec![vec![1]; 2],
+            stack,
+            pending,
+        };
+        let grid = vec![vec![CellState::Empty; 2]; 2];
+        let frame = |grid: Vec<Vec<CellState>>, row| SearchFrame {
+            grid,
+            row,
+            col: 0,
+            next: 0,
+        };
+        assert!(snapshot(Vec::new(), Some(grid.clone())).check().is_ok());
+        assert!(snapshot(vec![frame(grid.clone(), 1)], None).check().is_ok());
+        assert!(
+            snapshot(Vec::new(), Some(vec![vec![CellState::Empty; 3]; 2]))
+                .check()
+                .is_err()
+        );
+        assert!(
+            snapshot(vec![frame(grid.clone(), 2)], None)
+                .check()
+                .is_err()
+        );
+        assert!(
+            snapshot(vec![frame(vec![Vec::new()], 0)], None)
+                .check()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn board_undo_and_redo_walk_the_history_in_order() {
+        let rules = vec![vec![1]; 3];
+        let mut board = Board::from_rules(3, 3, &rules, &rules, 0).unwrap();
+        let mut states = vec![board.grid.clone()];
+        for (row, col, state) in [
+            (0, 0, CellState::Filled),
+            (1, 1, CellState::Crossed),
+            (0, 0, CellState::Crossed),
+        ] {
+            assert!(board.set_cell(row, col, state).is_ok());
+            states.push(board.grid.clone());
+        }
+        assert!(!board.can_redo());
+        // アンドゥは新しい操作から順に取り消す
+        for expected in states.iter().rev().skip(1) {
+            assert!(board.undo());
+            assert_eq!(&board.grid, expected);
+        }
+        assert!(!board.undo());
+        // リドゥは取り消した順と逆に、古い操作からやり直す
+        for expected in states.iter().skip(1) {
+            assert!(board.redo());
+            assert_eq!(&board.grid, expected);
+        }
+        assert!(!board.redo());
+        // 取り消した後に新しく操作すると、やり直し用の履歴は破棄される
+        assert!(board.undo());
+        assert!(board.set_cell(2, 2, CellState::Filled).is_ok());
+        assert!(!board.can_redo());
+        assert!(board.undo());
+        assert_eq!(board.grid, states[2]);
+    }
+
+    #[test]
+    fn board_history_limit_drops_the_oldest_snapshots() {
+        let rules = vec![vec![1]; 3];
+        let mut board = Board::from_rules(3, 3, &rules, &rules, 2).unwrap();
+        for col in 0..3 {
+            assert!(board.set_cell(0, col, CellState::Filled).is_ok());
+        }
+        assert_eq!(board.undo_stack.len(), 2);
+        assert!(board.undo());
+        assert!(board.undo());
+        assert!(!board.undo());
+        // 最初の操作の前の盤面は捨てられているので、1マス目を塗った盤面までしか戻れない
+        let f = CellState::Filled;
+        let e = CellState::Empty;
+        assert_eq!(board.grid[0], vec![f, e, e]);
+    }
+
+    #[test]
+    fn grid_snapshot_round_trips_sparse_and_dense_grids() {
+        let mut sparse = vec![vec![CellState::Empty; 40]; 30];
+        sparse[3][7] = CellState::Filled;
+        sparse[29][39] = CellState::Crossed;
+        let snapshot = GridSnapshot::capture(&sparse);
+        assert!(matches!(snapshot, GridSnapshot::Sparse(_)));
+        assert_eq!(snapshot.restore(), sparse);
+
+        let dense = random_picture(6, 5, 119);
+        let snapshot = GridSnapshot::capture(&dense);
+        assert!(matches!(snapshot, GridSnapshot::Dense(_)));
+        assert_eq!(snapshot.restore(), dense);
+    }
+
+    #[test]
+    fn cancel_token_stops_a_search_from_another_thread() {
+        let rules = vec![vec![1]; 3];
+        let propagator = Propagator::new(3, 3, &rules, &rules);
+        let mut search = SolutionSearch::new(&propagator, vec![vec![CellState::Empty; 3]; 3]);
+        let token = CancelToken::new();
+        let (started_tx, started_rx) = std::sync::mpsc::channel();
+        let (cancelled_tx, cancelled_rx) = std::sync::mpsc::channel();
+        let canceller = {
+            let token = token.clone();
+            std::thread::spawn(move || {
+                started_rx.recv().unwrap();
+                token.cancel();
+                cancelled_tx.send(()).unwrap();
+            })
+        };
+        // 分岐点を1つ作った後で別スレッドに中断を頼み、中断が済むのを待ってからフラグを確認する
+        let checks = std::cell::Cell::new(0);
+        let is_cancelled = || {
+            checks.set(checks.get() + 1);
+            if checks.get() == 2 {
+                started_tx.send(()).unwrap();
+                cancelled_rx.recv().unwrap();
+            }
+            token.is_cancelled()
+        };
+        assert!(matches!(
+            search.next_solution(&is_cancelled),
+            SearchOutcome::Cancelled
+        ));
+        assert!(!search.stack.is_empty());
+        canceller.join().unwrap();
+    }
+
+    #[test]
+    fn binomial_overflows_only_when_the_result_does() {
+        // パスカルの三角形を足し算だけで作り、128ビットに収まる範囲の正確な値と比べる
+        let mut row: Vec<Option<u128>> = vec![Some(1)];
+        for n in 1..=140u128 {
+            let mut next = vec![Some(1); n as usize + 1];
+            for k in 1..n as usize {
+                next[k] = row[k - 1].zip(row[k]).and_then(|(a, b)| a.checked_add(b));
+            }
+            row = next;
+            for (k, expected) in row.iter().enumerate() {
+                assert_eq!(binomial(n, k as u128), *expected, "C({}, {})", n, k);
+            }
+        }
+        assert_eq!(
+            binomial(130, 65),
+            Some(95_067_625_827_960_698_145_584_333_020_095_113_100)
+        );
+        assert_eq!(binomial(132, 66), None);
+        assert_eq!(binomial(3, 5), Some(0));
+
+        // 65個の長さ1のブロックはC(130, 65)通りで、66個ではC(132, 66)通りになり収まらない
+        assert_eq!(
+            possibility_count(129 + 65, &[1; 65]),
+            binomial(130, 65).unwrap()
+        );
+        assert_eq!(
+            possibility_count(131 + 66, &[1; 66]),
+            POSSIBILITY_COUNT_OVERFLOW
+        );
+    }
+
+    /// 幅`n`の1行のパズル（全て塗る）を`Solver`で解き、解析結果の盤面を返す
+    fn solve_row(solver: &mut Solver, n: usize) -> Vec<Vec<CellState>> {
+        let empty = vec![vec![CellState::Empty; n]];
+        solver
+            .solve_native(1, n, &[vec![n]], &vec![vec![1]; n], empty)
+            .grid()
+            .to_vec()
+    }
+
+    /// `Solver`がキャッシュしている入力の幅を、古い順に返す
+    fn cached_widths(solver: &Solver) -> Vec<usize> {
+        let key = |n: usize| {
+            Solver::cache_key(
+                1,
+                n,
+                &[vec![n]],
+                &vec![vec![1]; n],
+                &[vec![CellState::Empty; n]],
+            )
+        };
+        solver
+            .entries
+            .iter()
+            .filter_map(|(cached, _)| (1..=4).find(|&n| key(n) == *cached))
+            .collect()
+    }
+
+    #[test]
+    fn solver_cache_evicts_the_least_recently_used_result() {
+        let mut solver = Solver::new(2);
+        let solved = solve_row(&mut solver, 1);
+        assert_eq!(solved, vec![vec![CellState::Filled]]);
+        solve_row(&mut solver, 2);
+        // ヒットした結果は末尾に移るので、次に捨てられるのは幅2の結果になる
+        assert_eq!(solve_row(&mut solver, 1), solved);
+        assert_eq!(cached_widths(&solver), vec![2, 1]);
+        solve_row(&mut solver, 3);
+        assert_eq!(cached_widths(&solver), vec![1, 3]);
+        solve_row(&mut solver, 2);
+        assert_eq!(cached_widths(&solver), vec![3, 2]);
+
+        let metrics = solver.cache_metrics();
+        assert_eq!((metrics.hits, metrics.misses), (1, 4));
+        assert_eq!(metrics.hit_rate, 0.2);
+        assert_eq!((metrics.entries, metrics.capacity), (2, 2));
+
+        solver.clear();
+        let metrics = solver.cache_metrics();
+        assert_eq!((metrics.hits, metrics.misses, metrics.entries), (0, 0, 0));
+        assert_eq!(metrics.hit_rate, 0.0);
+    }
+
+    #[test]
+    fn solver_with_zero_capacity_never_caches() {
+        let mut solver = Solver::new(0);
+        for _ in 0..3 {
+            assert_eq!(solve_row(&mut solver, 2), vec![vec![CellState::Filled; 2]]);
+        }
+        let metrics = solver.cache_metrics();
+        assert_eq!((metrics.hits, metrics.misses), (0, 3));
+        assert_eq!((metrics.entries, metrics.capacity), (0, 0));
+    }
+
+    /// 輪郭を、穴かどうかと、並びの向きを問わない頂点の集合の組にする
+    fn contour_shapes(grid: &[Vec<CellState>]) -> Vec<(bool, Vec<(usize, usize)>)> {
+        let width = grid.first().map_or(0, Vec::len);
+        let mut shapes: Vec<_> = contours_of(grid, width)
+            .into_iter()
+            .map(|contour| {
+                let mut points = contour.points;
+                points.sort_unstable();
+                (contour.hole, points)
+            })
+            .collect();
+        shapes.sort_unstable();
+        shapes
+    }
+
+    #[test]
+    fn contours_of_a_single_cell_is_one_square() {
+        let contours = contours_of(&[vec![CellState::Filled]], 1);
+        assert_eq!(contours.len(), 1);
+        assert_eq!(contours[0].points, vec![(1, 0), (1, 1), (0, 1), (0, 0)]);
+        assert!(!contours[0].hole);
+    }
+
+    #[test]
+    fn contours_of_a_ring_has_an_outer_contour_and_a_hole() {
+        let (f, x) = (CellState::Filled, CellState::Crossed);
+        let ring = vec![vec![f, f, f], vec![f, x, f], vec![f, f, f]];
+        assert_eq!(
+            contour_shapes(&ring),
+            vec![
+                (false, vec![(0, 0), (0, 3), (3, 0), (3, 3)]),
+                (true, vec![(1, 1), (1, 2), (2, 1), (2, 2)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn contours_of_diagonally_touching_cells_are_separate() {
+        let (f, x) = (CellState::Filled, CellState::Crossed);
+        for grid in [vec![vec![f, x], vec![x, f]], vec![vec![x, f], vec![f, x]]] {
+            let shapes = contour_shapes(&grid);
+            assert_eq!(shapes.len(), 2);
+            for (hole, points) in &shapes {
+                assert!(!hole);
+                assert_eq!(points.len(), 4);
+            }
+            // 2つの正方形は、接している頂点だけを共有する
+            let shared: Vec<_> = shapes[0]
+                .1
+                .iter()
+                .filter(|point| shapes[1].1.contains(point))
+                .collect();
+            assert_eq!(shared, vec![&(1, 1)]);
+        }
+    }
+
+    #[test]
+    fn step_solver_replays_solve_puzzle_native_cell_by_cell() {
+        for seed in 0..10 {
+            let (rows, cols) = (5 + seed as usize % 4, 9 - seed as usize % 3);
+            let (row_rules, col_rules, grid) = random_puzzle(rows, cols, seed, 4);
+            let expected = solve_puzzle_native(rows, cols, &row_rules, &col_rules, grid.clone());
+            assert!(!expected.is_error(), "{}", expected.message());
+
+            let mut stepper =
+                StepSolver::from_rules(rows, cols, &row_rules, &col_rules, grid.clone()).unwrap();
+            let mut replayed = grid;
+            loop {
+                let patch = match stepper.next_patch() {
+                    Ok(Some(patch)) => patch,
+                    Ok(None) => break,
+                    Err(e) => panic!("seed {}: {}", seed, e),
+                };
+                // 同じマスが2回返されることはなく、返されるのは未確定だったマスだけ
+                assert_eq!(
+                    replayed[patch.row][patch.col],
+                    CellState::Empty,
+                    "seed {}",
+                    seed
+                );
+                replayed[patch.row][patch.col] = patch.state;
+            }
+            assert_eq!(&replayed, expected.grid(), "seed {}", seed);
+            assert!(matches!(stepper.next_patch(), Ok(None)));
+        }
+    }
+
+    #[test]
+    fn step_solver_conflict_ends_the_stream() {
+        // 行は全て塗るが、列は全て塗らないという矛盾したルール
+        let mut stepper = StepSolver::from_rules(
+            1,
+            2,
+            &[vec![2]],
+            &[vec![0], vec![0]],
+            vec![vec![CellState::Empty; 2]],
+        )
+        .unwrap();
+        assert!(stepper.next_patch().is_err());
+        // 矛盾する前に行の解析で確定したマスも返さない
+        assert!(matches!(stepper.next_patch(), Ok(None)));
+        assert!(matches!(stepper.next_patch(), Ok(None)));
+    }
+
+    #[test]
+    fn shared_rules_round_trip() {
+        let mut cases = vec![
+            (Vec::new(), Vec::new()),
+            (vec![vec![]], vec![vec![0]]),
+            (vec![vec![1], vec![1, 1]], vec![vec![2], vec![]]),
+        ];
+        for seed in 0..10 {
+            cases.push(rules_from_grid(&random_picture(
+                1 + seed as usize % 6,
+                1 + seed as usize % 4,
+                seed,
+            )));
+        }
+        for (row_rules, col_rules) in cases {
+            let encoded = encode_shared_rules(&row_rules, &col_rules);
+            assert!(encoded.starts_with("v1-"), "{}", encoded);
+            let decoded = decode_shared_rules(&encoded).unwrap();
+            let normalized = |rules: &[Vec<usize>]| {
+                rules
+                    .iter()
+                    .map(|rule| normalize_rule(rule))
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(decoded.row_rules, normalized(&row_rules), "{}", encoded);
+            assert_eq!(decoded.col_rules, normalized(&col_rules), "{}", encoded);
+        }
+        assert_eq!(
+            encode_shared_rules(&[vec![1], vec![1, 1]], &[vec![2], vec![]]),
+            "v1-1_1.1~2_0"
+        );
+    }
+
+    #[test]
+    fn shared_grid_round_trip() {
+        let mut rng = SeededRng::new(178);
+        for (rows, cols) in [(0, 0), (1, 1), (3, 0), (2, 5), (7, 7), (10, 3)] {
+            let grid = seeded_grid(&mut rng, rows, cols, 1.0 / 3.0, 1.0 / 3.0);
+            let encoded = encode_shared_grid(&grid).unwrap();
+            assert!(encoded.starts_with("v1-"), "{}", encoded);
+            assert_eq!(decode_shared_grid(&encoded), Ok(grid), "{}", encoded);
+        }
+        let side = MAX_SHARED_GRID_SIDE + 1;
+        assert!(encode_shared_grid(&vec![vec![CellState::Empty; 1]; side]).is_err());
+    }
+
+    #[test]
+    fn shared_strings_reject_missing_unknown_and_malformed_versions() {
+        // バージョン番号の接頭辞がない、または数字でない
+        for s in ["1_1~1", "v-1~1", "vx-1~1", "1.1.B", ""] {
+            assert!(decode_shared_rules(s).is_err(), "{}", s);
+            assert!(decode_shared_grid(s).is_err(), "{}", s);
+        }
+        // まだ存在しないバージョン
+        let unknown = decode_shared_rules("v2-1~1").err().unwrap();
+        assert!(unknown.contains("バージョン2"), "{}", unknown);
+        let unknown = decode_shared_grid("v2-1.1.B").err().unwrap();
+        assert!(unknown.contains("バージョン2"), "{}", unknown);
+        // v1の本体が壊れている
+        for s in ["v1-1_1", "v1-1_a~1", "v1-1~1..2", "v1-~-1"] {
+            assert!(decode_shared_rules(s).is_err(), "{}", s);
+        }
+        for s in ["v1-1.1", "v1-x.1.B", "v1-2.2.A", "v1-1.1.!", "v1-1.1.D"] {
+            assert!(decode_shared_grid(s).is_err(), "{}", s);
         }
     }
 }
@@ -7,7 +7,7 @@
 
 // --- WASMモジュールの初期化 ---
 // WASMファイルからソルバー関数をインポートし、非同期で初期化
-import init, { solve_puzzle } from './pkg/picross_solver.js';
+import init, { solve_puzzle_marked } from './pkg/picross_solver.js';
 async function run() { await init(); }
 run();
 
@@ -18,7 +18,7 @@ run();
  * @readonly
  * @enum {number}
  */
-const CELL_STATE = { EMPTY: 0, FILLED: 1, CROSSED: 2 };
+const CELL_STATE = { EMPTY: 0, FILLED: 1, CROSSED: 2, AUTO_CROSSED: 3 }; // AUTO_CROSSEDは自動解析が付けた×
 
 /** @type {number} 盤面の最大サイズ */
 const MAX_SIZE = 100;
@@ -122,8 +122,10 @@ function renderGrid() {
       const cellState = state.grid[r][c];
       // セルの状態に応じて、CSSクラスや×印(SVG)を追加
       if (cellState === CELL_STATE.FILLED) { cell.classList.add('filled'); }
-      else if (cellState === CELL_STATE.CROSSED) {
+      else if (cellState === CELL_STATE.CROSSED || cellState === CELL_STATE.AUTO_CROSSED) {
         cell.classList.add('crossed');
+        // 自動解析が付けた×は、ユーザーが付けた×と色分けして表示
+        if (cellState === CELL_STATE.AUTO_CROSSED) { cell.classList.add('auto-crossed'); }
         const svgNS = "http://www.w3.org/2000/svg";
         const svg = document.createElementNS(svgNS, "svg");
         svg.setAttribute("class", "cross-icon"); svg.setAttribute("viewBox", "0 0 24 24");
@@ -219,7 +221,9 @@ function handleCellClick(event) {
   if (!cell) return;
   const r = parseInt(cell.dataset.row, 10);
   const c = parseInt(cell.dataset.col, 10);
-  state.grid[r][c] = (state.grid[r][c] + 1) % 3; // 0, 1, 2をループさせる
+  // 自動解析が付けた×はユーザーの×と同じ扱いで次の状態に進める
+  const current = state.grid[r][c] === CELL_STATE.AUTO_CROSSED ? CELL_STATE.CROSSED : state.grid[r][c];
+  state.grid[r][c] = (current + 1) % 3; // 0, 1, 2をループさせる
   hideMessage();
   renderGrid(); // グリッドのみ再描画
 }
@@ -280,7 +284,7 @@ function handleSolve() {
   setTimeout(() => {
     try {
       // WASM関数を呼び出す
-      const result = solve_puzzle(state.rows, state.cols, state.rowRules, state.colRules, state.grid);
+      const result = solve_puzzle_marked(state.rows, state.cols, state.rowRules, state.colRules, state.grid);
       // 解析結果でstateを更新
       state.grid = result.grid;
       showMessage(result.message, result.error ? 'error' : 'info');